@@ -0,0 +1,45 @@
+pub type IntCode = Vec<i32>;
+
+enum OpCode{
+    Add,
+    Mul,
+    Halt,
+    Err(i32)
+}
+
+fn get_opcode(number: i32) -> OpCode {
+    match number {
+        1   => OpCode::Add,
+        2   => OpCode::Mul,
+        99  => OpCode::Halt,
+        v   => OpCode::Err(v)
+    }
+}
+
+pub fn process(intcode: IntCode) -> IntCode {
+    let mut index = 0;
+    let mut result = intcode.clone();
+    loop {
+        match get_opcode(result[index]) {
+            OpCode::Add => {
+                let ix = result[index + 1] as usize;
+                let iy = result[index + 2] as usize;
+                let iz = result[index + 3] as usize;
+                result[iz] = result[ix] + result[iy];
+            },
+            OpCode::Mul => {
+                let ix = result[index + 1] as usize;
+                let iy = result[index + 2] as usize;
+                let iz = result[index + 3] as usize;
+                result[iz] = result[ix] * result[iy];
+            },
+            OpCode::Halt => break,
+            OpCode::Err(v) => {
+                println!("Program Error: {}", v);
+                break;
+            }
+        };
+        index += 4;
+    }
+    result
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::program::{Program, State};
+use crate::protocol::Decoder;
+
+/// World a program acts in. Every step the program is given the observed inputs and runs
+/// until it waits for more input or halts, the outputs it produced are then applied.
+pub trait Environment {
+    fn observe(&mut self) -> Vec<i64>;
+    fn apply(&mut self, outputs: &[i64]);
+    // Lets the environment end the run before the program halts.
+    fn is_done(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub inputs: Vec<i64>,
+    pub outputs: Vec<i64>
+}
+
+#[derive(Clone)]
+pub struct Agent<E: Environment> {
+    pub program: Program,
+    pub environment: E,
+    pub history: Vec<Turn>
+}
+
+impl<E: Environment> Agent<E> {
+    /// Runs the program up to its first input request, applying anything it outputs before.
+    pub fn new(program: Program, environment: E) -> Agent<E> {
+        let mut agent = Agent { program, environment, history: Vec::new() };
+        agent.advance(Vec::new());
+        agent
+    }
+
+    fn advance(&mut self, inputs: Vec<i64>) {
+        for value in &inputs {
+            self.program.push_input(*value);
+        }
+        self.program.process();
+        let mut outputs = Vec::new();
+        while let Some(value) = self.program.pop_output() {
+            outputs.push(value);
+        }
+        if inputs.is_empty() && outputs.is_empty() {
+            return;
+        }
+        self.environment.apply(&outputs);
+        self.history.push(Turn { inputs, outputs });
+    }
+
+    /// Feeds the observed inputs and runs the program until it waits again. Returns false
+    /// once the program halted or the environment is done.
+    pub fn step(&mut self) -> Result<bool, String> {
+        if let State::Error(ref e) = self.program.state {
+            return Err(e.clone());
+        }
+        if self.program.state == State::Halt || self.environment.is_done() {
+            return Ok(false);
+        }
+        let inputs = self.environment.observe();
+        self.advance(inputs);
+        match self.program.state {
+            State::Error(ref e) => Err(e.clone()),
+            _                   => Ok(true)
+        }
+    }
+
+    /// Steps until the program halts or the environment is done, giving up after `max_steps`.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize, String> {
+        for n in 0..max_steps {
+            if !self.step()? {
+                return Ok(n);
+            }
+        }
+        Err(format!("still running after {} steps", max_steps))
+    }
+}
+
+/// Breadth first search over program states. From every discovered state a snapshot of the
+/// program is given each of `commands`, `next` maps the state, the command and the produced
+/// outputs to the new state or `None` if the move was refused. Returns the shortest command
+/// sequence reaching every state.
+pub fn explore<K, F>(program: &Program, start: K, commands: &[i64], mut next: F) -> HashMap<K, Vec<i64>>
+    where K: Hash + Eq + Clone, F: FnMut(&K, i64, &[i64]) -> Option<K> {
+    let mut paths = HashMap::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start.clone());
+    paths.insert(start.clone(), Vec::new());
+    queue.push_back((start, program.clone(), Vec::new()));
+    while let Some((key, snapshot, path)) = queue.pop_front() {
+        for command in commands {
+            let mut branch = snapshot.clone();
+            branch.push_input(*command);
+            branch.process();
+            if let State::Error(_) = branch.state {
+                continue;
+            }
+            let mut outputs = Vec::new();
+            while let Some(value) = branch.pop_output() {
+                outputs.push(value);
+            }
+            if let Some(reached) = next(&key, *command, &outputs) {
+                if seen.insert(reached.clone()) {
+                    let mut path = path.clone();
+                    path.push(*command);
+                    paths.insert(reached.clone(), path.clone());
+                    if branch.state == State::WaitForInput {
+                        queue.push_back((reached, branch, path));
+                    }
+                }
+            }
+        }
+    }
+    paths
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl Direction {
+    fn turn(self, right: bool) -> Direction {
+        match (self, right) {
+            (Direction::Up, false) | (Direction::Down, true)    => Direction::Left,
+            (Direction::Up, true) | (Direction::Down, false)    => Direction::Right,
+            (Direction::Left, false) | (Direction::Right, true) => Direction::Down,
+            (Direction::Left, true) | (Direction::Right, false) => Direction::Up
+        }
+    }
+}
+
+/// The day 11 hull painting robot: observes the color below it, then paints and turns
+/// for every (color, turn) pair of outputs and moves one panel forward. A pair split across
+/// two steps is completed by the next one.
+#[derive(Debug, Clone)]
+pub struct Robot {
+    pub position: (i64, i64),
+    pub direction: Direction,
+    pub panels: HashMap<(i64, i64), i64>,
+    decoder: Decoder<(i64, i64)>
+}
+
+impl Robot {
+    pub fn new() -> Robot {
+        Robot {
+            position: (0, 0),
+            direction: Direction::Up,
+            panels: HashMap::new(),
+            decoder: Decoder::new(2, |t| Ok((t[0], t[1])))
+        }
+    }
+}
+
+impl Default for Robot {
+    fn default() -> Robot {
+        Robot::new()
+    }
+}
+
+impl Environment for Robot {
+    fn observe(&mut self) -> Vec<i64> {
+        vec![*self.panels.get(&self.position).unwrap_or(&0)]
+    }
+
+    fn apply(&mut self, outputs: &[i64]) {
+        for value in outputs {
+            let (color, turn) = match self.decoder.push(*value) {
+                Ok(Some(pair)) => pair,
+                _ => continue
+            };
+            self.panels.insert(self.position, color);
+            self.direction = self.direction.turn(turn == 1);
+            let (x, y) = self.position;
+            self.position = match self.direction {
+                Direction::Up       => (x, y - 1),
+                Direction::Down     => (x, y + 1),
+                Direction::Left     => (x - 1, y),
+                Direction::Right    => (x + 1, y)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{explore, Agent, Direction, Robot};
+    use crate::program::Program;
+
+    // Paints white and turns left four times.
+    fn square() -> Vec<i64> {
+        vec![3,100,104,1,104,0,1001,101,1,101,1007,101,4,102,1005,102,0,99]
+    }
+
+    // Droid in a corridor of positions 0 to 3: command 4 moves east, 3 west and 1, 2 hit
+    // walls. Replies 0 for a wall, 1 for a move and 2 on reaching position 3.
+    fn corridor() -> Vec<i64> {
+        vec![3,101,1008,101,4,102,1005,102,21,1008,101,3,102,1005,102,35,104,0,1105,1,0,
+             1008,100,3,102,1005,102,16,1001,100,1,100,1105,1,46,1008,100,0,102,1005,102,16,
+             1001,100,-1,100,1008,100,3,102,1001,102,1,102,4,102,1105,1,0]
+    }
+
+    #[test]
+    fn test_robot() {
+        let mut agent = Agent::new(Program::new(square(), false), Robot::new());
+        assert_eq!(Ok(4), agent.run(10));
+        assert_eq!(4, agent.environment.panels.len());
+        assert!(agent.environment.panels.values().all(|c| *c == 1));
+        assert_eq!((0, 0), agent.environment.position);
+        assert_eq!(Direction::Up, agent.environment.direction);
+        assert_eq!(vec![0], agent.history[0].inputs);
+        assert_eq!(vec![1, 0], agent.history[3].outputs);
+
+        // Outputs the color, waits for input and only then the turn.
+        let mut agent = Agent::new(Program::new(vec![3,100,104,1,3,100,104,1,99], false), Robot::new());
+        assert_eq!(Ok(2), agent.run(10));
+        assert_eq!(Some(&1), agent.environment.panels.get(&(0, 0)));
+        assert_eq!(Direction::Right, agent.environment.direction);
+        assert_eq!((1, 0), agent.environment.position);
+    }
+
+    #[test]
+    fn test_explore() {
+        let mut goal = None;
+        let paths = explore(&Program::new(corridor(), false), 0, &[1, 2, 3, 4], |position, command, outputs| {
+            let reached = match outputs {
+                [0]     => return None,
+                _       => if command == 4 { position + 1 } else { position - 1 }
+            };
+            if outputs == [2] {
+                goal = Some(reached);
+            }
+            Some(reached)
+        });
+        assert_eq!(4, paths.len());
+        assert_eq!(Some(3), goal);
+        assert_eq!(vec![4, 4, 4], paths[&3]);
+    }
+}
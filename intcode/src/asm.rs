@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::program::{Instruction, IntCode, OpCode, ParameterMode};
+
+/// Characters `start..end` of a zero based `line`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(i64),
+    Label(String)
+}
+
+/// `[n]` is position mode, `n` immediate and `[rb+n]` relative, as in the disassembler.
+/// Labels can stand in for numbers in position and immediate mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand {
+    pub span: Span,
+    pub mode: ParameterMode,
+    pub value: Value
+}
+
+/// One instruction or `data` line. `op_code` is `None` for data and unknown mnemonics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub address: usize,
+    pub len: usize,
+    pub mnemonic: String,
+    pub span: Span,
+    pub op_code: Option<OpCode>,
+    pub operands: Vec<Operand>
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Label {
+    pub address: usize,
+    pub span: Span
+}
+
+/// A word referring to a label the source does not define.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub address: usize,
+    pub label: String,
+    pub span: Span
+}
+
+/// `relocations` are the words holding the address of a label, they change if the code
+/// is moved. `imports` are also reported as undefined labels in `diagnostics`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Assembly {
+    pub code: IntCode,
+    pub statements: Vec<Statement>,
+    pub labels: HashMap<String, Label>,
+    pub relocations: Vec<usize>,
+    pub imports: Vec<Import>,
+    pub diagnostics: Vec<Diagnostic>
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line + 1, self.span.start + 1, self.message)
+    }
+}
+
+/// Mnemonic of `op_code`, its Debug name.
+pub fn mnemonic(op_code: &OpCode) -> String {
+    format!("{:?}", op_code)
+}
+
+fn lookup(mnemonic: &str) -> Option<OpCode> {
+    OpCode::all().into_iter().find(|op| format!("{:?}", op).eq_ignore_ascii_case(mnemonic))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn ordinal(n: usize) -> &'static str {
+    ["1st", "2nd", "3rd"].get(n).copied().unwrap_or("last")
+}
+
+fn value(text: &str) -> Option<Value> {
+    match text.parse::<i64>() {
+        Ok(n) => Some(Value::Number(n)),
+        Err(_) if is_identifier(text) => Some(Value::Label(String::from(text))),
+        Err(_) => None
+    }
+}
+
+fn operand(text: &str, span: Span) -> Result<Operand, Diagnostic> {
+    let invalid = || Diagnostic { span, message: format!("invalid operand `{}`", text) };
+    let (mode, value) = match text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        Some(inner) => {
+            let inner = inner.replace(' ', "");
+            match inner.strip_prefix("rb") {
+                Some("") => (ParameterMode::Relative, Value::Number(0)),
+                Some(offset) if offset.starts_with('+') || offset.starts_with('-') => {
+                    let offset = offset.trim_start_matches('+').parse::<i64>().map_err(|_| invalid())?;
+                    (ParameterMode::Relative, Value::Number(offset))
+                },
+                _ => (ParameterMode::Position, value(&inner).ok_or_else(invalid)?)
+            }
+        },
+        None => (ParameterMode::Immediate, value(text).ok_or_else(invalid)?)
+    };
+    Ok(Operand { span, mode, value })
+}
+
+// Splits `text` at top level commas, keeping the column of every piece.
+fn split_operands(text: &str, column: usize) -> Vec<(String, usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let chars = text.chars().collect::<Vec<char>>();
+    for i in 0..=chars.len() {
+        if i == chars.len() || chars[i] == ',' {
+            let piece = chars[start..i].iter().collect::<String>();
+            let leading = piece.len() - piece.trim_start().len();
+            let trimmed = piece.trim();
+            pieces.push((String::from(trimmed), column + start + leading, column + start + leading + trimmed.chars().count()));
+            start = i + 1;
+        }
+    }
+    pieces
+}
+
+/// Assembles `source`. Problems are collected as diagnostics; `code` is only meaningful
+/// if there are none.
+///
+/// ```text
+/// loop:   In [value]          ; comments start with ; or #
+///         JumpIfFalse [value], end
+///         Mul [value], 2, [rb+20]
+///         Out [rb+20]
+///         JumpIfTrue 1, loop
+/// end:    Halt
+/// value:  data 0
+/// ```
+pub fn assemble(source: &str) -> Assembly {
+    let mut assembly = Assembly::default();
+    let mut address = 0;
+    for (line, text) in source.lines().enumerate() {
+        let text = text.split([';', '#']).next().unwrap_or("");
+        let mut rest = text;
+        let mut column = 0;
+        if let Some(colon) = rest.find(':') {
+            let name = rest[..colon].trim();
+            let start = rest.find(name).unwrap_or(0);
+            let span = Span { line, start, end: start + name.len() };
+            if !is_identifier(name) {
+                assembly.diagnostics.push(Diagnostic { span, message: format!("invalid label `{}`", name) });
+            } else if let Some(previous) = assembly.labels.get(name) {
+                let message = format!("label `{}` is already defined on line {}", name, previous.span.line + 1);
+                assembly.diagnostics.push(Diagnostic { span, message });
+            } else {
+                assembly.labels.insert(String::from(name), Label { address, span });
+            }
+            rest = &rest[colon + 1..];
+            column = colon + 1;
+        }
+        let leading = rest.len() - rest.trim_start().len();
+        let rest = rest.trim_start();
+        if rest.trim().is_empty() {
+            continue;
+        }
+        column += leading;
+        let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let name = &rest[..word_end];
+        let span = Span { line, start: column, end: column + name.len() };
+        let operand_text = &rest[word_end..];
+        let mut operands = Vec::new();
+        let mut found = 0;
+        if !operand_text.trim().is_empty() {
+            for (piece, start, end) in split_operands(operand_text, column + word_end) {
+                found += 1;
+                let span = Span { line, start, end };
+                match operand(&piece, span) {
+                    Ok(o) => operands.push(o),
+                    Err(d) => assembly.diagnostics.push(d)
+                }
+            }
+        }
+        let op_code = lookup(name);
+        let len = match &op_code {
+            Some(op) => {
+                let count = op.parameter_count();
+                if found != count {
+                    let message = format!("{} takes {} operand{}, found {}", mnemonic(op), count, if count == 1 { "" } else { "s" }, found);
+                    assembly.diagnostics.push(Diagnostic { span, message });
+                }
+                if let Some(written) = op.written_parameter().and_then(|n| operands.get(n).map(|o| (n, o))) {
+                    if written.1.mode == ParameterMode::Immediate {
+                        let message = format!("{} writes its {} operand, it can not be immediate", mnemonic(op), ordinal(written.0));
+                        assembly.diagnostics.push(Diagnostic { span: written.1.span, message });
+                    }
+                }
+                count + 1
+            },
+            None if name.eq_ignore_ascii_case("data") => {
+                for o in operands.iter().filter(|o| o.mode != ParameterMode::Immediate) {
+                    assembly.diagnostics.push(Diagnostic { span: o.span, message: String::from("data takes plain numbers or labels") });
+                }
+                found
+            },
+            None => {
+                assembly.diagnostics.push(Diagnostic { span, message: format!("unknown mnemonic `{}`", name) });
+                found + 1
+            }
+        };
+        assembly.statements.push(Statement { address, len, mnemonic: String::from(name), span, op_code, operands });
+        address += len;
+    }
+    for statement in &assembly.statements {
+        let mut words = Vec::new();
+        if let Some(op) = &statement.op_code {
+            let mut modes = [ParameterMode::Position; 3];
+            for (n, o) in statement.operands.iter().take(3).enumerate() {
+                modes[n] = o.mode;
+            }
+            let instruction = Instruction { op_code: op.clone(), parameter_modes: (modes[0], modes[1], modes[2]) };
+            words.push(instruction.encode().unwrap());
+        } else if !statement.mnemonic.eq_ignore_ascii_case("data") {
+            words.push(0);
+        }
+        for o in &statement.operands {
+            let address = assembly.code.len() + words.len();
+            let word = match &o.value {
+                Value::Number(n) => *n,
+                Value::Label(name) => match assembly.labels.get(name) {
+                    Some(label) => {
+                        assembly.relocations.push(address);
+                        label.address as i64
+                    },
+                    None => {
+                        assembly.imports.push(Import { address, label: name.clone(), span: o.span });
+                        assembly.diagnostics.push(Diagnostic { span: o.span, message: format!("undefined label `{}`", name) });
+                        0
+                    }
+                }
+            };
+            words.push(word);
+        }
+        words.resize(statement.len, 0);
+        assembly.code.extend(words);
+    }
+    assembly.diagnostics.sort_by_key(|d| (d.span.line, d.span.start));
+    assembly
+}
+
+impl Assembly {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// The statement on `line`, if there is one.
+    pub fn statement(&self, line: usize) -> Option<&Statement> {
+        self.statements.iter().find(|s| s.span.line == line)
+    }
+
+    /// The label used or defined at `line`, `column`.
+    pub fn label_at(&self, line: usize, column: usize) -> Option<&str> {
+        let inside = |span: &Span| span.line == line && span.start <= column && column <= span.end;
+        if let Some((name, _)) = self.labels.iter().find(|(_, l)| inside(&l.span)) {
+            return Some(name);
+        }
+        self.statements.iter()
+                       .flat_map(|s| s.operands.iter())
+                       .find(|o| inside(&o.span))
+                       .and_then(|o| match &o.value {
+                           Value::Label(name) => Some(name.as_str()),
+                           _ => None
+                       })
+    }
+}
+
+/// Assembles `source` or reports every diagnostic, one per line.
+pub fn assemble_code(source: &str) -> Result<IntCode, String> {
+    let assembly = assemble(source);
+    if assembly.is_ok() {
+        Ok(assembly.code)
+    } else {
+        Err(assembly.diagnostics.iter().map(|d| d.to_string()).collect::<Vec<String>>().join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assemble, assemble_code};
+    use crate::disassemble::listing;
+    use crate::program::{Program, State};
+
+    const DOUBLER: &str = "\
+loop:   In [value]          ; read
+        JumpIfFalse [value], end
+        Mul [value], 2, [rb+20]
+        Out [rb+20]
+        JumpIfTrue 1, loop
+end:    Halt
+value:  data 0
+";
+
+    #[test]
+    fn test_assemble() {
+        let code = assemble_code(DOUBLER).unwrap();
+        assert_eq!(vec![3,15,1006,15,14,21002,15,2,20,204,20,1105,1,0,99,0], code);
+        let mut program = Program::new(code, false);
+        program.push_input(21);
+        program.push_input(0);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(Some(42), program.pop_output());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let assembly = assemble("start: Add 1, 2\n  Mul 1, 2, 3\n  Jump start\n  In [missing]\nstart: Halt\n  Out [rb*2]\n");
+        let messages = assembly.diagnostics.iter().map(|d| d.to_string()).collect::<Vec<String>>();
+        assert_eq!(vec![
+            "1:8: Add takes 3 operands, found 2",
+            "2:13: Mul writes its 3rd operand, it can not be immediate",
+            "3:3: unknown mnemonic `Jump`",
+            "4:6: undefined label `missing`",
+            "5:1: label `start` is already defined on line 1",
+            "6:7: invalid operand `[rb*2]`"], messages);
+        assert_eq!(Some("start"), assembly.label_at(2, 9));
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let code = vec![1002,4,3,4,33,109,-1,21101,1,2,3,99];
+        let source = listing(&code).lines().map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            match fields[2..].join(" ").as_str() {
+                "data" => format!("data {}", fields[1]),
+                text => String::from(text)
+            }
+        }).collect::<Vec<String>>().join("\n");
+        assert_eq!(Ok(code), assemble_code(&source));
+    }
+}
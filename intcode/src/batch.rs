@@ -0,0 +1,208 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::fuzz::panic_message;
+use crate::program::{IntCode, Program, State};
+
+/// One run of the batch: memory words to overwrite before starting and the input queue.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Job {
+    pub patches: Vec<(usize, i64)>,
+    pub input: Vec<i64>
+}
+
+/// The finished program of a job together with everything it output.
+pub struct Outcome {
+    pub output: Vec<i64>,
+    pub program: Program
+}
+
+/// Runs one program for many jobs on a pool of threads. Every job starts from a clone of
+/// the template program, which shares the memory image until the job writes to it.
+pub struct Batch {
+    template: Program,
+    threads: usize,
+    max_steps: Option<usize>
+}
+
+/// Results in the order the jobs finish. Dropping it stops the workers after their
+/// current job.
+pub struct Results {
+    received: Receiver<(Job, Outcome)>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>
+}
+
+impl Job {
+    pub fn new(patches: Vec<(usize, i64)>, input: Vec<i64>) -> Job {
+        Job { patches, input }
+    }
+}
+
+impl Outcome {
+    pub fn state(&self) -> &State {
+        &self.program.state
+    }
+}
+
+impl Batch {
+    pub fn new(code: IntCode) -> Batch {
+        Batch::from_program(Program::new(code, false))
+    }
+
+    /// Uses `template` with its extensions and devices as the starting point of every job.
+    pub fn from_program(template: Program) -> Batch {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Batch { template, threads, max_steps: None }
+    }
+
+    pub fn threads(mut self, threads: usize) -> Batch {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stops jobs still running after `max_steps` instructions, leaving them `Idle`.
+    pub fn max_steps(mut self, max_steps: usize) -> Batch {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    fn execute(template: &Program, job: &Job, max_steps: Option<usize>) -> Outcome {
+        let mut program = template.clone();
+        for (address, value) in &job.patches {
+            program.set_memory(*address, *value);
+        }
+        for value in &job.input {
+            program.push_input(*value);
+        }
+        let run = panic::catch_unwind(AssertUnwindSafe(|| match max_steps {
+            Some(max) => {
+                while program.state == State::Idle && program.instruction_count() < max {
+                    program.step();
+                }
+            },
+            None => program.process()
+        }));
+        // A panicking interpreter fails the job instead of the worker.
+        if let Err(e) = run {
+            program.state = State::Error(format!("panic: {}", panic_message(e)));
+        }
+        let mut output = Vec::new();
+        while let Some(value) = program.pop_output() {
+            output.push(value);
+        }
+        Outcome { output, program }
+    }
+
+    pub fn run<I>(&self, jobs: I) -> Results
+        where I: IntoIterator<Item = Job>, I::IntoIter: Send + 'static {
+        let jobs = Arc::new(Mutex::new(jobs.into_iter()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, received) = mpsc::channel();
+        let workers = (0..self.threads).map(|_| {
+            let jobs = jobs.clone();
+            let stop = stop.clone();
+            let sender = sender.clone();
+            let template = self.template.clone();
+            let max_steps = self.max_steps;
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let job = match jobs.lock().unwrap().next() {
+                        Some(job) => job,
+                        None => break
+                    };
+                    let outcome = Batch::execute(&template, &job, max_steps);
+                    if sender.send((job, outcome)).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect();
+        Results { received, stop, workers }
+    }
+
+    /// First result matching `predicate`, remaining jobs are abandoned.
+    pub fn find<I, P>(&self, jobs: I, mut predicate: P) -> Option<(Job, Outcome)>
+        where I: IntoIterator<Item = Job>, I::IntoIter: Send + 'static, P: FnMut(&Job, &Outcome) -> bool {
+        self.run(jobs).find(|(job, outcome)| predicate(job, outcome))
+    }
+}
+
+impl Iterator for Results {
+    type Item = (Job, Outcome);
+
+    fn next(&mut self) -> Option<(Job, Outcome)> {
+        self.received.recv().ok()
+    }
+}
+
+impl Results {
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Results {
+    fn drop(&mut self) {
+        self.stop();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Batch, Job};
+    use crate::program::{Program, State};
+
+    // memory[0] = noun * 100 + verb, with noun at 1 and verb at 6.
+    fn noun_verb() -> Vec<i64> {
+        vec![1102,0,100,0,1001,0,0,0,99]
+    }
+
+    fn jobs() -> impl Iterator<Item = Job> {
+        (0..100).flat_map(|noun| (0..100).map(move |verb| Job::new(vec![(1, noun), (6, verb)], Vec::new())))
+    }
+
+    #[test]
+    fn test_find() {
+        let batch = Batch::new(noun_verb()).threads(4);
+        let (job, outcome) = batch.find(jobs(), |_, o| o.program.memory()[0] == 1234).unwrap();
+        assert_eq!(vec![(1, 12), (6, 34)], job.patches);
+        assert_eq!(&State::Halt, outcome.state());
+        assert!(batch.find(jobs(), |_, o| o.program.memory()[0] < 0).is_none());
+    }
+
+    #[test]
+    fn test_stream() {
+        // Doubles one input, or loops forever on 0.
+        let code = vec![3,20,1006,20,2,1002,20,2,20,4,20,99];
+        let batch = Batch::from_program(Program::new(code, false)).threads(3).max_steps(1000);
+        let mut results = batch.run((0..20).map(|v| Job::new(Vec::new(), vec![v])))
+                               .map(|(job, outcome)| (job.input[0], outcome.output, outcome.program.state))
+                               .collect::<Vec<_>>();
+        results.sort_by_key(|r| r.0);
+        assert_eq!(20, results.len());
+        assert_eq!((0, Vec::new(), State::Idle), results[0]);
+        assert_eq!((7, vec![14], State::Halt), results[7]);
+    }
+
+    #[test]
+    fn test_panic() {
+        // Reads an address and outputs the word there, a negative one panics the interpreter.
+        let code = vec![3,5,4,0,99];
+        let batch = Batch::new(code).threads(1);
+        let mut results = batch.run([-3, 0, 4].iter().map(|v| Job::new(vec![(3, *v)], vec![1])))
+                               .map(|(job, outcome)| (job.patches[0].1, outcome.output, outcome.program.state))
+                               .collect::<Vec<_>>();
+        results.sort_by_key(|r| r.0);
+        assert_eq!(3, results.len());
+        assert!(matches!(&results[0].2, State::Error(e) if e.starts_with("panic: ")), "{:?}", results[0]);
+        assert_eq!((0, vec![3], State::Halt), results[1]);
+        assert_eq!((4, vec![99], State::Halt), results[2]);
+    }
+}
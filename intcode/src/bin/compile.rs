@@ -0,0 +1,27 @@
+use intcode::{compile, image};
+
+// Usage: compile <source> [output] [--asm]
+// Writes the program as text, or as a binary image if the output name ends in `.icim`.
+// With --asm the generated assembly is written instead.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().filter(|a| a != "--asm").collect();
+    let assembly = std::env::args().any(|a| a == "--asm");
+    let source = std::fs::read_to_string(&args[1])?;
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let output = if assembly {
+        compile::to_assembly(&source).map_err(invalid)?.into_bytes()
+    } else {
+        let code = compile::compile(&source).map_err(invalid)?;
+        match args.get(2) {
+            Some(path) if path.ends_with(".icim") => image::encode(&code, true),
+            _ => format!("{}\n", image::to_text(&code)).into_bytes()
+        }
+    };
+    match args.get(2) {
+        Some(path) => std::fs::write(path, output),
+        None => {
+            print!("{}", String::from_utf8_lossy(&output));
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use intcode::coverage::Coverage;
+use intcode::{image, IntCode, Program};
+
+fn read_values(path: &str) -> std::io::Result<IntCode> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content.trim().split(',').filter(|s| !s.trim().is_empty()).map(|s| s.trim().parse::<i64>().unwrap()).collect())
+}
+
+// Usage: coverage <program> [input file ...], one run per input file.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let code = image::read(&args[1])?;
+    let mut inputs = Vec::new();
+    for path in &args[2..] {
+        inputs.push(read_values(path)?);
+    }
+    if inputs.is_empty() {
+        inputs.push(Vec::new());
+    }
+    let mut coverage = Coverage::new();
+    for input in inputs {
+        let mut program = Program::new(code.clone(), false);
+        program.enable_coverage();
+        for value in input {
+            program.push_input(value);
+        }
+        program.process();
+        coverage.merge(program.coverage().unwrap());
+    }
+    println!("{}", coverage.listing(&code));
+    let (executed, total) = coverage.summary(&code);
+    println!("{} of {} instructions executed", executed, total);
+    std::fs::write("coverage.html", coverage.html(&code))
+}
@@ -0,0 +1,53 @@
+use std::io::{BufReader, ErrorKind};
+use std::sync::mpsc;
+use std::thread;
+
+use intcode::dap::Adapter;
+use intcode::json::{read_message, write_message, Json};
+
+fn handle(adapter: &mut Adapter, request: Result<Json, String>) -> Vec<Json> {
+    match request {
+        Ok(request) => adapter.handle(&request),
+        Err(e) => adapter.malformed(&e)
+    }
+}
+
+// Debug adapter speaking DAP over stdin/stdout. Launch arguments: `program` (path to the
+// comma separated code), optional `inputs` and `stopOnEntry`.
+fn main() -> std::io::Result<()> {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(std::io::stdin());
+        loop {
+            // A malformed message is answered and skipped, only a broken stream ends the session.
+            let message = match read_message(&mut reader) {
+                Ok(Some(m)) => Ok(m),
+                Err(e) if e.kind() == ErrorKind::InvalidData => Err(e.to_string()),
+                _ => break
+            };
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    let mut stdout = std::io::stdout();
+    let mut adapter = Adapter::new();
+    while !adapter.finished {
+        let mut messages = Vec::new();
+        if adapter.is_running() {
+            while let Ok(request) = requests.try_recv() {
+                messages.extend(handle(&mut adapter, request));
+            }
+            messages.extend(adapter.run_slice());
+        } else {
+            match requests.recv() {
+                Ok(request) => messages.extend(handle(&mut adapter, request)),
+                Err(_) => break
+            }
+        }
+        for message in &messages {
+            write_message(&mut stdout, message)?;
+        }
+    }
+    Ok(())
+}
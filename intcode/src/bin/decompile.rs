@@ -0,0 +1,14 @@
+use intcode::decompile::decompile;
+
+// Usage: decompile <program> [output]
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let source = decompile(&intcode::image::read(&args[1])?);
+    match args.get(2) {
+        Some(path) => std::fs::write(path, source),
+        None => {
+            print!("{}", source);
+            Ok(())
+        }
+    }
+}
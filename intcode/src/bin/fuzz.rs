@@ -0,0 +1,22 @@
+use intcode::fuzz;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let iterations = args.get(1).map(|s| s.parse::<usize>().unwrap()).unwrap_or(1000);
+    let seed = match args.get(2) {
+        Some(s) => s.parse::<u64>().unwrap(),
+        None    => std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs()
+    };
+    // Panics are reported as divergences, keep the default hook from printing them.
+    std::panic::set_hook(Box::new(|_| {}));
+    let report = fuzz::fuzz(seed, iterations);
+    // day5 echoes every opcode on stdout, so the report goes to stderr.
+    eprintln!("Seed: {}", seed);
+    eprintln!("Cases: {} ({} skipped), failures: {}", report.cases, report.skipped, report.failures);
+    for divergence in &report.divergences {
+        eprintln!("{}", divergence);
+    }
+}
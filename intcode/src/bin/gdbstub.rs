@@ -0,0 +1,20 @@
+use std::net::TcpListener;
+
+use intcode::gdb::Stub;
+use intcode::{image, Program};
+
+// Usage: gdbstub <program> [port | -]
+// With `-` the protocol runs over stdin/stdout: `target remote | gdbstub program.txt -`.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut stub = Stub::new(Program::new(image::read(&args[1])?, false));
+    match args.get(2).map(|s| s.as_str()) {
+        Some("-") => stub.serve(std::io::stdin(), std::io::stdout()),
+        port => {
+            let listener = TcpListener::bind(("127.0.0.1", port.unwrap_or("1234").parse::<u16>().unwrap()))?;
+            eprintln!("waiting for gdb on {}", listener.local_addr()?);
+            let (stream, _) = listener.accept()?;
+            stub.serve(stream.try_clone()?, stream)
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use intcode::image;
+
+// Usage: image <input> <output> [--no-compress]
+// Converts between the text form and binary images; the output gets the other format.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let bytes = std::fs::read(&args[1])?;
+    let code = image::load(&bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let output = if image::is_binary(&bytes) {
+        let mut text = image::to_text(&code);
+        text.push('\n');
+        text.into_bytes()
+    } else {
+        image::encode(&code, args.get(3).map(|a| a.as_str()) != Some("--no-compress"))
+    };
+    std::fs::write(&args[2], &output)?;
+    eprintln!("{} words, {} -> {} bytes", code.len(), bytes.len(), output.len());
+    Ok(())
+}
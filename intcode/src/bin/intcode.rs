@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use intcode::spec;
+
+// Collects the `.toml` files below `path`, or `path` itself if it is a file.
+fn spec_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = std::fs::read_dir(path)?.map(|e| e.map(|e| e.path())).collect::<std::io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().map(|e| e == "toml").unwrap_or(false) {
+            spec_files(&entry, files)?;
+        }
+    }
+    Ok(())
+}
+
+// Usage: intcode test [spec files or directories]
+// Runs the test specs, see `intcode::spec`, and exits with 1 if any of them fails.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|a| a.as_str()) != Some("test") {
+        eprintln!("usage: intcode test [spec files or directories]");
+        std::process::exit(2);
+    }
+    let mut files = Vec::new();
+    for path in &args[2..] {
+        spec_files(Path::new(path), &mut files)?;
+    }
+    // Panics of the interpreter are reported as failures, keep the default hook from printing them.
+    std::panic::set_hook(Box::new(|_| {}));
+    let (mut passed, mut failed) = (0, 0);
+    for file in &files {
+        let specs = match spec::load(file) {
+            Ok(specs) => specs,
+            Err(e) => {
+                println!("ERROR {}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        for outcome in specs.iter().map(|s| s.run()) {
+            if outcome.passed() {
+                println!("PASS  {}: {}", file.display(), outcome.name);
+                passed += 1;
+            } else {
+                println!("FAIL  {}: {}", file.display(), outcome.name);
+                for failure in &outcome.failures {
+                    for line in failure.lines() {
+                        println!("      {}", line);
+                    }
+                }
+                failed += 1;
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::io::{BufReader, ErrorKind};
+
+use intcode::json::{read_message, write_message};
+use intcode::lsp::Server;
+
+// Language server for intcode assembly speaking LSP over stdin/stdout.
+fn main() -> std::io::Result<()> {
+    let mut reader = BufReader::new(std::io::stdin());
+    let mut stdout = std::io::stdout();
+    let mut server = Server::new();
+    while !server.finished {
+        let message = match read_message(&mut reader) {
+            Ok(Some(m)) => m,
+            Ok(None) => break,
+            // A malformed message is answered and skipped, only a broken stream ends the server.
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                write_message(&mut stdout, &server.parse_error(&e.to_string()))?;
+                continue;
+            },
+            Err(e) => return Err(e)
+        };
+        for reply in server.handle(&message) {
+            write_message(&mut stdout, &reply)?;
+        }
+    }
+    std::process::exit(if server.clean_exit() { 0 } else { 1 });
+}
@@ -0,0 +1,27 @@
+use intcode::{image, minimise};
+
+// Usage: minimise <program> [input] [max steps]
+// Shrinks a program that stops with an error or panics into a reproducer that fails the same
+// way. The input is a comma separated list of values.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let code = image::read(&args[1])?;
+    let input = match args.get(2) {
+        Some(list) => image::parse(list).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        None       => Vec::new()
+    };
+    let max_steps = args.get(3).map(|s| s.parse::<usize>().unwrap()).unwrap_or(minimise::DEFAULT_MAX_STEPS);
+    // Candidates panic all the time, keep the default hook from printing them.
+    std::panic::set_hook(Box::new(|_| {}));
+    let original = minimise::execute(&code, &input, max_steps);
+    eprintln!("Original: {} words, {} inputs, {:?}", code.len(), input.len(), original.status);
+    match minimise::minimise(&code, &input, max_steps, minimise::same_failure(&original)) {
+        Ok(reproducer) => {
+            eprintln!("Reproducer after {} runs: {} words, {} inputs", reproducer.tests, reproducer.code.len(), reproducer.input.len());
+            println!("code:\t{}", image::to_text(&reproducer.code));
+            println!("input:\t{}", image::to_text(&reproducer.input));
+        },
+        Err(e) => eprintln!("{}", e)
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use intcode::session::{replay, Recorder, Session};
+use intcode::{image, IntCode, Program, State};
+
+// Interactive loop like day9's, saving every input and output to `session` on exit.
+fn record(code: IntCode, session: &str) -> std::io::Result<()> {
+    let mut recorder = Recorder::new(Program::new(code, false));
+    loop {
+        for v in recorder.run() {
+            println!("Out: {:?}", v);
+        }
+        match recorder.program.state {
+            State::WaitForInput => {
+                print!("Inp: ");
+                std::io::Write::flush(&mut std::io::stdout())?;
+                let mut input_text = String::new();
+                if std::io::stdin().read_line(&mut input_text)? == 0 {
+                    break;
+                }
+                let trimmed = input_text.trim();
+                match trimmed.parse::<i64>() {
+                    Ok(v) => recorder.push_input(v),
+                    Err(..) => println!("this was not an integer: {}", trimmed)
+                };
+            },
+            State::Error(ref e) => {
+                println!("Error: {:?} !", e);
+                break;
+            },
+            _ => break
+        }
+    }
+    std::fs::write(session, recorder.session().to_string())
+}
+
+// Usage: session record <program> <session file>
+//        session replay <program> <session file>
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let code = image::read(&args[2])?;
+    match args[1].as_str() {
+        "record" => record(code, &args[3]),
+        "replay" => {
+            let content = std::fs::read_to_string(&args[3])?;
+            let session = Session::parse(&content).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            match replay(code, &session) {
+                Ok(s) => println!("replayed {} events without divergence", s.events.len()),
+                Err(d) => {
+                    println!("divergence at event {}: expected {:?}, got {:?}", d.index, d.expected, d.actual);
+                    std::process::exit(1);
+                }
+            }
+            Ok(())
+        },
+        mode => {
+            eprintln!("unknown mode {}, expected record or replay", mode);
+            std::process::exit(2);
+        }
+    }
+}
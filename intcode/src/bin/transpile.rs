@@ -0,0 +1,18 @@
+use intcode::transpile;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    match intcode::image::read(&args[1]) {
+        Ok(code) => {
+            let source = transpile::transpile_binary(&code);
+            match args.get(2) {
+                Some(path) => std::fs::write(path, source),
+                None => {
+                    print!("{}", source);
+                    Ok(())
+                }
+            }
+        },
+        Err(e) => Err(e)
+    }
+}
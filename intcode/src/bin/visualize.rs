@@ -0,0 +1,86 @@
+use std::io::{Read, Write};
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use intcode::visualize::{Grid, Screen};
+use intcode::{image, Program, State};
+
+const FRAME: Duration = Duration::from_millis(40);
+
+// Switches the terminal to unbuffered input without echo, or back.
+fn raw_mode(on: bool) {
+    let args: &[&str] = if on { &["-icanon", "-echo", "min", "1"] } else { &["sane"] };
+    let _ = Command::new("stty").args(args).status();
+    print!("{}", if on { "\x1b[?25l" } else { "\x1b[?25h\n" });
+}
+
+// Usage: visualize <program> [tiles <palette> | ascii]
+// Keys: space play/pause, s step, + faster, - slower, h hex/decimal, q quit.
+// Typing a number followed by enter pushes it as input.
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let code = image::read(&args[1])?;
+    let grid = match args.get(2).map(|s| s.as_str()) {
+        Some("tiles")   => Grid::Tiles(args.get(3).cloned().unwrap_or_else(|| String::from(" #x-o"))),
+        Some("ascii")   => Grid::Ascii,
+        _               => Grid::Off
+    };
+    let mut program = Program::new(code, false);
+    program.enable_call_stack();
+    let mut screen = Screen::new(grid);
+
+    let (keys, received) = mpsc::channel();
+    thread::spawn(move || {
+        // Byte by byte so every key press arrives as soon as it is typed.
+        let mut key = [0u8; 1];
+        while let Ok(1) = std::io::stdin().read(&mut key) {
+            if keys.send(key[0]).is_err() {
+                break;
+            }
+        }
+    });
+
+    raw_mode(true);
+    let mut playing = false;
+    let mut speed = 1;
+    let mut typed = String::new();
+    loop {
+        let mut steps = if playing { speed } else { 0 };
+        while let Ok(key) = received.try_recv() {
+            match key {
+                b'q'                        => {
+                    raw_mode(false);
+                    return Ok(());
+                },
+                b' '                        => playing = !playing,
+                b's'                        => steps += 1,
+                b'+'                        => speed = (speed * 2).min(1 << 20),
+                b'-'                        => speed = (speed / 2).max(1),
+                b'h'                        => screen.toggle_radix(),
+                b'\n'                       => {
+                    if let Ok(v) = typed.parse::<i64>() {
+                        program.push_input(v);
+                    }
+                    typed.clear();
+                },
+                b'0'..=b'9'                 => typed.push(key as char),
+                b'n'                        => typed.push('-'),
+                _                           => ()
+            }
+        }
+        for _ in 0..steps {
+            program.step();
+        }
+        screen.collect(&mut program);
+        let status = format!("{}  speed {}/frame  typed: {}", if playing { "playing" } else { "paused" }, speed, typed);
+        print!("{}", screen.render(&program, &status));
+        println!("space play/pause  s step  + faster  - slower  h hex/dec  q quit  n minus  enter push input");
+        std::io::stdout().flush()?;
+        if matches!(program.state, State::Halt | State::Error(_)) {
+            playing = false;
+        }
+        thread::sleep(FRAME);
+    }
+}
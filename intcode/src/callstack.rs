@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+
+use crate::program::{Instruction, IntCode, OpCode, ParameterMode};
+
+/// An active call: `entry` was jumped to from `call_site` after the caller stored
+/// `return_address` on the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub entry: usize,
+    pub call_site: usize,
+    pub return_address: usize,
+    pub relative_base: usize
+}
+
+/// Reconstructs calls and returns of compiled programs which keep their stack behind the
+/// relative base. A taken jump counts as a call if the address right after it was just
+/// written to a relative slot, and as a return if its target was read from memory and
+/// matches the return address of an active frame.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    pub functions: BTreeSet<usize>,
+    pub names: HashMap<usize, String>,
+    // Values written to relative slots since the last jump.
+    stored: BTreeSet<i64>
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    /// The relative slot the instruction at `start` writes to, if any. Resolved before the
+    /// instruction runs, since it may overwrite its own operands.
+    pub(crate) fn destination(code: &IntCode, start: usize, instruction: &Instruction, relative_base: usize) -> Option<usize> {
+        let (pm1, _, pm3) = instruction.parameter_modes;
+        let operand = match instruction.op_code {
+            OpCode::Add | OpCode::Mul | OpCode::LessThan | OpCode::Equals if pm3 == ParameterMode::Relative => 3,
+            OpCode::In if pm1 == ParameterMode::Relative => 1,
+            _ => return None
+        };
+        let offset = *code.get(start + operand)?;
+        usize::try_from((relative_base as i64).checked_add(offset)?).ok()
+    }
+
+    /// `written` is the value the instruction stored to its `destination`, if any.
+    pub(crate) fn observe(&mut self, start: usize, instruction: &Instruction, written: Option<i64>, relative_base: usize, index: usize, new_relative_base: usize) {
+        let (_, pm2, _) = instruction.parameter_modes;
+        if let Some(value) = written {
+            self.stored.insert(value);
+        }
+        match instruction.op_code {
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                let next = start + 3;
+                if index != next {
+                    let returned = pm2 != ParameterMode::Immediate
+                        && self.frames.iter().any(|f| f.return_address == index);
+                    if returned {
+                        while let Some(frame) = self.frames.pop() {
+                            if frame.return_address == index {
+                                break;
+                            }
+                        }
+                    } else if self.stored.contains(&(next as i64)) {
+                        self.functions.insert(index);
+                        self.frames.push(Frame { entry: index, call_site: start, return_address: next, relative_base });
+                    }
+                }
+                self.stored.clear();
+            },
+            OpCode::AdjustRelativeBase => {
+                // Releasing the stack below a frame also ends it.
+                while self.frames.last().map(|f| f.relative_base > new_relative_base).unwrap_or(false) {
+                    self.frames.pop();
+                }
+            },
+            _ => ()
+        }
+    }
+
+    pub fn name(&self, entry: usize) -> String {
+        match self.names.get(&entry) {
+            Some(name)              => name.clone(),
+            None if entry == 0      => String::from("main"),
+            None                    => format!("fn_{}", entry)
+        }
+    }
+
+    /// `address` relative to the innermost active function, e.g. `fn_40+3`.
+    pub fn symbol(&self, address: usize) -> String {
+        let entry = self.frames.last().map(|f| f.entry).unwrap_or(0);
+        if address >= entry {
+            format!("{}+{}", self.name(entry), address - entry)
+        } else {
+            format!("{}-{}", self.name(entry), entry - address)
+        }
+    }
+
+    /// `(address, entry of the enclosing function)` per frame, innermost first: the
+    /// current `index`, then every call site.
+    pub fn locations(&self, index: usize) -> Vec<(usize, usize)> {
+        let mut locations = vec![(index, self.frames.last().map(|f| f.entry).unwrap_or(0))];
+        for (depth, frame) in self.frames.iter().enumerate().rev() {
+            let entry = if depth == 0 { 0 } else { self.frames[depth - 1].entry };
+            locations.push((frame.call_site, entry));
+        }
+        locations
+    }
+
+    /// One line per frame, innermost first, ending with `main`.
+    pub fn backtrace(&self, index: usize, relative_base: usize) -> Vec<String> {
+        let mut lines = vec![format!("#0  {} (ip {}, rb {})", self.symbol(index), index, relative_base)];
+        let frames = self.frames.iter().rev();
+        for (depth, ((call_site, entry), frame)) in self.locations(index).into_iter().skip(1).zip(frames).enumerate() {
+            let offset = call_site as i64 - entry as i64;
+            lines.push(format!("#{}  {}{:+} (call at {}, rb {})", depth + 1, self.name(entry), offset, call_site, frame.relative_base));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::program::Program;
+
+    // main stores its return address at [rb+0], moves the stack up and calls 24, which calls
+    // double at 38 the same way. Both return through the address stored on the stack.
+    fn nested() -> Vec<i64> {
+        vec![109,100,21101,15,0,0,21101,3,0,1,109,2,1105,1,24,99,0,0,0,0,0,0,0,0,
+             21101,33,0,0,109,1,1105,1,38,109,-1,2106,0,-1,
+             109,-1,2106,0,0]
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut program = Program::new(nested(), false);
+        program.enable_call_stack();
+        program.call_stack_mut().unwrap().names.insert(38, String::from("double"));
+        while program.index() != 38 {
+            program.step();
+        }
+        let calls = program.call_stack().unwrap();
+        assert_eq!(vec![24, 38], calls.frames.iter().map(|f| f.entry).collect::<Vec<usize>>());
+        assert_eq!(vec![
+            String::from("#0  double+0 (ip 38, rb 103)"),
+            String::from("#1  fn_24+6 (call at 30, rb 103)"),
+            String::from("#2  main+12 (call at 12, rb 102)")], program.backtrace().unwrap());
+        program.process();
+        let calls = program.call_stack().unwrap();
+        assert!(calls.frames.is_empty());
+        assert_eq!(vec![24, 38], calls.functions.iter().copied().collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_overwritten_operand() {
+        // Writes a large value over its own destination operand.
+        let mut program = Program::new(vec![21101,1000000,0,3,99], false);
+        program.enable_call_stack();
+        program.process();
+        assert_eq!(1000000, program.memory()[3]);
+        assert!(program.call_stack().unwrap().frames.is_empty());
+    }
+}
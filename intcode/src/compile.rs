@@ -0,0 +1,645 @@
+use std::collections::HashMap;
+
+use crate::asm::{self, mnemonic};
+use crate::program::{IntCode, OpCode};
+
+// Stack frames live behind the relative base:
+//   [rb+0]  return address, written by the caller right before it jumps
+//   [rb+1]  return value
+//   [rb+2]  parameters, then locals, then the temporaries of the current statement
+// A call copies the arguments above the caller's frame, moves rb there and jumps, so the
+// call stack reconstruction of `callstack` sees every call and return.
+const RETURN_ADDRESS: usize = 0;
+const RETURN_VALUE: usize = 1;
+const PARAMETERS: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Symbol(&'static str)
+}
+
+const SYMBOLS: [&str; 19] = ["==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "<", ">", "+", "-", "*", "!"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or("");
+        let chars = line.chars().collect::<Vec<char>>();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let text = chars[start..i].iter().collect::<String>();
+                let value = text.parse::<i64>().map_err(|_| format!("line {}: number {} is too large", n + 1, text))?;
+                tokens.push((Token::Number(value), n + 1));
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Identifier(chars[start..i].iter().collect()), n + 1));
+            } else {
+                let rest = chars[i..].iter().take(2).collect::<String>();
+                match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                    Some(symbol) => {
+                        tokens.push((Token::Symbol(symbol), n + 1));
+                        i += symbol.len();
+                    },
+                    None => return Err(format!("line {}: unexpected character {:?}", n + 1, c))
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(i64),
+    Variable(String, usize),
+    Call(String, Vec<Expr>, usize),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr, usize),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>, usize),
+    Break(usize),
+    Continue(usize),
+    Expr(Expr)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Function {
+    name: String,
+    parameters: Vec<String>,
+    body: Vec<Stmt>,
+    line: usize
+}
+
+// Binary operators from the loosest to the tightest binding.
+const PRECEDENCE: [&[&str]; 5] = [&["||"], &["&&"], &["==", "!="], &["<", "<=", ">", ">="], &["+", "-"]];
+const PRODUCT: &[&str] = &["*"];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    at: usize
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens.get(self.at).or_else(|| self.tokens.last()).map(|t| t.1).unwrap_or(1)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        match self.tokens.get(self.at) {
+            Some(_) => Err(format!("line {}: {}", self.line(), message)),
+            None => Err(format!("line {}: {} at the end of the program", self.line(), message))
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|t| &t.0)
+    }
+
+    fn next_is(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn keyword(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(w)) if w == word)
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        if self.next_is(symbol) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", symbol))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Identifier(name)) if !KEYWORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.at += 1;
+                Ok(name)
+            },
+            _ => self.error("expected a name")
+        }
+    }
+
+    fn program(&mut self) -> Result<(Vec<Function>, Vec<Stmt>), String> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        while self.peek().is_some() {
+            if self.keyword("fn") {
+                let line = self.line();
+                self.at += 1;
+                let name = self.identifier()?;
+                self.expect("(")?;
+                let mut parameters = Vec::new();
+                while !self.accept(")") {
+                    if !parameters.is_empty() {
+                        self.expect(",")?;
+                    }
+                    parameters.push(self.identifier()?);
+                }
+                let body = self.block()?;
+                functions.push(Function { name, parameters, body, line });
+            } else {
+                main.push(self.statement()?);
+            }
+        }
+        Ok((functions, main))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.accept("}") {
+            if self.peek().is_none() {
+                return self.error("expected `}`");
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        let line = self.line();
+        let word = match self.peek() {
+            Some(Token::Identifier(w)) => w.clone(),
+            _ => String::new()
+        };
+        let statement = match word.as_str() {
+            "let" => {
+                self.at += 1;
+                let name = self.identifier()?;
+                self.expect("=")?;
+                Stmt::Let(name, self.expression(0)?)
+            },
+            "if" => {
+                self.at += 1;
+                let condition = self.expression(0)?;
+                let then = self.block()?;
+                let otherwise = if self.keyword("else") {
+                    self.at += 1;
+                    if self.keyword("if") { vec![self.statement()?] } else { self.block()? }
+                } else {
+                    Vec::new()
+                };
+                return Ok(Stmt::If(condition, then, otherwise));
+            },
+            "while" => {
+                self.at += 1;
+                let condition = self.expression(0)?;
+                return Ok(Stmt::While(condition, self.block()?));
+            },
+            "return" => {
+                self.at += 1;
+                let value = if self.next_is(";") { None } else { Some(self.expression(0)?) };
+                Stmt::Return(value, line)
+            },
+            "break" => {
+                self.at += 1;
+                Stmt::Break(line)
+            },
+            "continue" => {
+                self.at += 1;
+                Stmt::Continue(line)
+            },
+            "fn" => return self.error("functions can only be defined at the top level"),
+            _ => {
+                let assignment = matches!(self.tokens.get(self.at + 1), Some((Token::Symbol("="), _)));
+                if assignment && !word.is_empty() {
+                    let name = self.identifier()?;
+                    self.at += 1;
+                    Stmt::Assign(name, self.expression(0)?, line)
+                } else {
+                    Stmt::Expr(self.expression(0)?)
+                }
+            }
+        };
+        self.expect(";")?;
+        Ok(statement)
+    }
+
+    fn expression(&mut self, level: usize) -> Result<Expr, String> {
+        let operators = match PRECEDENCE.get(level) {
+            Some(o) => *o,
+            None => PRODUCT
+        };
+        let next = |p: &mut Parser| if level < PRECEDENCE.len() { p.expression(level + 1) } else { p.unary() };
+        let mut lhs = next(self)?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Symbol(s)) if operators.contains(s) => *s,
+                _ => return Ok(lhs)
+            };
+            self.at += 1;
+            lhs = Expr::Binary(operator, Box::new(lhs), Box::new(next(self)?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        for operator in &["-", "!"] {
+            if self.accept(operator) {
+                return Ok(Expr::Unary(operator, Box::new(self.unary()?)));
+            }
+        }
+        let line = self.line();
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.at += 1;
+                Ok(Expr::Number(n))
+            },
+            Some(Token::Symbol("(")) => {
+                self.at += 1;
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            Some(Token::Identifier(_)) => {
+                let name = self.identifier()?;
+                if !self.accept("(") {
+                    return Ok(Expr::Variable(name, line));
+                }
+                let mut arguments = Vec::new();
+                while !self.accept(")") {
+                    if !arguments.is_empty() {
+                        self.expect(",")?;
+                    }
+                    arguments.push(self.expression(0)?);
+                }
+                Ok(Expr::Call(name, arguments, line))
+            },
+            _ => self.error("expected an expression")
+        }
+    }
+}
+
+const KEYWORDS: [&str; 8] = ["fn", "let", "if", "else", "while", "return", "break", "continue"];
+// Built in functions and their number of arguments.
+const BUILTINS: [(&str, usize); 2] = [("input", 0), ("output", 1)];
+
+// An operand before the frame size of its function is known.
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Immediate(i64),
+    Label(String),
+    Slot(usize),
+    // Slot of the frame a call sets up, relative to the end of the current frame.
+    Callee(usize),
+    // Plus or minus the frame size.
+    FrameSize(i64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Label(String),
+    Op(OpCode, Vec<Arg>)
+}
+
+struct Generator<'a> {
+    functions: &'a HashMap<String, usize>,
+    lines: Vec<Line>,
+    scopes: Vec<HashMap<String, usize>>,
+    locals: usize,
+    temporaries: usize,
+    frame: usize,
+    loops: Vec<(String, String)>,
+    labels: &'a mut usize,
+    in_function: bool
+}
+
+fn function_label(name: &str) -> String {
+    format!("f_{}", name)
+}
+
+impl<'a> Generator<'a> {
+    fn new(functions: &'a HashMap<String, usize>, labels: &'a mut usize, parameters: &[String]) -> Generator<'a> {
+        let scope = parameters.iter().enumerate().map(|(i, p)| (p.clone(), PARAMETERS + i)).collect();
+        Generator {
+            functions,
+            lines: Vec::new(),
+            scopes: vec![scope],
+            locals: PARAMETERS + parameters.len(),
+            temporaries: 0,
+            frame: PARAMETERS + parameters.len(),
+            loops: Vec::new(),
+            labels,
+            in_function: false
+        }
+    }
+
+    fn label(&mut self) -> String {
+        *self.labels += 1;
+        format!("_L{}", self.labels)
+    }
+
+    fn emit(&mut self, op_code: OpCode, args: Vec<Arg>) {
+        self.lines.push(Line::Op(op_code, args));
+    }
+
+    fn temporary(&mut self) -> usize {
+        let slot = self.locals + self.temporaries;
+        self.temporaries += 1;
+        self.frame = self.frame.max(slot + 1);
+        slot
+    }
+
+    fn variable(&self, name: &str, line: usize) -> Result<usize, String> {
+        self.scopes.iter().rev().find_map(|s| s.get(name).copied())
+                   .ok_or_else(|| format!("line {}: undefined variable `{}`", line, name))
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            self.temporaries = 0;
+            self.statement(statement)?;
+        }
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), String> {
+        match statement {
+            Stmt::Let(name, value) => {
+                let value = self.expression(value)?;
+                let slot = self.locals;
+                self.locals += 1;
+                self.frame = self.frame.max(self.locals);
+                self.scopes.last_mut().unwrap().insert(name.clone(), slot);
+                self.emit(OpCode::Add, vec![value, Arg::Immediate(0), Arg::Slot(slot)]);
+            },
+            Stmt::Assign(name, value, line) => {
+                let slot = self.variable(name, *line)?;
+                let value = self.expression(value)?;
+                self.emit(OpCode::Add, vec![value, Arg::Immediate(0), Arg::Slot(slot)]);
+            },
+            Stmt::If(condition, then, otherwise) => {
+                let condition = self.expression(condition)?;
+                let (otherwise_label, end) = (self.label(), self.label());
+                self.emit(OpCode::JumpIfFalse, vec![condition, Arg::Label(otherwise_label.clone())]);
+                self.block(then)?;
+                if !otherwise.is_empty() {
+                    self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Label(end.clone())]);
+                }
+                self.lines.push(Line::Label(otherwise_label));
+                if !otherwise.is_empty() {
+                    self.block(otherwise)?;
+                    self.lines.push(Line::Label(end));
+                }
+            },
+            Stmt::While(condition, body) => {
+                let (start, end) = (self.label(), self.label());
+                self.lines.push(Line::Label(start.clone()));
+                let condition = self.expression(condition)?;
+                self.emit(OpCode::JumpIfFalse, vec![condition, Arg::Label(end.clone())]);
+                self.loops.push((start.clone(), end.clone()));
+                self.block(body)?;
+                self.loops.pop();
+                self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Label(start)]);
+                self.lines.push(Line::Label(end));
+            },
+            Stmt::Return(value, line) => {
+                if !self.in_function {
+                    return Err(format!("line {}: return outside of a function", line));
+                }
+                let value = match value {
+                    Some(v) => self.expression(v)?,
+                    None => Arg::Immediate(0)
+                };
+                self.emit(OpCode::Add, vec![value, Arg::Immediate(0), Arg::Slot(RETURN_VALUE)]);
+                self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Slot(RETURN_ADDRESS)]);
+            },
+            Stmt::Break(line) => {
+                let (_, end) = self.loops.last().cloned().ok_or_else(|| format!("line {}: break outside of a loop", line))?;
+                self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Label(end)]);
+            },
+            Stmt::Continue(line) => {
+                let (start, _) = self.loops.last().cloned().ok_or_else(|| format!("line {}: continue outside of a loop", line))?;
+                self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Label(start)]);
+            },
+            Stmt::Expr(value) => {
+                self.expression(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Result<Arg, String> {
+        Ok(match expression {
+            Expr::Number(n) => Arg::Immediate(*n),
+            Expr::Variable(name, line) => Arg::Slot(self.variable(name, *line)?),
+            Expr::Unary(operator, operand) => {
+                let operand = self.expression(operand)?;
+                let result = self.temporary();
+                match *operator {
+                    "-" => self.emit(OpCode::Mul, vec![operand, Arg::Immediate(-1), Arg::Slot(result)]),
+                    _   => self.emit(OpCode::Equals, vec![operand, Arg::Immediate(0), Arg::Slot(result)])
+                }
+                Arg::Slot(result)
+            },
+            Expr::Binary(operator, lhs, rhs) if *operator == "&&" || *operator == "||" => {
+                // Keeps the negated value of the side decided last, so both end up as 0 or 1.
+                let lhs = self.expression(lhs)?;
+                let result = self.temporary();
+                let done = self.label();
+                self.emit(OpCode::Equals, vec![lhs, Arg::Immediate(0), Arg::Slot(result)]);
+                let jump = if *operator == "&&" { OpCode::JumpIfTrue } else { OpCode::JumpIfFalse };
+                self.emit(jump, vec![Arg::Slot(result), Arg::Label(done.clone())]);
+                let rhs = self.expression(rhs)?;
+                self.emit(OpCode::Equals, vec![rhs, Arg::Immediate(0), Arg::Slot(result)]);
+                self.lines.push(Line::Label(done));
+                self.emit(OpCode::Equals, vec![Arg::Slot(result), Arg::Immediate(0), Arg::Slot(result)]);
+                Arg::Slot(result)
+            },
+            Expr::Binary(operator, lhs, rhs) => {
+                let lhs = self.expression(lhs)?;
+                let rhs = self.expression(rhs)?;
+                let result = Arg::Slot(self.temporary());
+                match *operator {
+                    "+"     => self.emit(OpCode::Add, vec![lhs, rhs, result.clone()]),
+                    "*"     => self.emit(OpCode::Mul, vec![lhs, rhs, result.clone()]),
+                    "-"     => {
+                        self.emit(OpCode::Mul, vec![rhs, Arg::Immediate(-1), result.clone()]);
+                        self.emit(OpCode::Add, vec![lhs, result.clone(), result.clone()]);
+                    },
+                    "=="    => self.emit(OpCode::Equals, vec![lhs, rhs, result.clone()]),
+                    "<"     => self.emit(OpCode::LessThan, vec![lhs, rhs, result.clone()]),
+                    ">"     => self.emit(OpCode::LessThan, vec![rhs, lhs, result.clone()]),
+                    "!="    => {
+                        self.emit(OpCode::Equals, vec![lhs, rhs, result.clone()]);
+                        self.emit(OpCode::Equals, vec![result.clone(), Arg::Immediate(0), result.clone()]);
+                    },
+                    "<="    => {
+                        self.emit(OpCode::LessThan, vec![rhs, lhs, result.clone()]);
+                        self.emit(OpCode::Equals, vec![result.clone(), Arg::Immediate(0), result.clone()]);
+                    },
+                    _       => {
+                        self.emit(OpCode::LessThan, vec![lhs, rhs, result.clone()]);
+                        self.emit(OpCode::Equals, vec![result.clone(), Arg::Immediate(0), result.clone()]);
+                    }
+                }
+                result
+            },
+            Expr::Call(name, arguments, line) => self.call(name, arguments, *line)?
+        })
+    }
+
+    fn call(&mut self, name: &str, arguments: &[Expr], line: usize) -> Result<Arg, String> {
+        let arity = BUILTINS.iter().find(|b| b.0 == name).map(|b| b.1)
+                            .or_else(|| self.functions.get(name).copied())
+                            .ok_or_else(|| format!("line {}: undefined function `{}`", line, name))?;
+        if arguments.len() != arity {
+            return Err(format!("line {}: `{}` takes {} argument{}, found {}", line, name, arity, if arity == 1 { "" } else { "s" }, arguments.len()));
+        }
+        // Every argument is evaluated before any is copied, a nested call would overwrite them.
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.expression(argument)?);
+        }
+        match name {
+            "input" => {
+                let result = self.temporary();
+                self.emit(OpCode::In, vec![Arg::Slot(result)]);
+                return Ok(Arg::Slot(result));
+            },
+            "output" => {
+                self.emit(OpCode::Out, values);
+                return Ok(Arg::Immediate(0));
+            },
+            _ => {}
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            self.emit(OpCode::Add, vec![value, Arg::Immediate(0), Arg::Callee(PARAMETERS + i)]);
+        }
+        let back = self.label();
+        self.emit(OpCode::AdjustRelativeBase, vec![Arg::FrameSize(1)]);
+        self.emit(OpCode::Add, vec![Arg::Label(back.clone()), Arg::Immediate(0), Arg::Slot(RETURN_ADDRESS)]);
+        self.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Label(function_label(name))]);
+        self.lines.push(Line::Label(back));
+        self.emit(OpCode::AdjustRelativeBase, vec![Arg::FrameSize(-1)]);
+        let result = self.temporary();
+        self.emit(OpCode::Add, vec![Arg::Callee(RETURN_VALUE), Arg::Immediate(0), Arg::Slot(result)]);
+        Ok(Arg::Slot(result))
+    }
+
+    fn render(&self, source: &mut String) {
+        for line in &self.lines {
+            match line {
+                Line::Label(label) => source.push_str(&format!("{}:\n", label)),
+                Line::Op(op_code, args) => {
+                    let args = args.iter().map(|a| match a {
+                        Arg::Immediate(n)   => n.to_string(),
+                        Arg::Label(label)   => label.clone(),
+                        Arg::Slot(slot)     => format!("[rb+{}]", slot),
+                        Arg::Callee(slot)   => format!("[rb+{}]", self.frame + slot),
+                        Arg::FrameSize(s)   => (s * self.frame as i64).to_string()
+                    }).collect::<Vec<String>>();
+                    source.push_str(&format!("        {} {}\n", mnemonic(op_code), args.join(", ")).replace(" \n", "\n"));
+                }
+            }
+        }
+    }
+}
+
+/// Translates a program into the assembly dialect of `asm`.
+///
+/// ```text
+/// fn square(x) { return x * x; }
+/// let n = input();
+/// while n > 0 {
+///     output(square(n));
+///     n = n - 1;
+/// }
+/// ```
+///
+/// Statements outside of functions form the main program. Variables hold integers,
+/// comparisons and `!`, `&&`, `||` give 0 or 1, and `if` and `while` take any non zero
+/// value as true. `input()` reads a value, `output(x)` writes one.
+pub fn to_assembly(source: &str) -> Result<String, String> {
+    let mut parser = Parser { tokens: tokenize(source)?, at: 0 };
+    let (functions, main) = parser.program()?;
+    let mut arities = HashMap::new();
+    for function in &functions {
+        if BUILTINS.iter().any(|b| b.0 == function.name) || arities.insert(function.name.clone(), function.parameters.len()).is_some() {
+            return Err(format!("line {}: function `{}` is already defined", function.line, function.name));
+        }
+    }
+    let mut labels = 0;
+    let mut source = String::from("        AdjustRelativeBase _stack\n");
+    let mut generator = Generator::new(&arities, &mut labels, &[]);
+    generator.block(&main)?;
+    generator.emit(OpCode::Halt, Vec::new());
+    generator.render(&mut source);
+    for function in &functions {
+        let mut generator = Generator::new(&arities, &mut labels, &function.parameters);
+        generator.in_function = true;
+        generator.block(&function.body)?;
+        generator.emit(OpCode::Add, vec![Arg::Immediate(0), Arg::Immediate(0), Arg::Slot(RETURN_VALUE)]);
+        generator.emit(OpCode::JumpIfTrue, vec![Arg::Immediate(1), Arg::Slot(RETURN_ADDRESS)]);
+        source.push_str(&format!("{}:\n", function_label(&function.name)));
+        generator.render(&mut source);
+    }
+    source.push_str("_stack:\n        data 0\n");
+    Ok(source)
+}
+
+/// Compiles a program, see `to_assembly` for the language.
+pub fn compile(source: &str) -> Result<IntCode, String> {
+    asm::assemble_code(&to_assembly(source)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compile, to_assembly};
+
+    #[test]
+    fn test_assembly() {
+        let assembly = to_assembly("fn inc(x) { return x + 1; }\noutput(inc(41));").unwrap();
+        assert!(assembly.starts_with("        AdjustRelativeBase _stack\n"));
+        assert!(assembly.contains("f_inc:\n        Add [rb+2], 1, [rb+3]\n"), "{}", assembly);
+        assert!(assembly.contains("        JumpIfTrue 1, [rb+0]\n"), "{}", assembly);
+        assert!(assembly.ends_with("_stack:\n        data 0\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| compile(source).unwrap_err();
+        assert_eq!("line 2: undefined variable `y`", error("let x = 1;\nx = y;"));
+        assert_eq!("line 1: undefined function `f`", error("output(f(1));"));
+        assert_eq!("line 1: `output` takes 1 argument, found 2", error("output(1, 2);"));
+        assert_eq!("line 1: return outside of a function", error("return 1;"));
+        assert_eq!("line 1: break outside of a loop", error("if 1 { break; }"));
+        assert_eq!("line 2: function `f` is already defined", error("fn f() {}\nfn f(x) {}"));
+        assert_eq!("line 1: expected `;`", error("let x = 1 let"));
+        assert_eq!("line 1: expected an expression at the end of the program", error("let x ="));
+        assert_eq!("line 1: unexpected character '$'", error("let $ = 1;"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use crate::disassemble::disassemble;
+use crate::program::IntCode;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Branch {
+    pub taken: usize,
+    pub not_taken: usize
+}
+
+/// Execution counts per instruction address and outcomes of every conditional jump.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    pub hits: BTreeMap<usize, usize>,
+    pub lengths: BTreeMap<usize, usize>,
+    pub branches: BTreeMap<usize, Branch>
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Line {
+    Executed(usize),
+    Unexecuted,
+    Data
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub(crate) fn hit(&mut self, address: usize, len: usize) {
+        *self.hits.entry(address).or_insert(0) += 1;
+        self.lengths.insert(address, len);
+    }
+
+    pub(crate) fn branch(&mut self, address: usize, taken: bool) {
+        let branch = self.branches.entry(address).or_default();
+        if taken {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.hits {
+            *self.hits.entry(*address).or_insert(0) += count;
+        }
+        self.lengths.extend(other.lengths.iter().map(|(a, l)| (*a, *l)));
+        for (address, branch) in &other.branches {
+            let mine = self.branches.entry(*address).or_default();
+            mine.taken += branch.taken;
+            mine.not_taken += branch.not_taken;
+        }
+    }
+
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.hits.contains_key(&address)
+    }
+
+    // Splits the image into executed instructions, never executed instructions and data.
+    fn lines(&self, code: &IntCode) -> Vec<(usize, usize, Line)> {
+        let mut lines = Vec::new();
+        let mut ip = 0;
+        while ip < code.len() {
+            if let Some(count) = self.hits.get(&ip) {
+                let len = self.lengths[&ip].min(code.len() - ip);
+                lines.push((ip, len, Line::Executed(*count)));
+                ip += len;
+                continue;
+            }
+            // Never executed instructions must not overlap with executed ones.
+            let unexecuted = disassemble(code, ip).map(|(_, len)| len)
+                .filter(|len| (ip + 1..ip + len).all(|a| !self.hits.contains_key(&a)));
+            match unexecuted {
+                Some(len) => {
+                    lines.push((ip, len, Line::Unexecuted));
+                    ip += len;
+                },
+                None => {
+                    lines.push((ip, 1, Line::Data));
+                    ip += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    /// Annotated disassembly: execution counts, `----` for code that never ran and branch
+    /// outcomes, with `!` marking conditional jumps that only ever went one way.
+    pub fn listing(&self, code: &IntCode) -> String {
+        let mut out = Vec::new();
+        for (ip, len, line) in self.lines(code) {
+            let words = code[ip..ip + len].iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",");
+            let text = match line {
+                Line::Data  => String::from("data"),
+                _           => disassemble(code, ip).map(|(t, _)| t).unwrap_or_default()
+            };
+            let count = match line {
+                Line::Executed(n)   => format!("x{}", n),
+                Line::Unexecuted    => String::from("----"),
+                Line::Data          => String::new()
+            };
+            let branch = match self.branches.get(&ip) {
+                Some(b) => format!("{}taken {} / not taken {}", if b.taken == 0 || b.not_taken == 0 { "! " } else { "" }, b.taken, b.not_taken),
+                None    => String::new()
+            };
+            out.push(format!("{:>6}  {:<8}{:<24} {:<32} {}", ip, count, words, text, branch).trim_end().to_string());
+        }
+        out.join("\n")
+    }
+
+    /// Number of executed and statically found instructions.
+    pub fn summary(&self, code: &IntCode) -> (usize, usize) {
+        let lines = self.lines(code);
+        let executed = lines.iter().filter(|(_, _, l)| matches!(l, Line::Executed(_))).count();
+        let code_lines = lines.iter().filter(|(_, _, l)| *l != Line::Data).count();
+        (executed, code_lines)
+    }
+
+    pub fn html(&self, code: &IntCode) -> String {
+        let (executed, total) = self.summary(code);
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Intcode coverage</title>\n");
+        html.push_str("<style>\nbody { font-family: monospace; }\ntd { padding: 0 1em; }\n");
+        html.push_str(".executed { background: #c8f7c5; }\n.partial { background: #fbe7a1; }\n.unexecuted { background: #f7c5c5; }\n.data { color: #888; }\n</style>\n</head>\n<body>\n");
+        html.push_str(&format!("<h1>Intcode coverage</h1>\n<p>{} of {} instructions executed</p>\n<table>\n", executed, total));
+        html.push_str("<tr><th>Address</th><th>Hits</th><th>Words</th><th>Instruction</th><th>Branch</th></tr>\n");
+        for (ip, len, line) in self.lines(code) {
+            let words = code[ip..ip + len].iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",");
+            let branch = self.branches.get(&ip);
+            let class = match line {
+                Line::Executed(_) if branch.map(|b| b.taken == 0 || b.not_taken == 0).unwrap_or(false) => "partial",
+                Line::Executed(_)   => "executed",
+                Line::Unexecuted    => "unexecuted",
+                Line::Data          => "data"
+            };
+            let hits = match line {
+                Line::Executed(n)   => n.to_string(),
+                Line::Unexecuted    => String::from("0"),
+                Line::Data          => String::new()
+            };
+            let text = match line {
+                Line::Data  => String::from("data"),
+                _           => disassemble(code, ip).map(|(t, _)| t).unwrap_or_default()
+            };
+            let branch = branch.map(|b| format!("taken {} / not taken {}", b.taken, b.not_taken)).unwrap_or_default();
+            html.push_str(&format!("<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n", class, ip, hits, words, text, branch));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Branch, Coverage};
+    use crate::program::Program;
+
+    // Outputs 1 if the input equals 8 and 0 otherwise.
+    fn compare() -> Vec<i64> {
+        vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9]
+    }
+
+    fn run(input: i64) -> Coverage {
+        let mut program = Program::new(compare(), false);
+        program.enable_coverage();
+        program.push_input(input);
+        program.process();
+        program.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn test_branch_coverage() {
+        let zero = run(0);
+        assert_eq!(Some(&Branch { taken: 1, not_taken: 0 }), zero.branches.get(&2));
+        assert!(!zero.is_executed(5));
+        assert!(zero.listing(&compare()).contains("! taken 1 / not taken 0"));
+        let mut merged = zero.clone();
+        merged.merge(&run(5));
+        assert_eq!(Some(&Branch { taken: 1, not_taken: 1 }), merged.branches.get(&2));
+        assert!(merged.is_executed(5));
+        assert_eq!(Some(&2), merged.hits.get(&0));
+        assert_eq!((5, 5), merged.summary(&compare()));
+        assert_eq!((4, 5), zero.summary(&compare()));
+    }
+
+    #[test]
+    fn test_reports() {
+        let coverage = run(0);
+        let listing = coverage.listing(&compare());
+        assert!(listing.lines().any(|l| l.contains("----") && l.contains("Add [13], [14], [13]")));
+        assert!(listing.lines().any(|l| l.contains("x1") && l.contains("Halt")));
+        let html = coverage.html(&compare());
+        assert!(html.contains("<tr class=\"unexecuted\"><td>5</td>"));
+        assert!(html.contains("<tr class=\"partial\"><td>2</td>"));
+        assert!(html.contains("4 of 5 instructions executed"));
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::disassemble::listing;
+use crate::fuzz::panic_message;
+use crate::image;
+use crate::json::Json;
+use crate::program::{IntCode, Program, State};
+
+// Instructions executed per call of `run_slice`.
+const SLICE: usize = 4096;
+// Words per memory region shown in the variables view.
+const REGION: usize = 64;
+
+const REGISTERS: i64 = 1;
+const IO: i64 = 2;
+const MEMORY: i64 = 3;
+const REGIONS: i64 = 1000;
+
+// How far a resumed program runs before it stops on its own.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Target {
+    Breakpoint,
+    // Stop once the call depth is at most the given depth.
+    Depth(usize)
+}
+
+/// Debug Adapter Protocol session for one program. The disassembly listing serves as
+/// source, so line breakpoints refer to its lines, instruction breakpoints to addresses.
+pub struct Adapter {
+    program: Option<Program>,
+    code: IntCode,
+    lines: Vec<usize>,
+    line_breakpoints: BTreeSet<usize>,
+    instruction_breakpoints: BTreeSet<usize>,
+    outputs: Vec<i64>,
+    stop_on_entry: bool,
+    running: Option<Target>,
+    seq: i64,
+    pub finished: bool
+}
+
+impl Default for Adapter {
+    fn default() -> Adapter {
+        Adapter::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Adapter {
+        Adapter {
+            program: None,
+            code: Vec::new(),
+            lines: Vec::new(),
+            line_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            outputs: Vec::new(),
+            stop_on_entry: false,
+            running: None,
+            seq: 0,
+            finished: false
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    // Numbers messages in the order they are sent.
+    fn stamp(&mut self, mut messages: Vec<Json>) -> Vec<Json> {
+        for message in &mut messages {
+            if let Json::Object(members) = message {
+                self.seq += 1;
+                members.insert(0, (String::from("seq"), Json::from(self.seq)));
+            }
+        }
+        messages
+    }
+
+    fn event(&self, event: &str, body: Json) -> Json {
+        Json::object(vec![
+            ("type", Json::from("event")),
+            ("event", Json::from(event)),
+            ("body", body)])
+    }
+
+    fn response(&self, request: &Json, result: Result<Json, String>) -> Json {
+        let mut members = vec![
+            ("type", Json::from("response")),
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("success", Json::from(result.is_ok()))];
+        match result {
+            Ok(body) => members.push(("body", body)),
+            Err(message) => members.push(("message", Json::from(message)))
+        }
+        Json::object(members)
+    }
+
+    fn line(&self, address: usize) -> usize {
+        match self.lines.binary_search(&address) {
+            Ok(n) => n + 1,
+            Err(n) => n.max(1)
+        }
+    }
+
+    fn source(&self) -> Json {
+        Json::object(vec![("name", Json::from("program.asm")), ("sourceReference", Json::from(1i64))])
+    }
+
+    fn depth(&self) -> usize {
+        self.program.as_ref().and_then(|p| p.call_stack()).map(|c| c.frames.len()).unwrap_or(0)
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>) -> Json {
+        self.running = None;
+        let mut body = vec![
+            ("reason", Json::from(reason)),
+            ("threadId", Json::from(1i64)),
+            ("allThreadsStopped", Json::from(true))];
+        if let Some(text) = description {
+            body.push(("description", Json::from(text.clone())));
+            body.push(("text", Json::from(text)));
+        }
+        self.event("stopped", Json::object(body))
+    }
+
+    // Output events for new outputs and the event for a program that can not go on, if any.
+    fn report(&mut self, messages: &mut Vec<Json>, reason: &str) -> bool {
+        let program = match self.program.as_mut() {
+            Some(p) => p,
+            None => return true
+        };
+        let mut outputs = Vec::new();
+        while let Some(value) = program.pop_output() {
+            outputs.push(value);
+        }
+        let state = program.state.clone();
+        for value in outputs {
+            self.outputs.push(value);
+            let event = self.event("output", Json::object(vec![("category", Json::from("stdout")), ("output", Json::from(format!("{}\n", value)))]));
+            messages.push(event);
+        }
+        match state {
+            State::Halt => {
+                self.running = None;
+                let exited = self.event("exited", Json::object(vec![("exitCode", Json::from(0i64))]));
+                let terminated = self.event("terminated", Json::object(vec![]));
+                messages.push(exited);
+                messages.push(terminated);
+                true
+            },
+            State::Error(e) => {
+                let event = self.stopped("exception", Some(format!("Error: {}", e)));
+                messages.push(event);
+                true
+            },
+            State::WaitForInput => {
+                let event = self.stopped(reason, Some(String::from("waiting for input, evaluate `input <values>`")));
+                messages.push(event);
+                true
+            },
+            State::Idle => false
+        }
+    }
+
+    fn stop_reason(&self, target: Target) -> Option<&'static str> {
+        let index = self.program.as_ref()?.index();
+        match target {
+            Target::Depth(depth) if self.depth() <= depth           => Some("step"),
+            _ if self.line_breakpoints.contains(&index)             => Some("breakpoint"),
+            _ if self.instruction_breakpoints.contains(&index)      => Some("instruction breakpoint"),
+            _                                                       => None
+        }
+    }
+
+    /// Runs a running program for a while and returns the resulting events.
+    pub fn run_slice(&mut self) -> Vec<Json> {
+        let messages = self.slice();
+        self.stamp(messages)
+    }
+
+    fn slice(&mut self) -> Vec<Json> {
+        let mut messages = Vec::new();
+        let target = match self.running {
+            Some(t) => t,
+            None => return messages
+        };
+        for _ in 0..SLICE {
+            self.step();
+            if self.report(&mut messages, "pause") {
+                return messages;
+            }
+            if let Some(reason) = self.stop_reason(target) {
+                let event = self.stopped(reason, None);
+                messages.push(event);
+                return messages;
+            }
+        }
+        messages
+    }
+
+    fn launch(&mut self, arguments: Option<&Json>) -> Result<Json, String> {
+        let arguments = arguments.ok_or("missing launch arguments")?;
+        let path = arguments.get("program").and_then(|p| p.as_str()).ok_or("missing program")?;
+        let bytes = std::fs::read(path).map_err(|e| format!("can not read {}: {}", path, e))?;
+        let code = image::load(&bytes)?;
+        let mut program = Program::new(code.clone(), false);
+        program.enable_call_stack();
+        for value in arguments.get("inputs").and_then(|i| i.as_array()).into_iter().flatten() {
+            program.push_input(value.as_i64().ok_or("inputs must be integers")?);
+        }
+        self.lines = listing(&code).lines()
+                                   .filter_map(|l| l.split_whitespace().next().and_then(|a| a.parse::<usize>().ok()))
+                                   .collect();
+        self.code = code;
+        self.stop_on_entry = arguments.get("stopOnEntry").and_then(|s| s.as_bool()).unwrap_or(false);
+        self.program = Some(program);
+        Ok(Json::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: Option<&Json>) -> Result<Json, String> {
+        let requested = arguments.and_then(|a| a.get("breakpoints")).and_then(|b| b.as_array()).cloned().unwrap_or_default();
+        self.line_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint.get("line").and_then(|l| l.as_i64()).unwrap_or(0) as usize;
+            let address = if line >= 1 { self.lines.get(line - 1).copied() } else { None };
+            if let Some(address) = address {
+                self.line_breakpoints.insert(address);
+            }
+            breakpoints.push(Json::object(vec![
+                ("verified", Json::from(address.is_some())),
+                ("line", Json::from(line)),
+                ("source", self.source())]));
+        }
+        Ok(Json::object(vec![("breakpoints", Json::from(breakpoints))]))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: Option<&Json>) -> Result<Json, String> {
+        let requested = arguments.and_then(|a| a.get("breakpoints")).and_then(|b| b.as_array()).cloned().unwrap_or_default();
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let reference = breakpoint.get("instructionReference").and_then(|r| r.as_str()).unwrap_or("");
+            let offset = breakpoint.get("offset").and_then(|o| o.as_i64()).unwrap_or(0);
+            let address = reference.parse::<i64>().ok().map(|a| a + offset).filter(|a| *a >= 0).map(|a| a as usize);
+            if let Some(address) = address {
+                self.instruction_breakpoints.insert(address);
+            }
+            breakpoints.push(Json::object(vec![("verified", Json::from(address.is_some()))]));
+        }
+        Ok(Json::object(vec![("breakpoints", Json::from(breakpoints))]))
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let program = self.program.as_ref().ok_or("not launched")?;
+        let calls = program.call_stack().unwrap();
+        let frames = calls.locations(program.index()).into_iter().enumerate().map(|(id, (address, entry))| {
+            Json::object(vec![
+                ("id", Json::from(id)),
+                ("name", Json::from(format!("{}+{}", calls.name(entry), address as i64 - entry as i64))),
+                ("source", self.source()),
+                ("line", Json::from(self.line(address))),
+                ("column", Json::from(1i64)),
+                ("instructionPointerReference", Json::from(address.to_string()))])
+        }).collect::<Vec<Json>>();
+        let total = frames.len();
+        Ok(Json::object(vec![("stackFrames", Json::from(frames)), ("totalFrames", Json::from(total))]))
+    }
+
+    fn variables(&self, reference: i64) -> Result<Json, String> {
+        let program = self.program.as_ref().ok_or("not launched")?;
+        let variable = |name: String, value: String, reference: i64| Json::object(vec![
+            ("name", Json::from(name)),
+            ("value", Json::from(value)),
+            ("variablesReference", Json::from(reference))]);
+        let list = |values: &mut dyn Iterator<Item = &i64>| values.map(|v| v.to_string()).collect::<Vec<String>>().join(", ");
+        let memory = program.memory();
+        let variables = match reference {
+            REGISTERS => vec![
+                variable(String::from("ip"), program.index().to_string(), 0),
+                variable(String::from("rb"), program.relative_base().to_string(), 0),
+                variable(String::from("instructions"), program.instruction_count().to_string(), 0),
+                variable(String::from("state"), format!("{:?}", program.state), 0)],
+            IO => vec![
+                variable(String::from("input"), format!("[{}]", list(&mut program.input_queue().iter())), 0),
+                variable(String::from("output"), format!("[{}]", list(&mut self.outputs.iter())), 0)],
+            MEMORY => (0..memory.len()).step_by(REGION).map(|start| {
+                let end = (start + REGION).min(memory.len());
+                variable(format!("{}..{}", start, end - 1), format!("{} words", end - start), REGIONS + (start / REGION) as i64)
+            }).collect(),
+            r if r >= REGIONS => {
+                let start = ((r - REGIONS) as usize).checked_mul(REGION).filter(|s| *s < memory.len())
+                                                    .ok_or_else(|| format!("unknown variables reference {}", reference))?;
+                (start..(start + REGION).min(memory.len())).map(|a| variable(format!("[{}]", a), memory[a].to_string(), 0)).collect()
+            },
+            _ => return Err(format!("unknown variables reference {}", reference))
+        };
+        Ok(Json::object(vec![("variables", Json::from(variables))]))
+    }
+
+    fn evaluate(&mut self, arguments: Option<&Json>) -> Result<Json, String> {
+        let program = self.program.as_mut().ok_or("not launched")?;
+        let expression = arguments.and_then(|a| a.get("expression")).and_then(|e| e.as_str()).unwrap_or("").trim();
+        let result = match expression.strip_prefix("input") {
+            Some(values) => {
+                let values = values.split_whitespace()
+                                   .map(|v| v.parse::<i64>().map_err(|_| format!("not an integer: {}", v)))
+                                   .collect::<Result<Vec<i64>, String>>()?;
+                for value in &values {
+                    program.push_input(*value);
+                }
+                format!("pushed {} inputs", values.len())
+            },
+            None => {
+                let address = expression.trim_start_matches('[').trim_end_matches(']').parse::<usize>()
+                                        .map_err(|_| String::from("expected `input <values>` or an address"))?;
+                program.memory().get(address).copied().unwrap_or(0).to_string()
+            }
+        };
+        Ok(Json::object(vec![("result", Json::from(result)), ("variablesReference", Json::from(0i64))]))
+    }
+
+    // Steps the program, turning a panic of the interpreter into an error of the program.
+    fn step(&mut self) {
+        if let Some(program) = self.program.as_mut() {
+            if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| program.step())) {
+                program.state = State::Error(format!("panic: {}", panic_message(e)));
+            }
+        }
+    }
+
+    fn resume(&mut self, target: Target, messages: &mut Vec<Json>) {
+        if self.program.is_none() {
+            return;
+        }
+        // The first instruction always runs, so resuming leaves the breakpoint it stopped on.
+        self.step();
+        self.running = Some(target);
+        if self.report(messages, "step") {
+            return;
+        }
+        if let Some(reason) = self.stop_reason(target) {
+            let event = self.stopped(reason, None);
+            messages.push(event);
+        }
+    }
+
+    /// Error response to a message that could not be read.
+    pub fn malformed(&mut self, error: &str) -> Vec<Json> {
+        let response = self.response(&Json::Null, Err(format!("invalid message: {}", error)));
+        self.stamp(vec![response])
+    }
+
+    /// Handles one request and returns the response followed by any events.
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        let command = request.get("command").and_then(|c| c.as_str()).unwrap_or("").to_string();
+        let arguments = request.get("arguments");
+        let mut events = Vec::new();
+        let result = match command.as_str() {
+            "initialize" => {
+                events.push(self.event("initialized", Json::object(vec![])));
+                Ok(Json::object(vec![
+                    ("supportsConfigurationDoneRequest", Json::from(true)),
+                    ("supportsInstructionBreakpoints", Json::from(true)),
+                    ("supportsTerminateRequest", Json::from(true))]))
+            },
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(Json::object(vec![("breakpoints", Json::from(Vec::new()))])),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry", None));
+                } else {
+                    self.running = Some(Target::Breakpoint);
+                }
+                Ok(Json::Null)
+            },
+            "threads" => Ok(Json::object(vec![("threads", Json::from(vec![
+                Json::object(vec![("id", Json::from(1i64)), ("name", Json::from("intcode"))])]))])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(Json::object(vec![("scopes", Json::from(vec![
+                Json::object(vec![("name", Json::from("Registers")), ("variablesReference", Json::from(REGISTERS)), ("expensive", Json::from(false))]),
+                Json::object(vec![("name", Json::from("I/O")), ("variablesReference", Json::from(IO)), ("expensive", Json::from(false))]),
+                Json::object(vec![("name", Json::from("Memory")), ("variablesReference", Json::from(MEMORY)), ("expensive", Json::from(true))])]))])),
+            "variables" => self.variables(arguments.and_then(|a| a.get("variablesReference")).and_then(|r| r.as_i64()).unwrap_or(0)),
+            "source" => Ok(Json::object(vec![("content", Json::from(listing(&self.code)))])),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => {
+                self.resume(Target::Breakpoint, &mut events);
+                Ok(Json::object(vec![("allThreadsContinued", Json::from(true))]))
+            },
+            "next" => {
+                let depth = self.depth();
+                self.resume(Target::Depth(depth), &mut events);
+                Ok(Json::Null)
+            },
+            "stepIn" => {
+                self.resume(Target::Depth(usize::MAX), &mut events);
+                Ok(Json::Null)
+            },
+            "stepOut" => {
+                // Stepping out of the outermost function runs to the end.
+                let target = match self.depth() {
+                    0 => Target::Breakpoint,
+                    depth => Target::Depth(depth - 1)
+                };
+                self.resume(target, &mut events);
+                Ok(Json::Null)
+            },
+            "pause" => {
+                events.push(self.stopped("pause", None));
+                Ok(Json::Null)
+            },
+            "disconnect" | "terminate" => {
+                self.finished = true;
+                Ok(Json::Null)
+            },
+            _ => Err(format!("unsupported request {}", command))
+        };
+        let mut messages = vec![self.response(request, result)];
+        messages.extend(events);
+        self.stamp(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Adapter;
+    use crate::json::Json;
+
+    fn request(seq: i64, command: &str, arguments: Json) -> Json {
+        Json::object(vec![
+            ("seq", Json::from(seq)),
+            ("type", Json::from("request")),
+            ("command", Json::from(command)),
+            ("arguments", arguments)])
+    }
+
+    fn kinds(messages: &[Json]) -> Vec<String> {
+        messages.iter().map(|m| {
+            let kind = m.get("event").or_else(|| m.get("command")).and_then(|k| k.as_str()).unwrap_or("");
+            String::from(kind)
+        }).collect()
+    }
+
+    fn run(adapter: &mut Adapter) -> Vec<Json> {
+        let mut messages = Vec::new();
+        while adapter.is_running() {
+            messages.extend(adapter.run_slice());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_session() {
+        // [20] = 5, reads [21], outputs [20] + [21] and halts.
+        let path = std::env::temp_dir().join(format!("intcode-dap-{}.txt", std::process::id()));
+        std::fs::write(&path, "1101,2,3,20,3,21,1,20,21,22,4,22,99").unwrap();
+        let mut adapter = Adapter::new();
+        assert_eq!(vec!["initialize", "initialized"], kinds(&adapter.handle(&request(1, "initialize", Json::Null))));
+        let launch = adapter.handle(&request(2, "launch", Json::object(vec![
+            ("program", Json::from(path.to_str().unwrap())),
+            ("inputs", Json::from(vec![Json::from(7i64)]))])));
+        assert_eq!(Some(true), launch[0].get("success").and_then(|s| s.as_bool()));
+        let lines = Json::object(vec![("breakpoints", Json::from(vec![Json::object(vec![("line", Json::from(3i64))])]))]);
+        let breakpoints = adapter.handle(&request(3, "setBreakpoints", lines));
+        assert_eq!(Some(true), breakpoints[0].at(&["body", "breakpoints"]).and_then(|b| b.as_array()).and_then(|b| b[0].get("verified")).and_then(|v| v.as_bool()));
+        adapter.handle(&request(4, "configurationDone", Json::Null));
+
+        let stopped = run(&mut adapter);
+        assert_eq!(Some("breakpoint"), stopped[0].at(&["body", "reason"]).and_then(|r| r.as_str()));
+        let trace = adapter.handle(&request(5, "stackTrace", Json::object(vec![("threadId", Json::from(1i64))])));
+        let frame = trace[0].at(&["body", "stackFrames"]).and_then(|f| f.as_array()).map(|f| f[0].clone()).unwrap();
+        assert_eq!(Some(3), frame.get("line").and_then(|l| l.as_i64()));
+        assert_eq!(Some("main+6"), frame.get("name").and_then(|n| n.as_str()));
+        let registers = adapter.handle(&request(6, "variables", Json::object(vec![("variablesReference", Json::from(1i64))])));
+        assert!(registers[0].to_string().contains(r#"{"name":"ip","value":"6","variablesReference":0}"#));
+        let region = adapter.handle(&request(7, "variables", Json::object(vec![("variablesReference", Json::from(1000i64))])));
+        assert!(region[0].to_string().contains(r#"{"name":"[21]","value":"7","variablesReference":0}"#));
+        let huge = adapter.handle(&request(7, "variables", Json::object(vec![("variablesReference", Json::from(i64::MAX))])));
+        assert_eq!(Some(false), huge[0].get("success").and_then(|s| s.as_bool()));
+
+        let step = adapter.handle(&request(8, "next", Json::object(vec![("threadId", Json::from(1i64))])));
+        assert_eq!(vec!["next", "stopped"], kinds(&step));
+        let mut rest = adapter.handle(&request(9, "continue", Json::Null));
+        rest.extend(run(&mut adapter));
+        assert_eq!(vec!["continue", "output", "exited", "terminated"], kinds(&rest));
+        assert_eq!(Some("12\n"), rest[1].at(&["body", "output"]).and_then(|o| o.as_str()));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_panic() {
+        // An Add missing its operands panics the interpreter.
+        let path = std::env::temp_dir().join(format!("intcode-dap-panic-{}.txt", std::process::id()));
+        std::fs::write(&path, "1101,1").unwrap();
+        let mut adapter = Adapter::new();
+        adapter.handle(&request(1, "launch", Json::object(vec![("program", Json::from(path.to_str().unwrap()))])));
+        adapter.handle(&request(2, "configurationDone", Json::Null));
+        let stopped = run(&mut adapter);
+        assert_eq!(Some("exception"), stopped[0].at(&["body", "reason"]).and_then(|r| r.as_str()));
+        assert!(stopped[0].at(&["body", "text"]).and_then(|t| t.as_str()).unwrap().starts_with("Error: panic: "));
+        let malformed = adapter.malformed("missing Content-Length");
+        assert_eq!(Some(false), malformed[0].get("success").and_then(|s| s.as_bool()));
+        std::fs::remove_file(path).unwrap();
+    }
+}
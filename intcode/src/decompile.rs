@@ -0,0 +1,811 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::program::{decode, IntCode, OpCode, ParameterMode};
+
+// Functions are found through the calling convention of `compile` and `library`: the caller
+// stores an immediate return address in [rb+0] and jumps to an immediate target, the callee
+// returns by jumping to [rb+0]. Within a function the relative base is tracked as an offset
+// from its value on entry, so [rb+n] names the same local before and after an
+// `AdjustRelativeBase`. The program starts with rb = 0, so in `main` the offset is the
+// absolute address and relative operands name memory directly.
+
+// How a block ends.
+#[derive(Debug, Clone, PartialEq)]
+enum Exit {
+    Next(usize),
+    // Condition under which the jump is taken, its target and the fallthrough.
+    Branch(String, usize, usize),
+    // Callee, return address and the offset rb has in the call.
+    Call(usize, usize, Option<i64>),
+    Return(String),
+    Halt,
+    Goto(String),
+    // An address that does not hold a complete instruction.
+    Invalid(usize)
+}
+
+impl Exit {
+    fn successors(&self) -> Vec<usize> {
+        match self {
+            Exit::Next(next)            => vec![*next],
+            Exit::Branch(_, taken, next) => vec![*taken, *next],
+            Exit::Call(_, back, _)      => vec![*back],
+            _                           => Vec::new()
+        }
+    }
+}
+
+// One decoded instruction: its statement, and the exit if it ends a block.
+struct Lifted {
+    len: usize,
+    statement: Option<String>,
+    exit: Option<Exit>,
+    delta: Option<i64>,
+    reads: Vec<String>,
+    writes: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    statements: Vec<String>,
+    exit: Exit
+}
+
+struct Function {
+    entry: usize,
+    blocks: BTreeMap<usize, Block>,
+    parameters: Vec<String>
+}
+
+fn local(slot: i64) -> String {
+    if slot < 0 {
+        format!("local_m{}", slot.unsigned_abs())
+    } else {
+        format!("local_{}", slot)
+    }
+}
+
+fn memory(address: i64) -> String {
+    if address < 0 {
+        format!("mem[{}]", address)
+    } else {
+        format!("mem_{}", address)
+    }
+}
+
+// The variable a relative operand names, see the comment at the top.
+fn slot(main: bool, delta: Option<i64>, raw: i64) -> String {
+    match delta.and_then(|d| d.checked_add(raw)) {
+        Some(slot) if main  => memory(slot),
+        Some(slot)          => local(slot),
+        None                => format!("rb[{}]", raw)
+    }
+}
+
+fn negate(condition: &str) -> String {
+    match condition.strip_prefix('!') {
+        Some(inner) => String::from(inner),
+        None        => format!("!{}", condition)
+    }
+}
+
+struct Decompiler<'a> {
+    code: &'a IntCode
+}
+
+impl<'a> Decompiler<'a> {
+    fn lift(&self, ip: usize, delta: Option<i64>, main: bool) -> Lifted {
+        let code = self.code;
+        let instruction = decode(code[ip]);
+        let count = instruction.op_code.parameter_count();
+        let mut lifted = Lifted { len: count + 1, statement: None, exit: None, delta, reads: Vec::new(), writes: Vec::new() };
+        if let OpCode::Err(_) = instruction.op_code {
+            lifted.exit = Some(Exit::Invalid(ip));
+            return lifted;
+        }
+        if ip + count >= code.len() {
+            lifted.exit = Some(Exit::Invalid(ip));
+            return lifted;
+        }
+        let mode = |n: usize| instruction.parameter_mode(n);
+        let raw = |n: usize| code[ip + n + 1];
+        let name = |n: usize| match mode(n) {
+            ParameterMode::Position     => memory(raw(n)),
+            ParameterMode::Immediate    => raw(n).to_string(),
+            ParameterMode::Relative     => slot(main, delta, raw(n))
+        };
+        for n in 0..count {
+            if mode(n) != ParameterMode::Immediate {
+                if instruction.op_code.written_parameter() == Some(n) {
+                    lifted.writes.push(name(n));
+                } else {
+                    lifted.reads.push(name(n));
+                }
+            }
+        }
+        let immediate = |n: usize, value: i64| mode(n) == ParameterMode::Immediate && raw(n) == value;
+        lifted.statement = match instruction.op_code {
+            OpCode::Add if immediate(1, 0)                              => Some(format!("{} = {};", name(2), name(0))),
+            OpCode::Add if immediate(0, 0)                              => Some(format!("{} = {};", name(2), name(1))),
+            OpCode::Add if mode(1) == ParameterMode::Immediate && raw(1) < 0 => Some(format!("{} = {} - {};", name(2), name(0), raw(1).unsigned_abs())),
+            OpCode::Add                                                 => Some(format!("{} = {} + {};", name(2), name(0), name(1))),
+            OpCode::Mul if immediate(1, 1)                              => Some(format!("{} = {};", name(2), name(0))),
+            OpCode::Mul if immediate(0, 1)                              => Some(format!("{} = {};", name(2), name(1))),
+            OpCode::Mul if immediate(1, -1)                             => Some(format!("{} = -{};", name(2), name(0))),
+            OpCode::Mul                                                 => Some(format!("{} = {} * {};", name(2), name(0), name(1))),
+            OpCode::LessThan                                            => Some(format!("{} = {} < {};", name(2), name(0), name(1))),
+            OpCode::Equals if immediate(1, 0)                           => Some(format!("{} = !{};", name(2), name(0))),
+            OpCode::Equals                                              => Some(format!("{} = {} == {};", name(2), name(0), name(1))),
+            OpCode::In                                                  => Some(format!("{} = input();", name(0))),
+            OpCode::Out                                                 => Some(format!("output({});", name(0))),
+            OpCode::AdjustRelativeBase => {
+                if mode(0) == ParameterMode::Immediate {
+                    lifted.delta = delta.and_then(|d| d.checked_add(raw(0)));
+                    None
+                } else {
+                    lifted.delta = None;
+                    Some(format!("rb += {};", name(0)))
+                }
+            },
+            OpCode::Halt => {
+                lifted.exit = Some(Exit::Halt);
+                None
+            },
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                let on_true = instruction.op_code == OpCode::JumpIfTrue;
+                let next = ip + 3;
+                if mode(0) == ParameterMode::Immediate && (raw(0) != 0) != on_true {
+                    // Never taken.
+                    return lifted;
+                }
+                let always = mode(0) == ParameterMode::Immediate;
+                let target = match mode(1) {
+                    ParameterMode::Immediate if raw(1) >= 0 && (raw(1) as usize) < code.len() => Some(raw(1) as usize),
+                    _ => None
+                };
+                let returns = !main && mode(1) == ParameterMode::Relative && delta.and_then(|d| d.checked_add(raw(1))) == Some(0);
+                let condition = if on_true { name(0) } else { format!("!{}", name(0)) };
+                lifted.exit = Some(match (always, target) {
+                    (true, Some(target)) => match self.return_address(ip) {
+                        Some(back) => Exit::Call(target, back, delta),
+                        None       => Exit::Next(target)
+                    },
+                    (true, None) if returns => Exit::Return(slot(main, delta, 1)),
+                    (true, None)            => Exit::Goto(name(1)),
+                    (false, Some(target))   => Exit::Branch(condition, target, next),
+                    (false, None) => {
+                        // Conditional jumps to computed addresses stay a statement.
+                        lifted.statement = Some(format!("if ({}) goto *{};", condition, name(1)));
+                        Exit::Next(next)
+                    }
+                });
+                None
+            },
+            OpCode::Err(_) => None
+        };
+        lifted
+    }
+
+    // The return address stored by the instruction right before a jump at `ip`, if it stores
+    // a constant to [rb+0].
+    fn return_address(&self, ip: usize) -> Option<usize> {
+        let previous = ip.checked_sub(4)?;
+        let instruction = decode(self.code[previous]);
+        let raw = |n: usize| self.code[previous + n + 1];
+        let immediate = |n: usize| instruction.parameter_mode(n) == ParameterMode::Immediate;
+        if instruction.parameter_mode(2) != ParameterMode::Relative || raw(2) != 0 || !immediate(0) || !immediate(1) {
+            return None;
+        }
+        let value = match instruction.op_code {
+            OpCode::Add => raw(0).checked_add(raw(1))?,
+            OpCode::Mul => raw(0).checked_mul(raw(1))?,
+            _ => return None
+        };
+        if value >= 0 && (value as usize) < self.code.len() {
+            Some(value as usize)
+        } else {
+            None
+        }
+    }
+
+    // Follows every path from `entry`, returning the blocks and the callees.
+    fn function(&self, entry: usize) -> (Function, Vec<usize>) {
+        let main = entry == 0;
+        let mut deltas: BTreeMap<usize, Option<i64>> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut callees = Vec::new();
+        let mut work = vec![(entry, Some(0))];
+        leaders.insert(entry);
+        while let Some((ip, delta)) = work.pop() {
+            if ip >= self.code.len() {
+                continue;
+            }
+            let delta = match deltas.get(&ip) {
+                Some(known) if *known == delta || known.is_none() => continue,
+                Some(_) => None,
+                None => delta
+            };
+            deltas.insert(ip, delta);
+            let lifted = self.lift(ip, delta, main);
+            match &lifted.exit {
+                None => work.push((ip + lifted.len, lifted.delta)),
+                Some(exit) => {
+                    if let Exit::Call(callee, _, _) = exit {
+                        callees.push(*callee);
+                    }
+                    for successor in exit.successors() {
+                        leaders.insert(successor);
+                        work.push((successor, lifted.delta));
+                    }
+                }
+            }
+        }
+        let mut blocks = BTreeMap::new();
+        for leader in leaders.iter().filter(|l| deltas.contains_key(l)) {
+            let mut ip = *leader;
+            let mut statements = Vec::new();
+            let mut last = None;
+            let exit = loop {
+                let lifted = self.lift(ip, deltas[&ip], main);
+                if let Some(statement) = lifted.statement {
+                    // Copies of a word onto itself do nothing.
+                    let parts = statement.trim_end_matches(';').split(" = ").collect::<Vec<&str>>();
+                    if parts.len() != 2 || parts[0] != parts[1] {
+                        statements.push(statement);
+                    }
+                    last = Some(ip);
+                }
+                if let Some(exit) = lifted.exit {
+                    if let Exit::Call(..) = exit {
+                        // The return address is part of the call.
+                        if last == ip.checked_sub(4) {
+                            statements.pop();
+                        }
+                    }
+                    break exit;
+                }
+                let next = ip + lifted.len;
+                if leaders.contains(&next) || next >= self.code.len() {
+                    break if next >= self.code.len() { Exit::Invalid(next) } else { Exit::Next(next) };
+                }
+                ip = next;
+            };
+            blocks.insert(*leader, Block { statements, exit });
+        }
+        let parameters = if main { Vec::new() } else { self.parameters(&deltas) };
+        (Function { entry, blocks, parameters }, callees)
+    }
+
+    // Locals from [rb+2] on up to the last one read before anything writes it, in address order.
+    fn parameters(&self, deltas: &BTreeMap<usize, Option<i64>>) -> Vec<String> {
+        let mut written = HashSet::new();
+        let mut last = 1;
+        for (ip, delta) in deltas {
+            let lifted = self.lift(*ip, *delta, false);
+            for read in &lifted.reads {
+                if written.contains(read) {
+                    continue;
+                }
+                let n = read.strip_prefix("local_").and_then(|n| n.parse::<i64>().ok());
+                if let Some(n) = n.filter(|n| *n > last) {
+                    last = n;
+                }
+            }
+            written.extend(lifted.writes);
+            if let Some(Exit::Call(_, _, delta)) = lifted.exit {
+                written.insert(slot(false, delta, 1));
+            }
+        }
+        (2..=last).map(local).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Label(usize),
+    Line(String),
+    If(String, Vec<Node>, Vec<Node>),
+    Loop(Vec<Node>),
+    While(String, Vec<Node>),
+    Break,
+    Continue,
+    Goto(usize),
+    // Statements that leave the function.
+    End(String)
+}
+
+fn jumps_away(nodes: &[Node]) -> bool {
+    matches!(nodes.last(), Some(Node::Break) | Some(Node::Continue) | Some(Node::Goto(_)) | Some(Node::End(_)))
+}
+
+// Turns the structure found into something closer to what a person writes: labels nothing
+// jumps to go away, branches that jump away come first without an else, preferring the
+// shorter one, and loops testing their condition first become `while` loops.
+fn simplify(nodes: Vec<Node>, gotos: &BTreeSet<usize>) -> Vec<Node> {
+    let mut result = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Label(address) if !gotos.contains(&address) => {},
+            Node::If(condition, then, otherwise) => {
+                let then = simplify(then, gotos);
+                let otherwise = simplify(otherwise, gotos);
+                if then.is_empty() && otherwise.is_empty() {
+                    continue;
+                } else if then.is_empty() {
+                    result.push(Node::If(negate(&condition), otherwise, Vec::new()));
+                } else if jumps_away(&otherwise) && (!jumps_away(&then) || otherwise.len() <= then.len()) {
+                    result.push(Node::If(negate(&condition), otherwise, Vec::new()));
+                    result.extend(then);
+                } else if !otherwise.is_empty() && jumps_away(&then) {
+                    result.push(Node::If(condition, then, Vec::new()));
+                    result.extend(otherwise);
+                } else {
+                    result.push(Node::If(condition, then, otherwise));
+                }
+            },
+            Node::Loop(body) => {
+                let mut body = simplify(body, gotos);
+                if body.last() == Some(&Node::Continue) {
+                    body.pop();
+                }
+                // A label in front of the condition can as well go in front of the loop.
+                let labels = body.iter().take_while(|n| matches!(n, Node::Label(_))).count();
+                match body.get(labels).cloned() {
+                    Some(Node::If(condition, then, otherwise)) if then == [Node::Break] && otherwise.is_empty() => {
+                        result.extend(body.drain(..labels));
+                        body.remove(0);
+                        result.push(Node::While(negate(&condition), body));
+                    },
+                    _ => result.push(Node::Loop(body))
+                }
+            },
+            other => result.push(other)
+        }
+    }
+    result
+}
+
+// Turns the blocks of a function into nested statements.
+struct Structurer<'a> {
+    blocks: &'a BTreeMap<usize, Block>,
+    ipdom: HashMap<usize, usize>,
+    // Header to body and the exit, if the loop has one that is not a dead end.
+    loops: HashMap<usize, (BTreeSet<usize>, Option<usize>)>,
+    emitted: HashSet<usize>,
+    gotos: BTreeSet<usize>,
+    stack: Vec<usize>
+}
+
+impl<'a> Structurer<'a> {
+    fn new(blocks: &'a BTreeMap<usize, Block>, entry: usize) -> Structurer<'a> {
+        let mut structurer = Structurer {
+            blocks,
+            ipdom: HashMap::new(),
+            loops: HashMap::new(),
+            emitted: HashSet::new(),
+            gotos: BTreeSet::new(),
+            stack: Vec::new()
+        };
+        structurer.post_dominators();
+        structurer.find_loops(entry);
+        structurer
+    }
+
+    fn successors(&self, block: usize) -> Vec<usize> {
+        self.blocks[&block].exit.successors().into_iter().filter(|s| self.blocks.contains_key(s)).collect()
+    }
+
+    fn terminal(&self, block: usize) -> bool {
+        self.successors(block).is_empty()
+    }
+
+    fn post_dominators(&mut self) {
+        // `usize::MAX` stands for the exit every dead end leads to.
+        const EXIT: usize = usize::MAX;
+        let all = self.blocks.keys().copied().chain(std::iter::once(EXIT)).collect::<BTreeSet<usize>>();
+        let mut pdom: HashMap<usize, BTreeSet<usize>> = self.blocks.keys().map(|b| (*b, all.clone())).collect();
+        pdom.insert(EXIT, std::iter::once(EXIT).collect());
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in self.blocks.keys().rev() {
+                let successors = self.successors(*block);
+                let successors = if successors.is_empty() { vec![EXIT] } else { successors };
+                let mut set = successors.iter()
+                                        .map(|s| pdom[s].clone())
+                                        .reduce(|a, b| a.intersection(&b).copied().collect())
+                                        .unwrap();
+                set.insert(*block);
+                if set != pdom[block] {
+                    pdom.insert(*block, set);
+                    changed = true;
+                }
+            }
+        }
+        for (block, set) in &pdom {
+            let strict = set.iter().filter(|d| *d != block).copied().collect::<BTreeSet<usize>>();
+            let immediate = strict.iter().find(|d| pdom.get(d).map(|p| *p == strict).unwrap_or(false));
+            if let Some(d) = immediate.filter(|d| **d != EXIT) {
+                self.ipdom.insert(*block, *d);
+            }
+        }
+    }
+
+    fn find_loops(&mut self, entry: usize) {
+        // Back edges from a depth first search, then the natural loop of each.
+        let mut back_edges = Vec::new();
+        let mut state: HashMap<usize, bool> = HashMap::new();
+        let mut stack = vec![(entry, 0)];
+        state.insert(entry, true);
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.successors(block);
+            if next < successors.len() {
+                stack.push((block, next + 1));
+                let successor = successors[next];
+                match state.get(&successor) {
+                    Some(true) => back_edges.push((block, successor)),
+                    Some(false) => {},
+                    None => {
+                        state.insert(successor, true);
+                        stack.push((successor, 0));
+                    }
+                }
+            } else {
+                state.insert(block, false);
+            }
+        }
+        let mut predecessors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for block in self.blocks.keys() {
+            for successor in self.successors(*block) {
+                predecessors.entry(successor).or_default().push(*block);
+            }
+        }
+        for (tail, header) in back_edges {
+            let body = &mut self.loops.entry(header).or_insert_with(|| (std::iter::once(header).collect(), None)).0;
+            let mut work = vec![tail];
+            while let Some(block) = work.pop() {
+                if body.insert(block) {
+                    work.extend(predecessors.get(&block).cloned().unwrap_or_default());
+                }
+            }
+        }
+        // The first block after the loop that is not a dead end, or the dead end the loop
+        // always leaves to or leaves to from several places, which is where `break` goes.
+        let exits = self.loops.iter().map(|(header, (body, _))| {
+            let edges = body.iter().flat_map(|b| self.successors(*b)).filter(|s| !body.contains(s)).collect::<Vec<usize>>();
+            let exit = edges.iter()
+                            .filter(|s| !self.terminal(**s))
+                            .min()
+                            .or_else(|| edges.iter().filter(|s| edges.iter().all(|e| e == *s) || edges.iter().filter(|e| e == s).count() > 1).min())
+                            .copied();
+            (*header, exit)
+        }).collect::<Vec<_>>();
+        for (header, exit) in exits {
+            self.loops.get_mut(&header).unwrap().1 = exit;
+        }
+    }
+
+    fn emit(&mut self, block: usize, stop: Option<usize>, out: &mut Vec<Node>) {
+        let mut current = Some(block);
+        while let Some(block) = current {
+            if Some(block) == stop {
+                return;
+            }
+            if !self.blocks.contains_key(&block) {
+                // Runs off the end of memory.
+                out.push(Node::End(format!("error(\"invalid instruction at {}\");", block)));
+                return;
+            }
+            if let Some(header) = self.stack.last() {
+                let (body, exit) = &self.loops[header];
+                if block == *header {
+                    out.push(Node::Continue);
+                    return;
+                }
+                if Some(block) == *exit {
+                    out.push(Node::Break);
+                    return;
+                }
+                // Dead ends are written out where they are reached, anything else outside the
+                // loop is jumped to.
+                if !body.contains(&block) && (!self.terminal(block) || self.emitted.contains(&block)) {
+                    self.gotos.insert(block);
+                    out.push(Node::Goto(block));
+                    return;
+                }
+            }
+            if self.emitted.contains(&block) {
+                self.gotos.insert(block);
+                out.push(Node::Goto(block));
+                return;
+            }
+            if self.loops.contains_key(&block) && !self.stack.contains(&block) {
+                self.stack.push(block);
+                let mut body = Vec::new();
+                if let Some(next) = self.block(block, None, &mut body) {
+                    self.emit(next, None, &mut body);
+                }
+                self.stack.pop();
+                out.push(Node::Loop(body));
+                current = self.loops[&block].1;
+            } else {
+                current = self.block(block, stop, out);
+            }
+        }
+    }
+
+    // Emits one block, returns where the code continues after it.
+    fn block(&mut self, block: usize, stop: Option<usize>, out: &mut Vec<Node>) -> Option<usize> {
+        self.emitted.insert(block);
+        out.push(Node::Label(block));
+        let contents = &self.blocks[&block];
+        out.extend(contents.statements.iter().map(|s| Node::Line(s.clone())));
+        match &contents.exit {
+            Exit::Next(next) => Some(*next),
+            Exit::Branch(condition, taken, next) => {
+                let mut merge = self.ipdom.get(&block).copied();
+                if let Some(header) = self.stack.last() {
+                    if merge.map(|m| !self.loops[header].0.contains(&m)).unwrap_or(false) {
+                        // Both branches leave the loop on their own.
+                        merge = None;
+                    }
+                }
+                let (condition, taken, next) = (condition.clone(), *taken, *next);
+                let branch_stop = merge.or(stop);
+                let mut then = Vec::new();
+                self.emit(next, branch_stop, &mut then);
+                let mut otherwise = Vec::new();
+                self.emit(taken, branch_stop, &mut otherwise);
+                out.push(Node::If(negate(&condition), then, otherwise));
+                merge
+            },
+            Exit::Call(..) => unreachable!("calls are turned into statements first"),
+            Exit::Return(value) => {
+                out.push(Node::End(format!("return {};", value)));
+                None
+            },
+            Exit::Halt => {
+                out.push(Node::End(String::from("halt();")));
+                None
+            },
+            Exit::Goto(target) => {
+                out.push(Node::End(format!("goto *{};", target)));
+                None
+            },
+            Exit::Invalid(address) => {
+                out.push(Node::End(format!("error(\"invalid instruction at {}\");", address)));
+                None
+            }
+        }
+    }
+
+    fn structure(mut self, entry: usize) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        self.emit(entry, None, &mut nodes);
+        // Targets of gotos that no structure took care of go after everything else.
+        while let Some(target) = self.gotos.iter().find(|g| !self.emitted.contains(g)).copied() {
+            self.emit(target, None, &mut nodes);
+        }
+        simplify(nodes, &self.gotos)
+    }
+}
+
+// Points branches past blocks that only jump on, then drops the blocks no longer reached.
+fn thread_jumps(blocks: &mut BTreeMap<usize, Block>, entry: usize) {
+    let forward = |mut block: usize| {
+        let mut seen = HashSet::new();
+        while let Some(Block { statements, exit: Exit::Next(next) }) = blocks.get(&block) {
+            if !statements.is_empty() || !seen.insert(block) {
+                break;
+            }
+            block = *next;
+        }
+        block
+    };
+    let threaded = blocks.iter().map(|(address, block)| {
+        let exit = match &block.exit {
+            Exit::Next(next)                        => Exit::Next(forward(*next)),
+            Exit::Branch(condition, taken, next)    => Exit::Branch(condition.clone(), forward(*taken), forward(*next)),
+            other                                   => other.clone()
+        };
+        (*address, exit)
+    }).collect::<Vec<_>>();
+    for (address, exit) in threaded {
+        blocks.get_mut(&address).unwrap().exit = exit;
+    }
+    let mut reached = HashSet::new();
+    let mut work = vec![entry];
+    while let Some(block) = work.pop() {
+        if reached.insert(block) {
+            work.extend(blocks.get(&block).map(|b| b.exit.successors()).unwrap_or_default());
+        }
+    }
+    blocks.retain(|address, _| reached.contains(address));
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 {
+        String::from("main")
+    } else {
+        format!("f_{}", entry)
+    }
+}
+
+fn render(nodes: &[Node], depth: usize, text: &mut String) {
+    let indent = "    ".repeat(depth);
+    for node in nodes {
+        match node {
+            Node::Label(address) => writeln!(text, "{}L_{}:", "    ".repeat(depth - 1), address).unwrap(),
+            Node::Line(line) | Node::End(line) => writeln!(text, "{}{}", indent, line).unwrap(),
+            Node::Break => writeln!(text, "{}break;", indent).unwrap(),
+            Node::Continue => writeln!(text, "{}continue;", indent).unwrap(),
+            Node::Goto(address) => writeln!(text, "{}goto L_{};", indent, address).unwrap(),
+            Node::If(condition, then, otherwise) => {
+                writeln!(text, "{}if ({}) {{", indent, condition).unwrap();
+                render(then, depth + 1, text);
+                let mut otherwise = otherwise;
+                while let [Node::If(condition, then, rest)] = &otherwise[..] {
+                    writeln!(text, "{}}} else if ({}) {{", indent, condition).unwrap();
+                    render(then, depth + 1, text);
+                    otherwise = rest;
+                }
+                if !otherwise.is_empty() {
+                    writeln!(text, "{}}} else {{", indent).unwrap();
+                    render(otherwise, depth + 1, text);
+                }
+                writeln!(text, "{}}}", indent).unwrap();
+            },
+            Node::Loop(body) => {
+                writeln!(text, "{}while (1) {{", indent).unwrap();
+                render(body, depth + 1, text);
+                writeln!(text, "{}}}", indent).unwrap();
+            },
+            Node::While(condition, body) => {
+                writeln!(text, "{}while ({}) {{", indent, condition).unwrap();
+                render(body, depth + 1, text);
+                writeln!(text, "{}}}", indent).unwrap();
+            }
+        }
+    }
+}
+
+/// Decompiles the code reachable from address 0 and from the functions it calls into C-like
+/// pseudocode. Words are named `mem_<address>`, the stack frame of a function `local_<n>`
+/// for [rb+n] on entry, where `local_0` is the return address and `local_1` the result.
+/// Control flow that has no structured form is left as `goto`.
+pub fn decompile(code: &IntCode) -> String {
+    if code.is_empty() {
+        return String::new();
+    }
+    let decompiler = Decompiler { code };
+    let mut functions = BTreeMap::new();
+    let mut work = vec![0];
+    while let Some(entry) = work.pop() {
+        if functions.contains_key(&entry) {
+            continue;
+        }
+        let (function, callees) = decompiler.function(entry);
+        functions.insert(entry, function);
+        work.extend(callees);
+    }
+    let parameters = functions.iter().map(|(e, f)| (*e, f.parameters.len())).collect::<HashMap<usize, usize>>();
+    let mut text = String::new();
+    for function in functions.values() {
+        let main = function.entry == 0;
+        let mut blocks = function.blocks.clone();
+        for block in blocks.values_mut() {
+            if let Exit::Call(callee, back, delta) = block.exit {
+                let arguments = (0..parameters[&callee] as i64).map(|n| slot(main, delta, n + 2)).collect::<Vec<String>>();
+                block.statements.push(format!("{} = {}({});", slot(main, delta, 1), function_name(callee), arguments.join(", ")));
+                block.exit = Exit::Next(back);
+            }
+        }
+        thread_jumps(&mut blocks, function.entry);
+        let nodes = Structurer::new(&blocks, function.entry).structure(function.entry);
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        let parameters = function.parameters.iter().map(|p| format!("int {}", p)).collect::<Vec<String>>();
+        if main {
+            writeln!(text, "void main() {{").unwrap();
+        } else {
+            writeln!(text, "int {}({}) {{", function_name(function.entry), parameters.join(", ")).unwrap();
+        }
+        render(&nodes, 1, &mut text);
+        text.push_str("}\n");
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::decompile;
+    use crate::compile::compile;
+    use crate::rng::Rng;
+
+    #[test]
+    fn test_structure() {
+        let code = compile("
+            fn twice(x) { return x + x; }
+            let n = input();
+            while n != 0 {
+                if n < 0 {
+                    output(0 - n);
+                } else {
+                    output(twice(n));
+                }
+                n = input();
+            }
+        ").unwrap();
+        let text = decompile(&code);
+        let functions = text.lines().filter(|l| !l.starts_with(' ') && l.ends_with('{')).collect::<Vec<&str>>();
+        assert_eq!(vec!["void main() {", "int f_70(int local_2) {"], functions);
+        assert!(!text.contains("goto"), "{}", text);
+        assert!(text.contains("    while (1) {\n"), "{}", text);
+        assert!(text.contains("        if (mem_"), "{}", text);
+        assert!(text.contains("        } else {\n"), "{}", text);
+        assert!(text.contains(" = f_70(mem_"), "{}", text);
+        assert!(text.contains("    local_3 = local_2 + local_2;\n    local_1 = local_3;\n    return local_1;\n"), "{}", text);
+        assert!(text.contains("    halt();\n}\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_while() {
+        // Counts mem_20 down from the input, outputting every value.
+        let code = vec![3,20,1008,20,0,21,1005,21,19,4,20,1001,20,-1,20,1105,1,2,99,99,0,0];
+        assert_eq!("void main() {
+    mem_20 = input();
+    while (1) {
+        mem_21 = !mem_20;
+        if (mem_21) {
+            break;
+        }
+        output(mem_20);
+        mem_20 = mem_20 - 1;
+    }
+    halt();
+}
+", decompile(&code));
+    }
+
+    #[test]
+    fn test_while_condition() {
+        // The loop tests a word directly, so the test becomes the condition of the loop.
+        let code = vec![3,15,1006,15,14,4,15,1001,15,-1,15,1105,1,2,99,0];
+        assert_eq!("void main() {
+    mem_15 = input();
+    while (mem_15) {
+        output(mem_15);
+        mem_15 = mem_15 - 1;
+    }
+    halt();
+}
+", decompile(&code));
+    }
+
+    #[test]
+    fn test_goto() {
+        // Enters a loop at two places, which has no structured form.
+        let mut code = vec![3,30,1005,30,9,4,31,4,32,1001,31,1,31,1005,31,5,99];
+        code.resize(33, 0);
+        let text = decompile(&code);
+        assert!(text.contains("goto L_"), "{}", text);
+        assert!(text.contains("L_"), "{}", text);
+    }
+
+    #[test]
+    fn test_arbitrary_code() {
+        let mut rng = Rng::new(49);
+        for _ in 0..500 {
+            let len = rng.range(1, 40) as usize;
+            let code = (0..len).map(|_| match rng.below(4) {
+                0 => rng.range(-5, 30),
+                1 => [1, 2, 3, 4, 5, 6, 7, 8, 9, 99, 1101, 1105, 1106, 109, 204, 21101][rng.below(16)],
+                2 => rng.next_u64() as i64,
+                _ => rng.range(0, len as i64)
+            }).collect::<Vec<i64>>();
+            decompile(&code);
+        }
+    }
+}
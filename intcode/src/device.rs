@@ -0,0 +1,250 @@
+use std::any::Any;
+use std::collections::VecDeque;
+
+use crate::rng::Rng;
+
+/// Hardware behind a range of addresses. Offsets are relative to the start of the mapping.
+pub trait Device: Any + Send + DeviceClone {
+    fn read(&mut self, offset: usize) -> i64;
+    fn write(&mut self, offset: usize, value: i64);
+    // Called once after every executed instruction.
+    fn tick(&mut self) {}
+}
+
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Box<dyn Device> {
+        self.clone_box()
+    }
+}
+
+#[derive(Clone)]
+pub struct Mapping {
+    pub start: usize,
+    pub len: usize,
+    pub device: Box<dyn Device>
+}
+
+impl Mapping {
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.start && address < self.start + self.len
+    }
+}
+
+pub(crate) fn downcast_ref<T: Device>(device: &dyn Device) -> Option<&T> {
+    let any: &dyn Any = device;
+    any.downcast_ref::<T>()
+}
+
+pub(crate) fn validate(mappings: &[Mapping]) -> Result<(), String> {
+    for (i, mapping) in mappings.iter().enumerate() {
+        if mapping.len == 0 {
+            return Err(format!("empty device mapping at {}", mapping.start));
+        }
+        let end = mapping.start + mapping.len;
+        if let Some(other) = mappings[..i].iter().find(|m| m.start < end && mapping.start < m.start + m.len) {
+            return Err(format!("device at {} overlaps device at {}", mapping.start, other.start));
+        }
+    }
+    Ok(())
+}
+
+/// Grid of cells, one address per cell in row-major order.
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<i64>
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer { width, height, pixels: vec![0; width * height] }
+    }
+
+    /// None outside the grid.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<i64> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[y * self.width + x])
+    }
+
+    pub fn render(&self) -> String {
+        self.pixels.chunks(self.width)
+                   .map(|row| row.iter().map(|p| if *p == 0 { '.' } else { '#' }).collect::<String>())
+                   .collect::<Vec<String>>()
+                   .join("\n")
+    }
+}
+
+/// A mapping longer than the grid reads 0 past its end and ignores writes there.
+impl Device for Framebuffer {
+    fn read(&mut self, offset: usize) -> i64 {
+        self.pixels.get(offset).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, offset: usize, value: i64) {
+        if let Some(pixel) = self.pixels.get_mut(offset) {
+            *pixel = value;
+        }
+    }
+}
+
+/// Counts executed instructions. Writing sets the counter.
+#[derive(Clone, Default)]
+pub struct Clock {
+    pub ticks: i64
+}
+
+impl Device for Clock {
+    fn read(&mut self, _offset: usize) -> i64 {
+        self.ticks
+    }
+
+    fn write(&mut self, _offset: usize, value: i64) {
+        self.ticks = value;
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+    }
+}
+
+/// Every read yields the next non-negative pseudo random number. Writing reseeds.
+#[derive(Clone)]
+pub struct Random {
+    rng: Rng
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { rng: Rng::new(seed) }
+    }
+}
+
+impl Device for Random {
+    fn read(&mut self, _offset: usize) -> i64 {
+        (self.rng.next_u64() >> 1) as i64
+    }
+
+    fn write(&mut self, _offset: usize, value: i64) {
+        self.rng = Rng::new(value as u64);
+    }
+}
+
+/// ASCII terminal: writes append a character, reads take the next queued key or 0.
+#[derive(Clone, Default)]
+pub struct Console {
+    pub echo: bool,
+    output: String,
+    input: VecDeque<i64>
+}
+
+impl Console {
+    pub fn new(echo: bool) -> Console {
+        Console { echo, output: String::new(), input: VecDeque::new() }
+    }
+
+    pub fn type_str(&mut self, text: &str) {
+        self.input.extend(text.bytes().map(i64::from));
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+}
+
+impl Device for Console {
+    fn read(&mut self, _offset: usize) -> i64 {
+        self.input.pop_front().unwrap_or(0)
+    }
+
+    fn write(&mut self, _offset: usize, value: i64) {
+        let c = (value as u8) as char;
+        if self.echo {
+            print!("{}", c);
+        }
+        self.output.push(c);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Clock, Console, Framebuffer, Random};
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_framebuffer_and_console() {
+        // Sets the diagonal of a 3x3 framebuffer at 100 and prints "Hi" to a console at 200.
+        let code = vec![1101,0,1,100,1101,0,1,104,1101,0,1,108,1101,0,72,200,1101,0,105,200,99];
+        let mut program = Program::builder(code)
+            .device(100, 9, Framebuffer::new(3, 3))
+            .device(200, 1, Console::new(false))
+            .build()
+            .unwrap();
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        let framebuffer = program.device::<Framebuffer>(100).unwrap();
+        assert_eq!("#..\n.#.\n..#", framebuffer.render());
+        assert_eq!(Some(1), framebuffer.pixel(1, 1));
+        assert_eq!(None, framebuffer.pixel(3, 0));
+        assert_eq!("Hi", program.device::<Console>(200).unwrap().output());
+        assert!(program.device::<Clock>(100).is_none());
+        assert_eq!(0, program.memory()[200]);
+    }
+
+    #[test]
+    fn test_framebuffer_bounds() {
+        // Writes 5 past the 2x2 grid and outputs what it reads back.
+        let mut program = Program::builder(vec![1101,0,5,105,4,105,99])
+            .device(100, 10, Framebuffer::new(2, 2))
+            .build()
+            .unwrap();
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(Some(0), program.pop_output());
+        assert_eq!("..\n..", program.device::<Framebuffer>(100).unwrap().render());
+    }
+
+    #[test]
+    fn test_clock_and_random() {
+        // Outputs the clock twice and two random numbers.
+        let code = vec![4,50,4,50,4,51,4,51,99];
+        let run = || {
+            let mut program = Program::builder(code.clone())
+                .device(50, 1, Clock::default())
+                .device(51, 1, Random::new(9))
+                .build()
+                .unwrap();
+            program.process();
+            let mut output = Vec::new();
+            while let Some(v) = program.pop_output() {
+                output.push(v);
+            }
+            output
+        };
+        let first = run();
+        assert_eq!(vec![0, 1], first[..2].to_vec());
+        assert!(first[2] >= 0 && first[3] >= 0 && first[2] != first[3]);
+        assert_eq!(first, run());
+    }
+
+    #[test]
+    fn test_overlapping_devices() {
+        let result = Program::builder(vec![99])
+            .device(10, 5, Clock::default())
+            .device(14, 2, Clock::default())
+            .build();
+        assert!(result.is_err());
+    }
+}
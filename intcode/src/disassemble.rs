@@ -0,0 +1,69 @@
+use crate::program::{decode, IntCode, OpCode, ParameterMode};
+
+pub fn operand(mode: ParameterMode, raw: i64) -> String {
+    match mode {
+        ParameterMode::Position                 => format!("[{}]", raw),
+        ParameterMode::Immediate                => raw.to_string(),
+        ParameterMode::Relative if raw < 0      => format!("[rb{}]", raw),
+        ParameterMode::Relative                 => format!("[rb+{}]", raw)
+    }
+}
+
+/// Decodes the instruction at `ip` as `(text, length)`. Returns `None` if the word is not a
+/// valid instruction or its parameters run past the end of the code.
+pub fn disassemble(code: &IntCode, ip: usize) -> Option<(String, usize)> {
+    let instruction = decode(*code.get(ip)?);
+    if let OpCode::Err(_) = instruction.op_code {
+        return None;
+    }
+    let count = instruction.op_code.parameter_count();
+    if ip + count >= code.len() {
+        return None;
+    }
+    let operands = (0..count)
+        .map(|n| operand(instruction.parameter_mode(n), code[ip + n + 1]))
+        .collect::<Vec<String>>();
+    let text = if operands.is_empty() {
+        format!("{:?}", instruction.op_code)
+    } else {
+        format!("{:?} {}", instruction.op_code, operands.join(", "))
+    };
+    Some((text, count + 1))
+}
+
+/// Linear sweep over the whole image, one line per instruction or data word.
+pub fn listing(code: &IntCode) -> String {
+    let mut lines = Vec::new();
+    let mut ip = 0;
+    while ip < code.len() {
+        match disassemble(code, ip) {
+            Some((text, len)) => {
+                let words = code[ip..ip + len].iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",");
+                lines.push(format!("{:>6}  {:<24} {}", ip, words, text));
+                ip += len;
+            },
+            None => {
+                lines.push(format!("{:>6}  {:<24} data", ip, code[ip]));
+                ip += 1;
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disassemble, listing};
+
+    #[test]
+    fn test_disassemble() {
+        let code = vec![1002,4,3,4,33,109,-1,21101,1,2,3,99,5];
+        assert_eq!(Some((String::from("Mul [4], 3, [4]"), 4)), disassemble(&code, 0));
+        assert_eq!(Some((String::from("AdjustRelativeBase -1"), 2)), disassemble(&code, 5));
+        assert_eq!(Some((String::from("Add 1, 2, [rb+3]"), 4)), disassemble(&code, 7));
+        assert_eq!(Some((String::from("Halt"), 1)), disassemble(&code, 11));
+        assert_eq!(None, disassemble(&code, 4));
+        assert_eq!(None, disassemble(&code, 12));
+        assert_eq!(6, listing(&code).lines().count());
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use crate::program::{IntCode, Program, State};
+
+pub type Handler = Arc<dyn Fn(&mut Context) -> Effect + Send + Sync>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Role {
+    Read,
+    Write
+}
+
+/// What the interpreter does after a custom instruction ran.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Next,
+    Jump(usize),
+    WaitForInput,
+    Halt,
+    Error(String)
+}
+
+#[derive(Clone)]
+pub struct Extension {
+    pub op_code: i64,
+    pub name: String,
+    pub roles: Vec<Role>,
+    pub handler: Handler
+}
+
+/// View on the machine handed to a custom instruction's handler.
+pub struct Context<'a> {
+    pub(crate) program: &'a mut Program,
+    pub(crate) parameters: [usize; 3],
+    pub(crate) roles: &'a [Role],
+    pub index: usize,
+    pub relative_base: usize
+}
+
+impl<'a> Context<'a> {
+    /// Reads mapped devices like the built in instructions.
+    pub fn read(&mut self, parameter: usize) -> i64 {
+        self.program.read(self.parameters[parameter])
+    }
+
+    /// Goes to mapped devices and through write protection like the built in instructions.
+    /// Once a write was trapped, further writes of the instruction are ignored.
+    pub fn write(&mut self, parameter: usize, value: i64) {
+        assert_eq!(Role::Write, self.roles[parameter], "parameter {} is not writable", parameter);
+        if let State::Error(_) = self.program.state {
+            return;
+        }
+        self.program.write(self.parameters[parameter], value);
+    }
+
+    pub fn pop_input(&mut self) -> Option<i64> {
+        let value = self.program.input.pop_front();
+        if let Some(v) = value {
+            self.program.record(|step| step.inputs.push(v));
+        }
+        value
+    }
+
+    pub fn push_output(&mut self, value: i64) {
+        self.program.record(|step| step.outputs += 1);
+        self.program.output.push_back(value);
+    }
+
+    pub fn memory(&self) -> &IntCode {
+        self.program.memory()
+    }
+
+    // Writes through here bypass the undo log and write protection.
+    pub fn memory_mut(&mut self) -> &mut IntCode {
+        self.program.memory_mut()
+    }
+}
+
+pub(crate) fn validate(extensions: &[Extension]) -> Result<(), String> {
+    for (i, extension) in extensions.iter().enumerate() {
+        if extension.op_code <= 9 || extension.op_code > 98 {
+            return Err(format!("opcode {} of {} is reserved or out of range", extension.op_code, extension.name));
+        }
+        if extension.roles.len() > 3 {
+            return Err(format!("{} takes more than three parameters", extension.name));
+        }
+        if extensions[..i].iter().any(|e| e.op_code == extension.op_code) {
+            return Err(format!("opcode {} is registered twice", extension.op_code));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Effect, Role};
+    use crate::device::{Console, Framebuffer};
+    use crate::program::{Program, State};
+    use crate::protect::Policy;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_custom_opcodes() {
+        let printed = Arc::new(AtomicI64::new(0));
+        let seen = printed.clone();
+        // 42: debug print, 43: double into the second parameter, 98: halt with code
+        let mut program = Program::builder(vec![42,9,43,9,10,42,10,198,7,21,0])
+            .opcode(42, "print", &[Role::Read], move |ctx| {
+                seen.store(ctx.read(0), Ordering::SeqCst);
+                Effect::Next
+            })
+            .opcode(43, "double", &[Role::Read, Role::Write], |ctx| {
+                let value = ctx.read(0) * 2;
+                ctx.write(1, value);
+                Effect::Next
+            })
+            .opcode(98, "halt_with", &[Role::Read], |ctx| {
+                let code = ctx.read(0);
+                ctx.push_output(code);
+                Effect::Halt
+            })
+            .build()
+            .unwrap();
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(42, printed.load(Ordering::SeqCst));
+        assert_eq!(Some(7), program.pop_output());
+    }
+
+    #[test]
+    fn test_wait_for_input() {
+        let mut program = Program::builder(vec![50,5,4,5,99,0])
+            .opcode(50, "read_doubled", &[Role::Write], |ctx| match ctx.pop_input() {
+                Some(v) => {
+                    ctx.write(0, 2 * v);
+                    Effect::Next
+                },
+                None => Effect::WaitForInput
+            })
+            .build()
+            .unwrap();
+        program.process();
+        assert_eq!(State::WaitForInput, program.state);
+        program.push_input(21);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(Some(42), program.pop_output());
+    }
+
+    #[test]
+    fn test_invalid_registrations() {
+        let noop = |_: &mut super::Context| Effect::Next;
+        assert!(Program::builder(vec![99]).opcode(4, "out", &[], noop).build().is_err());
+        assert!(Program::builder(vec![99]).opcode(99, "halt", &[], noop).build().is_err());
+        assert!(Program::builder(vec![99]).opcode(10, "four", &[Role::Read; 4], noop).build().is_err());
+        assert!(Program::builder(vec![99]).opcode(10, "a", &[], noop).opcode(10, "b", &[], noop).build().is_err());
+        let mut program = Program::builder(vec![150,0,99]).opcode(50, "store", &[Role::Write], noop).build().unwrap();
+        program.process();
+        assert!(matches!(program.state, State::Error(_)));
+    }
+
+    #[test]
+    fn test_write_protection() {
+        // 50 stores 7 into its parameter, here the opcode of the Add it ran before.
+        let mut program = Program::builder(vec![1101,0,0,9,50,0,99,0,0,0])
+            .opcode(50, "store", &[Role::Write], |ctx| {
+                ctx.write(0, 7);
+                Effect::Next
+            })
+            .build()
+            .unwrap();
+        program.enable_write_protection(Policy::Trap, false);
+        program.process();
+        assert_eq!(State::Error(String::from("write to executed code at 0 by the instruction at 4")), program.state);
+        assert_eq!(4, program.index());
+        assert_eq!(1101, program.memory()[0]);
+    }
+
+    #[test]
+    fn test_devices() {
+        // 50 copies its first parameter to the second: a pixel to the console, then 72 to a pixel.
+        let copy = |ctx: &mut super::Context| {
+            let value = ctx.read(0);
+            ctx.write(1, value);
+            Effect::Next
+        };
+        let mut program = Program::builder(vec![1101,0,65,101,50,101,200,50,11,100,99,72])
+            .opcode(50, "copy", &[Role::Read, Role::Write], copy)
+            .device(100, 4, Framebuffer::new(2, 2))
+            .device(200, 1, Console::new(false))
+            .build()
+            .unwrap();
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!("A", program.device::<Console>(200).unwrap().output());
+        assert_eq!(Some(72), program.device::<Framebuffer>(100).unwrap().pixel(0, 0));
+        assert_eq!(0, program.memory()[101]);
+    }
+}
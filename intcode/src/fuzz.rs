@@ -0,0 +1,745 @@
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::program::{Program, State};
+pub use crate::rng::Rng;
+
+// The interpreters of the earlier days, compiled straight from their crates.
+#[allow(warnings, clippy::all)]
+mod day2 {
+    include!("../../day2/src/intcode.rs");
+}
+
+#[allow(warnings, clippy::all)]
+mod day5 {
+    include!("../../day5/src/intcode.rs");
+}
+
+#[allow(warnings, clippy::all)]
+mod day7 {
+    include!("../../day7/src/intcode.rs");
+}
+
+#[allow(warnings, clippy::all)]
+mod day9 {
+    include!("../../day9/src/intcode.rs");
+
+    pub fn memory(program: &Program) -> IntCode {
+        program.code.clone()
+    }
+}
+
+const DATA_CELLS: usize = 8;
+const STEP_LIMIT: usize = 100_000;
+
+/// Instruction sets in the order the puzzles introduced them, each one extending the previous.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dialect {
+    Day2,
+    Day5,
+    Day7,
+    Day9
+}
+
+const DIALECTS: [Dialect; 4] = [Dialect::Day2, Dialect::Day5, Dialect::Day7, Dialect::Day9];
+
+/// `Position` and `Relative` operands name one of the data cells behind the code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    Position(usize),
+    Immediate(i64),
+    Relative(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Arithmetic(i64, Operand, Operand, Operand),
+    In(Operand),
+    Out(Operand),
+    // Jumps only go forward, skipping the given number of top-level items.
+    Jump(i64, Operand, usize),
+    AdjustRelativeBase(i64),
+    // Runs its body a fixed number of times using a counter cell.
+    Loop(usize, Vec<Item>)
+}
+
+/// A generated program kept in structured form so it can be shrunk and reassembled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub dialect: Dialect,
+    pub items: Vec<Item>,
+    pub data: Vec<i64>,
+    pub input: Vec<i64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Halt,
+    WaitForInput,
+    Error(String),
+    Panic(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub status: Status,
+    pub output: Vec<i64>,
+    pub memory: Vec<i64>
+}
+
+pub struct Implementation {
+    pub name: &'static str,
+    pub dialect: Dialect,
+    pub word_bits: u32,
+    run: fn(&[i64], &[i64]) -> Outcome
+}
+
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub implementation: &'static str,
+    pub case: Case,
+    pub expected: Outcome,
+    pub actual: Outcome
+}
+
+pub struct Report {
+    pub cases: usize,
+    pub skipped: usize,
+    pub failures: usize,
+    pub divergences: Vec<Divergence>
+}
+
+fn mode(operand: &Operand) -> i64 {
+    match operand {
+        Operand::Position(_)    => 0,
+        Operand::Immediate(_)   => 1,
+        Operand::Relative(_)    => 2
+    }
+}
+
+fn item_size(item: &Item) -> usize {
+    match item {
+        Item::Arithmetic(..)            => 4,
+        Item::In(_) | Item::Out(_)      => 2,
+        Item::Jump(..)                  => 3,
+        Item::AdjustRelativeBase(_)     => 2,
+        Item::Loop(_, body)             => body.iter().map(item_size).sum::<usize>() + 7
+    }
+}
+
+fn count_loops(items: &[Item]) -> usize {
+    items.iter().filter(|i| matches!(i, Item::Loop(..))).count()
+}
+
+impl Case {
+    /// Lays out the code, a halt, the loop counters and then the data cells.
+    pub fn assemble(&self) -> Vec<i64> {
+        let mut addresses = Vec::with_capacity(self.items.len());
+        let mut address = 0;
+        for item in &self.items {
+            addresses.push(address as i64);
+            address += item_size(item);
+        }
+        let halt = address as i64;
+        let counters = halt + 1;
+        let data_start = counters + count_loops(&self.items) as i64;
+        let parameter = |operand: &Operand| match operand {
+            Operand::Position(i)    => data_start + *i as i64,
+            Operand::Immediate(v)   => *v,
+            Operand::Relative(i)    => data_start + *i as i64
+        };
+        let mut code = Vec::new();
+        let mut loop_index = 0;
+        for (index, item) in self.items.iter().enumerate() {
+            match item {
+                Item::Jump(op, condition, skip) => {
+                    let target = addresses.get(index + 1 + skip).copied().unwrap_or(halt);
+                    code.extend(vec![op + 100 * mode(condition) + 1000, parameter(condition), target]);
+                },
+                Item::Loop(_, body) => {
+                    let start = code.len() as i64;
+                    for inner in body {
+                        emit(&mut code, inner, &parameter);
+                    }
+                    let counter = counters + loop_index;
+                    code.extend(vec![1001, counter, -1, counter, 1005, counter, start]);
+                    loop_index += 1;
+                },
+                _ => emit(&mut code, item, &parameter)
+            }
+        }
+        code.push(99);
+        for item in &self.items {
+            if let Item::Loop(iterations, _) = item {
+                code.push(*iterations as i64);
+            }
+        }
+        let slack = self.items.iter().map(|i| match i {
+            Item::AdjustRelativeBase(v) => *v as usize,
+            _                           => 0
+        }).sum::<usize>();
+        let mut data = self.data.clone();
+        if self.dialect == Dialect::Day9 {
+            // Trailing zero cells are left out so the interpreter has to grow its memory.
+            while data.last() == Some(&0) {
+                data.pop();
+            }
+        } else {
+            data.resize(DATA_CELLS + slack, 0);
+        }
+        code.extend(data);
+        code
+    }
+}
+
+fn emit<F: Fn(&Operand) -> i64>(code: &mut Vec<i64>, item: &Item, parameter: &F) {
+    match item {
+        Item::Arithmetic(op, a, b, c) => {
+            code.extend(vec![op + 100 * mode(a) + 1000 * mode(b) + 10000 * mode(c), parameter(a), parameter(b), parameter(c)]);
+        },
+        Item::In(a) => code.extend(vec![3 + 100 * mode(a), parameter(a)]),
+        Item::Out(a) => code.extend(vec![4 + 100 * mode(a), parameter(a)]),
+        Item::AdjustRelativeBase(v) => code.extend(vec![109, *v]),
+        Item::Jump(..) | Item::Loop(..) => unreachable!("jumps and loops are only emitted at top level")
+    }
+}
+
+fn read_operand(rng: &mut Rng, dialect: Dialect) -> Operand {
+    if dialect >= Dialect::Day9 && rng.chance(25) {
+        Operand::Relative(rng.below(DATA_CELLS))
+    } else if dialect >= Dialect::Day5 && rng.chance(40) {
+        Operand::Immediate(rng.range(-9, 9))
+    } else {
+        Operand::Position(rng.below(DATA_CELLS))
+    }
+}
+
+fn write_operand(rng: &mut Rng, dialect: Dialect) -> Operand {
+    if dialect >= Dialect::Day9 && rng.chance(25) {
+        Operand::Relative(rng.below(DATA_CELLS))
+    } else {
+        Operand::Position(rng.below(DATA_CELLS))
+    }
+}
+
+fn straight_item(rng: &mut Rng, dialect: Dialect) -> Item {
+    if dialect >= Dialect::Day7 && rng.chance(25) {
+        if rng.chance(50) {
+            Item::In(write_operand(rng, dialect))
+        } else {
+            Item::Out(read_operand(rng, dialect))
+        }
+    } else {
+        let ops: &[i64] = if dialect >= Dialect::Day5 { &[1, 2, 7, 8] } else { &[1, 2] };
+        let op = ops[rng.below(ops.len())];
+        Item::Arithmetic(op, read_operand(rng, dialect), read_operand(rng, dialect), write_operand(rng, dialect))
+    }
+}
+
+/// Generates a random program that uses only the instructions of `dialect` and always terminates.
+pub fn generate(rng: &mut Rng, dialect: Dialect) -> Case {
+    let mut items = Vec::new();
+    for _ in 0..rng.range(1, 12) {
+        let roll = rng.below(100);
+        let item = if dialect >= Dialect::Day5 && roll < 15 {
+            let op = if rng.chance(50) { 5 } else { 6 };
+            Item::Jump(op, read_operand(rng, dialect), rng.below(3))
+        } else if dialect >= Dialect::Day5 && roll < 25 {
+            let body = (0..rng.range(1, 3)).map(|_| straight_item(rng, dialect)).collect();
+            Item::Loop(rng.range(1, 3) as usize, body)
+        } else if dialect >= Dialect::Day9 && roll < 35 {
+            Item::AdjustRelativeBase(rng.range(0, 5))
+        } else {
+            straight_item(rng, dialect)
+        };
+        items.push(item);
+    }
+    let data = (0..DATA_CELLS).map(|_| rng.range(-9, 9)).collect();
+    let input = if dialect >= Dialect::Day7 {
+        (0..rng.range(0, 6)).map(|_| rng.range(-9, 9)).collect()
+    } else {
+        Vec::new()
+    };
+    Case { dialect, items, data, input }
+}
+
+fn trimmed(mut memory: Vec<i64>) -> Vec<i64> {
+    while memory.last() == Some(&0) {
+        memory.pop();
+    }
+    memory
+}
+
+/// Independent model of the full intcode machine, decoding numerically and computing
+/// in 128 bits. Also returns the largest magnitude any word reached.
+pub fn reference(code: &[i64], input: &[i64]) -> (Outcome, i128) {
+    let mut memory = code.iter().map(|&v| i128::from(v)).collect::<Vec<i128>>();
+    let mut input = input.iter();
+    let mut output = Vec::new();
+    let mut largest = memory.iter().map(|v| v.abs()).max().unwrap_or(0);
+    let mut ip: i128 = 0;
+    let mut base: i128 = 0;
+    let mut status = Status::Error(String::from("step limit"));
+    for _ in 0..STEP_LIMIT {
+        if ip < 0 {
+            status = Status::Error(String::from("negative address"));
+            break;
+        }
+        let word = memory.get(ip as usize).copied().unwrap_or(0);
+        let op = word % 100;
+        let mut addresses = [0i128; 3];
+        for (n, address) in addresses.iter_mut().enumerate() {
+            let raw = memory.get(ip as usize + n + 1).copied().unwrap_or(0);
+            *address = match word / [100, 1000, 10000][n] % 10 {
+                0   => raw,
+                1   => ip + n as i128 + 1,
+                _   => base + raw
+            };
+        }
+        let arity = match op {
+            1 | 2 | 7 | 8   => 3,
+            3 | 4 | 9       => 1,
+            5 | 6           => 2,
+            _               => 0
+        };
+        if addresses[..arity].iter().any(|a| *a < 0) {
+            status = Status::Error(String::from("negative address"));
+            break;
+        }
+        let top = addresses[..arity].iter().copied().max().unwrap_or(0) as usize;
+        if top >= memory.len() {
+            memory.resize(top + 1, 0);
+        }
+        let [a, b, c] = addresses.map(|a| a.max(0) as usize);
+        match op {
+            1 | 2 | 7 | 8 => {
+                let (x, y) = (memory[a], memory[b]);
+                let value = match op {
+                    1   => x.checked_add(y),
+                    2   => x.checked_mul(y),
+                    7   => Some((x < y) as i128),
+                    _   => Some((x == y) as i128)
+                };
+                match value {
+                    Some(v) => {
+                        largest = largest.max(v.abs());
+                        memory[c] = v;
+                    },
+                    None => {
+                        status = Status::Error(String::from("overflow"));
+                        largest = i128::MAX;
+                        break;
+                    }
+                }
+                ip += 4;
+            },
+            3 => match input.next() {
+                Some(v) => {
+                    memory[a] = i128::from(*v);
+                    ip += 2;
+                },
+                None => {
+                    status = Status::WaitForInput;
+                    break;
+                }
+            },
+            4 => {
+                output.push(memory[a] as i64);
+                ip += 2;
+            },
+            5 | 6 => {
+                if (memory[a] != 0) == (op == 5) {
+                    ip = memory[b];
+                } else {
+                    ip += 3;
+                }
+            },
+            9 => {
+                base += memory[a];
+                ip += 2;
+            },
+            99 => {
+                status = Status::Halt;
+                break;
+            },
+            v => {
+                status = Status::Error(v.to_string());
+                break;
+            }
+        }
+    }
+    let memory = memory.into_iter().map(|v| v as i64).collect();
+    (Outcome { status, output, memory: trimmed(memory) }, largest)
+}
+
+fn run_day2(code: &[i64], _input: &[i64]) -> Outcome {
+    let memory = day2::process(code.iter().map(|&v| v as i32).collect());
+    Outcome {
+        status: Status::Halt,
+        output: Vec::new(),
+        memory: trimmed(memory.into_iter().map(i64::from).collect())
+    }
+}
+
+fn run_day5(code: &[i64], _input: &[i64]) -> Outcome {
+    let memory = day5::process(code.iter().map(|&v| v as i32).collect());
+    Outcome {
+        status: Status::Halt,
+        output: Vec::new(),
+        memory: trimmed(memory.into_iter().map(i64::from).collect())
+    }
+}
+
+fn run_day7(code: &[i64], input: &[i64]) -> Outcome {
+    let mut program = day7::Program::new(code.iter().map(|&v| v as i32).collect());
+    for value in input {
+        program.push_input(*value as i32);
+    }
+    program.process();
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(i64::from(value));
+    }
+    let status = match program.state {
+        day7::State::Halt           => Status::Halt,
+        day7::State::WaitForInput   => Status::WaitForInput,
+        day7::State::Error(v)       => Status::Error(v.to_string()),
+        day7::State::Idle           => Status::Error(String::from("idle"))
+    };
+    let memory = program.intcode_to_string().split(',').map(|s| s.parse::<i64>().unwrap()).collect();
+    Outcome { status, output, memory: trimmed(memory) }
+}
+
+fn run_day9(code: &[i64], input: &[i64]) -> Outcome {
+    let mut program = day9::Program::new(code.to_vec(), false);
+    for value in input {
+        program.push_input(*value);
+    }
+    program.process();
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(value);
+    }
+    let status = match &program.state {
+        day9::State::Halt           => Status::Halt,
+        day9::State::WaitForInput   => Status::WaitForInput,
+        day9::State::Error(v)       => Status::Error(v.clone()),
+        day9::State::Idle           => Status::Error(String::from("idle"))
+    };
+    Outcome { status, output, memory: trimmed(day9::memory(&program)) }
+}
+
+fn run_program(code: &[i64], input: &[i64]) -> Outcome {
+    let mut program = Program::new(code.to_vec(), false);
+    for value in input {
+        program.push_input(*value);
+    }
+    program.process();
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(value);
+    }
+    let status = match &program.state {
+        State::Halt         => Status::Halt,
+        State::WaitForInput => Status::WaitForInput,
+        State::Error(v)     => Status::Error(v.clone()),
+        State::Idle         => Status::Error(String::from("idle"))
+    };
+    Outcome { status, output, memory: trimmed(program.memory().clone()) }
+}
+
+pub fn implementations() -> Vec<Implementation> {
+    vec![
+        Implementation { name: "day2", dialect: Dialect::Day2, word_bits: 32, run: run_day2 },
+        Implementation { name: "day5", dialect: Dialect::Day5, word_bits: 32, run: run_day5 },
+        Implementation { name: "day7", dialect: Dialect::Day7, word_bits: 32, run: run_day7 },
+        Implementation { name: "day9", dialect: Dialect::Day9, word_bits: 64, run: run_day9 },
+        Implementation { name: "program", dialect: Dialect::Day9, word_bits: 64, run: run_program }]
+}
+
+impl Implementation {
+    pub fn execute(&self, code: &[i64], input: &[i64]) -> Outcome {
+        match panic::catch_unwind(AssertUnwindSafe(|| (self.run)(code, input))) {
+            Ok(outcome) => outcome,
+            Err(e) => Outcome { status: Status::Panic(panic_message(e)), output: Vec::new(), memory: Vec::new() }
+        }
+    }
+}
+
+// The message a caught panic was raised with.
+pub(crate) fn panic_message(e: Box<dyn Any + Send>) -> String {
+    match e.downcast_ref::<&str>() {
+        Some(s) => s.to_string(),
+        None    => e.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+}
+
+fn agrees(expected: &Outcome, actual: &Outcome) -> bool {
+    let status = match (&expected.status, &actual.status) {
+        (Status::Error(_), Status::Error(_))    => true,
+        (e, a)                                  => e == a
+    };
+    status && expected.output == actual.output && expected.memory == actual.memory
+}
+
+/// Runs `case` through every implementation that understands its dialect and compares each
+/// outcome with the reference model. Returns `None` when the case is not well-formed.
+pub fn check(case: &Case, implementations: &[Implementation]) -> Option<Vec<Divergence>> {
+    let code = case.assemble();
+    let (expected, largest) = reference(&code, &case.input);
+    if let Status::Error(_) = expected.status {
+        return None;
+    }
+    let mut divergences = Vec::new();
+    for implementation in implementations {
+        if implementation.dialect < case.dialect || largest >= 1 << (implementation.word_bits - 1) {
+            continue;
+        }
+        let actual = implementation.execute(&code, &case.input);
+        if !agrees(&expected, &actual) {
+            divergences.push(Divergence {
+                implementation: implementation.name,
+                case: case.clone(),
+                expected: expected.clone(),
+                actual
+            });
+        }
+    }
+    Some(divergences)
+}
+
+fn shrink_operand(operand: &Operand) -> Option<Operand> {
+    match operand {
+        Operand::Immediate(v) if *v != 0    => Some(Operand::Immediate(0)),
+        Operand::Relative(i)                => Some(Operand::Position(*i)),
+        _                                   => None
+    }
+}
+
+fn shrink_item(item: &Item) -> Vec<Item> {
+    let mut candidates = Vec::new();
+    match item {
+        Item::Arithmetic(op, a, b, c) => {
+            if let Some(a) = shrink_operand(a) {
+                candidates.push(Item::Arithmetic(*op, a, *b, *c));
+            }
+            if let Some(b) = shrink_operand(b) {
+                candidates.push(Item::Arithmetic(*op, *a, b, *c));
+            }
+        },
+        Item::Out(a) => {
+            if let Some(a) = shrink_operand(a) {
+                candidates.push(Item::Out(a));
+            }
+        },
+        Item::Jump(op, condition, skip) => {
+            if *skip > 0 {
+                candidates.push(Item::Jump(*op, *condition, 0));
+            }
+            if let Some(condition) = shrink_operand(condition) {
+                candidates.push(Item::Jump(*op, condition, *skip));
+            }
+        },
+        Item::AdjustRelativeBase(v) if *v > 0 => candidates.push(Item::AdjustRelativeBase(0)),
+        Item::Loop(iterations, body) => {
+            if *iterations > 1 {
+                candidates.push(Item::Loop(1, body.clone()));
+            }
+            for i in 0..body.len() {
+                if body.len() > 1 {
+                    let mut smaller = body.clone();
+                    smaller.remove(i);
+                    candidates.push(Item::Loop(*iterations, smaller));
+                }
+                for inner in shrink_item(&body[i]) {
+                    let mut simpler = body.clone();
+                    simpler[i] = inner;
+                    candidates.push(Item::Loop(*iterations, simpler));
+                }
+            }
+        },
+        _ => ()
+    }
+    candidates
+}
+
+fn shrinks(case: &Case) -> Vec<Case> {
+    let mut candidates = Vec::new();
+    for i in 0..case.items.len() {
+        let mut smaller = case.clone();
+        smaller.items.remove(i);
+        candidates.push(smaller);
+        if let Item::Loop(_, body) = &case.items[i] {
+            let mut flat = case.clone();
+            flat.items.splice(i..=i, body.iter().cloned());
+            candidates.push(flat);
+        }
+    }
+    if !case.input.is_empty() {
+        let mut shorter = case.clone();
+        shorter.input.pop();
+        candidates.push(shorter);
+    }
+    for i in 0..case.data.len() {
+        if case.data[i] != 0 {
+            let mut zeroed = case.clone();
+            zeroed.data[i] = 0;
+            candidates.push(zeroed);
+        }
+    }
+    for i in 0..case.input.len() {
+        if case.input[i] != 0 {
+            let mut zeroed = case.clone();
+            zeroed.input[i] = 0;
+            candidates.push(zeroed);
+        }
+    }
+    for i in 0..case.items.len() {
+        for item in shrink_item(&case.items[i]) {
+            let mut simpler = case.clone();
+            simpler.items[i] = item;
+            candidates.push(simpler);
+        }
+    }
+    candidates
+}
+
+/// Greedily shrinks the case of `divergence` while the same implementation keeps diverging.
+pub fn minimise(divergence: Divergence, implementations: &[Implementation]) -> Divergence {
+    let implementation = match implementations.iter().find(|i| i.name == divergence.implementation) {
+        Some(i) => i,
+        None    => return divergence
+    };
+    let mut current = divergence;
+    'search: loop {
+        for candidate in shrinks(&current.case) {
+            if let Some(mut found) = check(&candidate, std::slice::from_ref(implementation)) {
+                if let Some(smaller) = found.pop() {
+                    current = smaller;
+                    continue 'search;
+                }
+            }
+        }
+        return current;
+    }
+}
+
+/// Generates `iterations` programs across all dialects and keeps the first minimised
+/// divergence found for every implementation.
+pub fn fuzz(seed: u64, iterations: usize) -> Report {
+    let implementations = implementations();
+    let mut rng = Rng::new(seed);
+    let mut report = Report { cases: 0, skipped: 0, failures: 0, divergences: Vec::new() };
+    for _ in 0..iterations {
+        let dialect = DIALECTS[rng.below(DIALECTS.len())];
+        let case = generate(&mut rng, dialect);
+        report.cases += 1;
+        let divergences = match check(&case, &implementations) {
+            Some(d) => d,
+            None    => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        for divergence in divergences {
+            report.failures += 1;
+            if report.divergences.iter().all(|d| d.implementation != divergence.implementation) {
+                report.divergences.push(minimise(divergence, &implementations));
+            }
+        }
+    }
+    report
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.case.assemble().iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",");
+        writeln!(f, "{} diverges on {:?} program:", self.implementation, self.case.dialect)?;
+        writeln!(f, "\tcode:\t\t{}", code)?;
+        writeln!(f, "\tinput:\t\t{:?}", self.case.input)?;
+        writeln!(f, "\texpected:\t{:?} {:?} {:?}", self.expected.status, self.expected.output, self.expected.memory)?;
+        write!(f, "\tactual:\t\t{:?} {:?} {:?}", self.actual.status, self.actual.output, self.actual.memory)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, generate, implementations, minimise, reference, Case, Dialect, Item, Operand, Rng, Status};
+
+    #[test]
+    fn test_reference() {
+        let (outcome, _) = reference(&[1,9,10,3,2,3,11,0,99,30,40,50], &[]);
+        assert_eq!(Status::Halt, outcome.status);
+        assert_eq!(vec![3500,9,10,70,2,3,11,0,99,30,40,50], outcome.memory);
+        let (outcome, _) = reference(&[109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99], &[]);
+        assert_eq!(vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99], outcome.output);
+        let (outcome, _) = reference(&[3,0,4,0,3,0], &[7]);
+        assert_eq!(Status::WaitForInput, outcome.status);
+        assert_eq!(vec![7], outcome.output);
+    }
+
+    #[test]
+    fn test_generated_programs_terminate() {
+        let mut rng = Rng::new(2019);
+        for dialect in &super::DIALECTS {
+            for _ in 0..200 {
+                let case = generate(&mut rng, *dialect);
+                let (outcome, _) = reference(&case.assemble(), &case.input);
+                assert_ne!(Status::Error(String::from("step limit")), outcome.status);
+            }
+        }
+    }
+
+    #[test]
+    fn test_day2_and_day5_agree() {
+        let implementations = implementations();
+        let mut rng = Rng::new(7);
+        for dialect in &[Dialect::Day2, Dialect::Day5] {
+            for _ in 0..200 {
+                let case = generate(&mut rng, *dialect);
+                if let Some(divergences) = check(&case, &implementations) {
+                    assert!(divergences.is_empty(), "{}", divergences[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_growth_divergence() {
+        // Writes to the first address past the image, which day9 forgets to allocate.
+        let case = Case {
+            dialect: Dialect::Day9,
+            items: vec![Item::Arithmetic(1, Operand::Immediate(1), Operand::Immediate(2), Operand::Position(0))],
+            data: vec![0; 8],
+            input: Vec::new()
+        };
+        let divergences = check(&case, &implementations()).unwrap();
+        assert_eq!(1, divergences.len());
+        assert_eq!("day9", divergences[0].implementation);
+        assert!(matches!(divergences[0].actual.status, Status::Panic(_)));
+    }
+
+    #[test]
+    fn test_minimise() {
+        // day7 pops its outputs from the back, so two different outputs are enough to diverge.
+        let case = Case {
+            dialect: Dialect::Day7,
+            items: vec![
+                Item::Arithmetic(1, Operand::Position(0), Operand::Immediate(3), Operand::Position(1)),
+                Item::Out(Operand::Immediate(4)),
+                Item::In(Operand::Position(2)),
+                Item::Out(Operand::Position(2)),
+                Item::Loop(2, vec![Item::Out(Operand::Position(3))])],
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            input: vec![5, 6]
+        };
+        let implementations = implementations();
+        let divergence = check(&case, &implementations).unwrap().pop().unwrap();
+        assert_eq!("day7", divergence.implementation);
+        let minimal = minimise(divergence, &implementations);
+        assert_eq!(2, minimal.case.items.len());
+        assert!(minimal.case.items.iter().all(|i| matches!(i, Item::Out(_))));
+        assert!(minimal.case.input.is_empty());
+    }
+}
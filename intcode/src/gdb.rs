@@ -0,0 +1,429 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::fuzz::panic_message;
+use crate::program::{Program, State};
+
+// Instructions run between two checks for an interrupt while continuing.
+const SLICE: usize = 4096;
+const PACKET_SIZE: usize = 4096;
+const INTERRUPT: u8 = 0x03;
+// Words a debugger may write beyond the end of memory, growing it.
+const WRITE_MARGIN: usize = 1 << 16;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.intcode.core">
+    <reg name="ip" bitsize="64" type="code_ptr" regnum="0"/>
+    <reg name="rb" bitsize="64" type="data_ptr" regnum="1"/>
+  </feature>
+</target>
+"#;
+
+pub fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b))
+}
+
+/// `data` framed as `$data#cs`.
+pub fn frame(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn register(value: u64) -> String {
+    hex(&value.to_le_bytes())
+}
+
+fn parse_register(text: &str) -> Option<u64> {
+    let bytes = unhex(text)?;
+    if bytes.len() != 8 {
+        return None;
+    }
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes);
+    Some(u64::from_le_bytes(word))
+}
+
+// "addr,len" in hex.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((address, len))
+}
+
+/// Console output shown by the debugger.
+fn console(text: &str) -> String {
+    format!("O{}", hex(text.as_bytes()))
+}
+
+/// GDB remote serial protocol server around a `Program`. Memory is byte addressed with
+/// every word taking 8 little endian bytes, the registers `ip` and `rb` hold byte addresses
+/// too, so `ip` is 8 times the instruction index. Inputs are
+/// pushed with `monitor input <values>`, outputs are printed on the debugger console.
+pub struct Stub {
+    pub program: Program,
+    breakpoints: BTreeSet<usize>,
+    no_ack: bool
+}
+
+impl Stub {
+    pub fn new(program: Program) -> Stub {
+        Stub { program, breakpoints: BTreeSet::new(), no_ack: false }
+    }
+
+    fn read_byte(&self, address: usize) -> u8 {
+        let word = self.program.memory().get(address / 8).copied().unwrap_or(0);
+        (word as u64 >> (8 * (address % 8))) as u8
+    }
+
+    fn writable(&self, address: usize, len: usize) -> bool {
+        match address.checked_add(len) {
+            Some(end) => end / 8 <= self.program.memory().len() + WRITE_MARGIN,
+            None => false
+        }
+    }
+
+    fn write_byte(&mut self, address: usize, value: u8) {
+        let word = self.program.memory().get(address / 8).copied().unwrap_or(0) as u64;
+        let shift = 8 * (address % 8);
+        let word = (word & !(0xff << shift)) | ((value as u64) << shift);
+        self.program.set_memory(address / 8, word as i64);
+    }
+
+    fn registers(&self) -> (u64, u64) {
+        ((self.program.index() as u64).wrapping_mul(8), (self.program.relative_base() as u64).wrapping_mul(8))
+    }
+
+    // Refuses an `ip` outside of memory.
+    fn set_registers(&mut self, ip: u64, rb: u64) -> bool {
+        if ip / 8 >= self.program.memory().len() as u64 {
+            return false;
+        }
+        self.program.set_registers((ip / 8) as usize, (rb / 8) as usize);
+        true
+    }
+
+    // Steps the program, turning a panic of the interpreter into an error of the program.
+    fn step(&mut self) {
+        let program = &mut self.program;
+        if let Err(e) = panic::catch_unwind(AssertUnwindSafe(|| program.step())) {
+            program.state = State::Error(format!("panic: {}", panic_message(e)));
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        match self.program.state {
+            State::Halt         => String::from("W00"),
+            State::Error(_)     => String::from("X04"),
+            _                   => String::from("S05")
+        }
+    }
+
+    fn drain_output(&mut self, replies: &mut Vec<String>) {
+        while let Some(value) = self.program.pop_output() {
+            replies.push(console(&format!("Out: {}\n", value)));
+        }
+        match self.program.state {
+            State::WaitForInput     => replies.push(console("waiting for input, use `monitor input <values>`\n")),
+            State::Error(ref e)     => replies.push(console(&format!("Error: {:?}\n", e))),
+            _                       => ()
+        }
+    }
+
+    fn resume(&mut self, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        let mut replies = Vec::new();
+        // Leave a breakpoint we are sitting on before checking for breakpoints again.
+        self.step();
+        'run: while self.program.state == State::Idle {
+            for _ in 0..SLICE {
+                if self.program.state != State::Idle || self.breakpoints.contains(&self.program.index()) {
+                    break 'run;
+                }
+                self.step();
+            }
+            if interrupted() {
+                break;
+            }
+        }
+        self.drain_output(&mut replies);
+        replies.push(self.stop_reply());
+        replies
+    }
+
+    fn monitor(&mut self, command: &str) -> Vec<String> {
+        let words = command.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            ["input", values @ ..] => {
+                match values.iter().map(|v| v.parse::<i64>()).collect::<Result<Vec<i64>, _>>() {
+                    Ok(values) => {
+                        for value in values {
+                            self.program.push_input(value);
+                        }
+                        vec![String::from("OK")]
+                    },
+                    Err(_) => vec![console("inputs must be integers\n"), String::from("OK")]
+                }
+            },
+            ["backtrace"] => {
+                self.program.enable_call_stack();
+                let lines = self.program.backtrace().unwrap_or_default();
+                vec![console(&(lines.join("\n") + "\n")), String::from("OK")]
+            },
+            _ => vec![console("commands: input <values>, backtrace\n"), String::from("OK")]
+        }
+    }
+
+    /// Replies to one packet. Console output packets come first, the last entry is the
+    /// response itself. No replies means the session ends.
+    pub fn handle(&mut self, packet: &str) -> Vec<String> {
+        self.respond(packet, &mut || false)
+    }
+
+    fn respond(&mut self, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> Vec<String> {
+        let reply = |s: &str| vec![String::from(s)];
+        let (command, args) = packet.split_at(packet.char_indices().nth(1).map(|(i, _)| i).unwrap_or(packet.len()));
+        match command {
+            "?" => vec![self.stop_reply()],
+            "g" => {
+                let (ip, rb) = self.registers();
+                vec![register(ip) + &register(rb)]
+            },
+            "G" => match (args.get(..16).and_then(parse_register), args.get(16..32).and_then(parse_register)) {
+                (Some(ip), Some(rb)) if self.set_registers(ip, rb) => reply("OK"),
+                _ => reply("E01")
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(0) => vec![register(self.registers().0)],
+                Ok(1) => vec![register(self.registers().1)],
+                _ => reply("E01")
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let number = parts.next().and_then(|n| usize::from_str_radix(n, 16).ok());
+                let value = parts.next().and_then(parse_register);
+                match (number, value) {
+                    (Some(0), Some(v)) if self.set_registers(v, self.registers().1) => reply("OK"),
+                    (Some(1), Some(v)) if self.set_registers(self.registers().0, v) => reply("OK"),
+                    _ => reply("E01")
+                }
+            },
+            "m" => match parse_range(args).and_then(|(a, len)| Some((a, a.checked_add(len.min(PACKET_SIZE / 2))?))) {
+                Some((start, end)) => {
+                    let bytes = (start..end).map(|a| self.read_byte(a)).collect::<Vec<u8>>();
+                    vec![hex(&bytes)]
+                },
+                None => reply("E01")
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                match (parts.next().and_then(parse_range), parts.next().and_then(unhex)) {
+                    (Some((address, len)), Some(bytes)) if bytes.len() == len && self.writable(address, len) => {
+                        for (n, byte) in bytes.into_iter().enumerate() {
+                            self.write_byte(address + n, byte);
+                        }
+                        reply("OK")
+                    },
+                    _ => reply("E01")
+                }
+            },
+            "s" => {
+                let mut replies = Vec::new();
+                self.step();
+                self.drain_output(&mut replies);
+                replies.push(self.stop_reply());
+                replies
+            },
+            "c" => self.resume(interrupted),
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let address = parts.next().and_then(|a| usize::from_str_radix(a, 16).ok());
+                match (kind, address) {
+                    (Some("0"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address / 8);
+                        } else {
+                            self.breakpoints.remove(&(address / 8));
+                        }
+                        reply("OK")
+                    },
+                    _ => reply("")
+                }
+            },
+            "H" => reply("OK"),
+            "k" => Vec::new(),
+            "D" => reply("OK"),
+            _ => self.query(packet)
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> Vec<String> {
+        if packet.starts_with("qSupported") {
+            return vec![format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE)];
+        }
+        if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            return vec![String::from("OK")];
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_range(range) {
+                Some((offset, len)) => {
+                    let start = offset.min(TARGET_XML.len());
+                    let end = (offset + len).min(TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    vec![format!("{}{}", more, &TARGET_XML[start..end])]
+                },
+                None => vec![String::from("E01")]
+            };
+        }
+        if let Some(command) = packet.strip_prefix("qRcmd,") {
+            return match unhex(command).and_then(|b| String::from_utf8(b).ok()) {
+                Some(command) => self.monitor(&command),
+                None => vec![String::from("E01")]
+            };
+        }
+        match packet {
+            "qAttached"     => vec![String::from("1")],
+            "qC"            => vec![String::from("QC1")],
+            "qfThreadInfo"  => vec![String::from("m1")],
+            "qsThreadInfo"  => vec![String::from("l")],
+            _               => vec![String::new()]
+        }
+    }
+
+    /// Serves one debugger connection until it detaches or kills the target.
+    pub fn serve<R, W>(&mut self, reader: R, mut writer: W) -> io::Result<()>
+        where R: Read + Send + 'static, W: Write {
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::BufReader::new(reader).bytes() {
+                match byte {
+                    Ok(b) if sender.send(b).is_ok() => (),
+                    _ => break
+                }
+            }
+        });
+        // Bytes that arrived while running are kept in `backlog` and read before new ones.
+        let mut backlog = VecDeque::new();
+        let next = |backlog: &mut VecDeque<u8>| backlog.pop_front().or_else(|| received.recv().ok());
+        loop {
+            let byte = match next(&mut backlog) {
+                Some(b) => b,
+                None => return Ok(())
+            };
+            if byte == INTERRUPT {
+                writer.write_all(frame("S02").as_bytes())?;
+                writer.flush()?;
+                continue;
+            }
+            if byte != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            loop {
+                match next(&mut backlog) {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(())
+                }
+            }
+            let sum = [next(&mut backlog), next(&mut backlog)];
+            let data = String::from_utf8_lossy(&data).to_string();
+            let valid = match sum {
+                [Some(a), Some(b)] => u8::from_str_radix(&String::from_utf8_lossy(&[a, b]), 16) == Ok(checksum(&data)),
+                _ => return Ok(())
+            };
+            if !self.no_ack {
+                writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                writer.flush()?;
+                continue;
+            }
+            let mut interrupted = || loop {
+                match received.try_recv() {
+                    Ok(INTERRUPT) => return true,
+                    Ok(b) => backlog.push_back(b),
+                    Err(_) => return false
+                }
+            };
+            let replies = self.respond(&data, &mut interrupted);
+            for reply in &replies {
+                writer.write_all(frame(reply).as_bytes())?;
+            }
+            writer.flush()?;
+            if replies.is_empty() || data == "D" {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{frame, hex, unhex, Stub};
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_framing() {
+        assert_eq!("$OK#9a", frame("OK"));
+        assert_eq!("$#00", frame(""));
+        assert_eq!(Some(b"input 5".to_vec()), unhex(&hex(b"input 5")));
+        assert_eq!(None, unhex("abc"));
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut stub = Stub::new(Program::new(vec![1101,2,3,7,109,-1,99,0], false));
+        assert_eq!(vec!["00000000000000000000000000000000"], stub.handle("g"));
+        assert_eq!(vec!["4d04000000000000"], stub.handle("m0,8"));
+        assert_eq!(vec!["OK"], stub.handle("M38,8:2a00000000000000"));
+        assert_eq!(42, stub.program.memory()[7]);
+        assert_eq!(vec!["S05"], stub.handle("s"));
+        assert_eq!(vec!["2000000000000000"], stub.handle("p0"));
+        assert_eq!(vec!["OK"], stub.handle("P1=5000000000000000"));
+        assert_eq!(10, stub.program.relative_base());
+        assert_eq!(vec!["S05"], stub.handle("s"));
+        assert_eq!(vec!["4800000000000000"], stub.handle("p1"));
+        assert_eq!(vec!["E01"], stub.handle("mfffffffffffffff0,10"));
+        assert_eq!(vec!["E01"], stub.handle("M7ffffffffff8,1:00"));
+        assert_eq!(vec!["OK"], stub.handle("M100,1:01"));
+        assert_eq!(1, stub.program.memory()[32]);
+    }
+
+    #[test]
+    fn test_bad_registers() {
+        let mut stub = Stub::new(Program::new(vec![1101,1], false));
+        assert_eq!(vec!["E01"], stub.handle("P0=0001000000000000"));
+        assert_eq!(0, stub.program.index());
+        // The Add is missing its operands, which panics the interpreter.
+        assert_eq!(Some(&String::from("X04")), stub.handle("s").last());
+        assert!(matches!(&stub.program.state, State::Error(e) if e.starts_with("panic: ")));
+    }
+
+    #[test]
+    fn test_breakpoint_pc() {
+        // A breakpoint set at an address stops with `ip` holding that same address.
+        let mut stub = Stub::new(Program::new(vec![1101,2,3,7,109,-1,99,0], false));
+        assert_eq!(vec!["OK"], stub.handle("Z0,20,1"));
+        assert_eq!(vec!["S05"], stub.handle("c"));
+        assert_eq!(vec!["2000000000000000"], stub.handle("p0"));
+        assert_eq!(vec!["OK"], stub.handle("P0=3000000000000000"));
+        assert_eq!(6, stub.program.index());
+    }
+}
@@ -0,0 +1,79 @@
+/// Everything needed to undo one executed instruction.
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    pub(crate) index: usize,
+    pub(crate) relative_base: usize,
+    pub(crate) memory_len: usize,
+    pub(crate) writes: Vec<(usize, i64)>,
+    pub(crate) inputs: Vec<i64>,
+    pub(crate) outputs: usize
+}
+
+impl Step {
+    pub(crate) fn new(index: usize, relative_base: usize, memory_len: usize) -> Step {
+        Step { index, relative_base, memory_len, ..Step::default() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::program::{Program, State};
+
+    // Reads n, then sums n + (n - 1) + ... + 1 into address 30 and outputs it.
+    fn sum() -> Vec<i64> {
+        vec![3,31,1,30,31,30,1001,31,-1,31,1005,31,2,4,30,99]
+    }
+
+    #[test]
+    fn test_step_back_to_start() {
+        let code = sum();
+        let mut program = Program::new(code.clone(), false);
+        program.enable_history();
+        program.push_input(4);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        let executed = program.instruction_count();
+        assert_eq!(15, executed);
+        while program.step_back() {}
+        assert_eq!(0, program.instruction_count());
+        assert_eq!(0, program.index());
+        assert_eq!(&code, program.memory());
+        assert_eq!(None, program.pop_output());
+        program.process();
+        assert_eq!(Some(10), program.pop_output());
+        assert_eq!(executed, program.instruction_count());
+    }
+
+    #[test]
+    fn test_run_back_to_write() {
+        let mut program = Program::new(sum(), false);
+        program.enable_history();
+        program.push_input(3);
+        program.process();
+        assert!(program.run_back_to_write(30));
+        // The last write to 30 is the add of the final iteration.
+        assert_eq!(2, program.index());
+        assert_eq!(5, program.memory()[30]);
+        program.step();
+        assert_eq!(6, program.memory()[30]);
+        assert!(program.run_back_to_write(31));
+        assert_eq!(6, program.index());
+        assert!(!program.run_back_to_write(20));
+        assert_eq!(0, program.instruction_count());
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let mut program = Program::new(sum(), false);
+        program.enable_history();
+        program.push_input(2);
+        program.process();
+        assert!(program.rewind_to(1));
+        assert_eq!(2, program.memory()[31]);
+        assert!(program.rewind_to(0));
+        assert_eq!(16, program.memory().len());
+        program.process();
+        assert_eq!(Some(3), program.pop_output());
+        assert!(!program.rewind_to(100));
+    }
+}
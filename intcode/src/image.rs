@@ -0,0 +1,245 @@
+use std::io;
+
+use crate::program::IntCode;
+
+/// First bytes of a binary image.
+pub const MAGIC: &[u8; 4] = b"ICIM";
+const VERSION: u8 = 1;
+// Flag bit for images that store runs of zeros as a single token.
+const ZERO_RUNS: u8 = 1;
+
+// Header layout: magic, version, word size in bytes, flags, then the word count as a varint.
+// The payload holds one zigzag varint per word, a zero token is followed by the length of
+// its run if ZERO_RUNS is set. A little endian CRC-32 of everything before it ends the image.
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        bytes.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    bytes.push(v as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.at).ok_or("truncated image")?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.at))
+    }
+}
+
+// Smallest number of bytes holding every word as a two's complement integer.
+fn word_size(code: &IntCode) -> u8 {
+    let bits = code.iter().map(|v| 65 - if *v < 0 { v.leading_ones() } else { v.leading_zeros() }).max().unwrap_or(1);
+    bits.div_ceil(8) as u8
+}
+
+fn fits(v: i64, size: u8) -> bool {
+    size >= 8 || (-(1i64 << (8 * size - 1))..(1i64 << (8 * size - 1))).contains(&v)
+}
+
+/// Binary image of `code`. With `compress` runs of zeros take two tokens whatever their length.
+pub fn encode(code: &IntCode, compress: bool) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.push(word_size(code));
+    bytes.push(if compress { ZERO_RUNS } else { 0 });
+    write_varint(&mut bytes, code.len() as u64);
+    let mut i = 0;
+    while i < code.len() {
+        write_varint(&mut bytes, zigzag(code[i]));
+        i += 1;
+        if compress && code[i - 1] == 0 {
+            let run = code[i..].iter().take_while(|v| **v == 0).count();
+            write_varint(&mut bytes, run as u64);
+            i += run;
+        }
+    }
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+/// Reads a binary image, checking its header, word size and checksum.
+pub fn decode(bytes: &[u8]) -> Result<IntCode, String> {
+    if !is_binary(bytes) {
+        return Err(String::from("not an intcode image"));
+    }
+    if bytes.len() < MAGIC.len() + 8 {
+        return Err(String::from("truncated image"));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if crc32(body) != expected {
+        return Err(String::from("checksum mismatch"));
+    }
+    let mut reader = Reader { bytes: body, at: MAGIC.len() };
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("unsupported image version {}", version));
+    }
+    let size = reader.byte()?;
+    if size == 0 || size > 8 {
+        return Err(format!("unsupported word size {}", size));
+    }
+    let flags = reader.byte()?;
+    if flags & !ZERO_RUNS != 0 {
+        return Err(format!("unknown flags {:#x}", flags));
+    }
+    let len = reader.varint()? as usize;
+    // The count comes from the image, only reserve what its bytes could hold without runs.
+    let mut code = IntCode::with_capacity(len.min(body.len()));
+    while code.len() < len {
+        let value = unzigzag(reader.varint()?);
+        if !fits(value, size) {
+            return Err(format!("word {} does not fit in {} bytes", value, size));
+        }
+        code.push(value);
+        if flags & ZERO_RUNS != 0 && value == 0 {
+            let run = reader.varint()? as usize;
+            if run > len - code.len() {
+                return Err(String::from("zero run past the end of the image"));
+            }
+            code.resize(code.len() + run, 0);
+        }
+    }
+    if reader.at != body.len() {
+        return Err(String::from("trailing bytes after the last word"));
+    }
+    Ok(code)
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Parses the comma separated text form.
+pub fn parse(text: &str) -> Result<IntCode, String> {
+    text.trim().split(',')
+        .map(|s| s.trim().parse::<i64>().map_err(|_| format!("invalid word {:?}", s)))
+        .collect()
+}
+
+/// The comma separated text form, the inverse of `parse`.
+pub fn to_text(code: &IntCode) -> String {
+    code.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+}
+
+/// Reads either format, telling them apart by the magic.
+pub fn load(bytes: &[u8]) -> Result<IntCode, String> {
+    if is_binary(bytes) {
+        decode(bytes)
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| String::from("program is neither text nor an intcode image"))?;
+        parse(text)
+    }
+}
+
+/// Loads the program at `path` in either format.
+pub fn read(path: &str) -> io::Result<IntCode> {
+    let bytes = std::fs::read(path)?;
+    load(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, load, parse, to_text, word_size, MAGIC};
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_round_trip() {
+        let code = vec![1102,34915192,34915192,7,4,7,99,0,0,0,0,0,-1,0,i64::MIN,i64::MAX];
+        for compress in &[false, true] {
+            let bytes = encode(&code, *compress);
+            assert!(bytes.starts_with(MAGIC));
+            assert_eq!(Ok(code.clone()), decode(&bytes));
+            assert_eq!(Ok(code.clone()), load(&bytes));
+        }
+        assert_eq!(Ok(code.clone()), load(to_text(&code).as_bytes()));
+        assert_eq!(Ok(Vec::new()), decode(&encode(&Vec::new(), true)));
+        assert_eq!(Ok(vec![0, 0]), decode(&encode(&vec![0, 0], true)));
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut code = vec![109, 1, 204, -1, 99];
+        code.resize(10_000, 0);
+        let plain = encode(&code, false);
+        let compressed = encode(&code, true);
+        assert!(plain.len() > 10_000);
+        assert!(compressed.len() < 30, "{} bytes", compressed.len());
+        assert_eq!(Ok(code), decode(&compressed));
+    }
+
+    #[test]
+    fn test_word_size() {
+        assert_eq!(1, word_size(&vec![0, 99, -128, 127]));
+        assert_eq!(2, word_size(&vec![128]));
+        assert_eq!(4, word_size(&vec![34915192]));
+        assert_eq!(8, word_size(&vec![i64::MIN]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes = encode(&vec![1, 2, 3, 99], true);
+        let mut corrupt = bytes.clone();
+        corrupt[8] ^= 1;
+        assert_eq!(Err(String::from("checksum mismatch")), decode(&corrupt));
+        assert_eq!(Err(String::from("not an intcode image")), decode(b"1,2,3"));
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(Err(String::from("invalid word \"x\"")), parse("1,x,3"));
+        assert!(load(&[0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn test_program_constructors() {
+        let text = "3,9,8,9,10,9,4,9,99,-1,8";
+        let code = parse(text).unwrap();
+        for mut program in [Program::from_text(text, false).unwrap(),
+                                Program::from_binary(&encode(&code, true), false).unwrap(),
+                                Program::load(&encode(&code, false), false).unwrap(),
+                                Program::load(text.as_bytes(), false).unwrap()] {
+            program.push_input(8);
+            program.process();
+            assert_eq!(State::Halt, program.state);
+            assert_eq!(Some(1), program.pop_output());
+        }
+        assert!(Program::from_binary(text.as_bytes(), false).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Minimal JSON value for the debug adapter and language server protocols.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keeps the key order, lookups are linear.
+    Object(Vec<(String, Json)>)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at byte {}", message, self.at))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.at < self.text.len() && (self.text[self.at] as char).is_ascii_whitespace() {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.at).copied()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.text[self.at..].starts_with(literal.as_bytes()) {
+            self.at += literal.len();
+            Ok(())
+        } else {
+            self.error(&format!("expected {}", literal))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.at += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        Some(b']') => {
+                            self.at += 1;
+                            return Ok(Json::Array(items));
+                        },
+                        _ => return self.error("expected , or ]")
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.at += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.at += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return self.error("expected key");
+                    }
+                    let key = self.string()?;
+                    if self.peek() != Some(b':') {
+                        return self.error("expected :");
+                    }
+                    self.at += 1;
+                    members.push((key, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        Some(b'}') => {
+                            self.at += 1;
+                            return Ok(Json::Object(members));
+                        },
+                        _ => return self.error("expected , or }")
+                    }
+                }
+            },
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let start = self.at;
+                while self.at < self.text.len() && b"+-.eE0123456789".contains(&self.text[self.at]) {
+                    self.at += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.at]).unwrap();
+                match number.parse::<f64>() {
+                    Ok(n) => Ok(Json::Number(n)),
+                    Err(_) => self.error("invalid number")
+                }
+            },
+            _ => self.error("unexpected character")
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.at..self.at + 4).and_then(|d| std::str::from_utf8(d).ok());
+        match digits.and_then(|d| u32::from_str_radix(d, 16).ok()) {
+            Some(v) => {
+                self.at += 4;
+                Ok(v)
+            },
+            None => self.error("invalid unicode escape")
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.at += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = match self.text.get(self.at) {
+                Some(b) => *b,
+                None => return self.error("unterminated string")
+            };
+            self.at += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = self.text.get(self.at).copied();
+                    self.at += 1;
+                    let c = match escaped {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.at..].starts_with(b"\\u") {
+                                self.at += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            std::char::from_u32(code).unwrap_or('\u{fffd}')
+                        },
+                        _ => return self.error("invalid escape")
+                    };
+                    let mut buffer = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                },
+                b => bytes.push(b)
+            }
+        }
+        String::from_utf8(bytes).or_else(|_| self.error("invalid utf-8"))
+    }
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text: text.as_bytes(), at: 0 };
+        let value = parser.value()?;
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => parser.error("trailing characters")
+        }
+    }
+
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(k, v)| (String::from(k), v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    /// Follows a path of object keys.
+    pub fn at(&self, path: &[&str]) -> Option<&Json> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"'                 => write!(f, "\\\"")?,
+            '\\'                => write!(f, "\\\\")?,
+            '\n'                => write!(f, "\\n")?,
+            '\r'                => write!(f, "\\r")?,
+            '\t'                => write!(f, "\\t")?,
+            c if c < ' '        => write!(f, "\\u{:04x}", c as u32)?,
+            c                   => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null                                              => write!(f, "null"),
+            Json::Bool(b)                                           => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15   => write!(f, "{}", *n as i64),
+            Json::Number(n)                                         => write!(f, "{}", n),
+            Json::String(s)                                         => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(String::from(s))
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+/// Reads one `Content-Length` framed message. Returns `None` at the end of the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+    let text = String::from_utf8_lossy(&body);
+    Json::parse(&text).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_message, write_message, Json};
+
+    #[test]
+    fn test_parse() {
+        let value = Json::parse(r#" {"seq": 3, "arguments": {"lines": [1, 2.5, -4e2], "ok": true, "none": null},
+                                     "text": "a\"b\\né😀"} "#).unwrap();
+        assert_eq!(Some(3), value.get("seq").and_then(|v| v.as_i64()));
+        assert_eq!(Some(&Json::Number(-400.0)), value.at(&["arguments", "lines"]).and_then(|l| l.as_array()).map(|l| &l[2]));
+        assert_eq!(Some(true), value.at(&["arguments", "ok"]).and_then(|v| v.as_bool()));
+        assert_eq!(Some(&Json::Null), value.at(&["arguments", "none"]));
+        assert_eq!(Some("a\"b\\né😀"), value.get("text").and_then(|v| v.as_str()));
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let value = Json::object(vec![
+            ("n", Json::from(42i64)),
+            ("s", Json::from("line\n\"quoted\"")),
+            ("a", Json::from(vec![Json::from(true), Json::Null, Json::Number(0.5)]))]);
+        let text = value.to_string();
+        assert_eq!(r#"{"n":42,"s":"line\n\"quoted\"","a":[true,null,0.5]}"#, text);
+        assert_eq!(value, Json::parse(&text).unwrap());
+    }
+
+    #[test]
+    fn test_framing() {
+        let message = Json::object(vec![("seq", Json::from(1i64)), ("command", Json::from("threads"))]);
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &message).unwrap();
+        assert!(buffer.starts_with(b"Content-Length: 29\r\n\r\n"));
+        let mut reader = &buffer[..];
+        assert_eq!(Some(message), read_message(&mut reader).unwrap());
+        assert_eq!(None, read_message(&mut reader).unwrap());
+
+        // A body that does not parse leaves the stream at the next message.
+        let mut reader = &b"Content-Length: 3\r\n\r\n{x}Content-Length: 2\r\n\r\n{}"[..];
+        assert_eq!(std::io::ErrorKind::InvalidData, read_message(&mut reader).unwrap_err().kind());
+        assert_eq!(Some(Json::object(vec![])), read_message(&mut reader).unwrap());
+    }
+}
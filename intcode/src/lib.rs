@@ -0,0 +1,33 @@
+pub mod agent;
+pub mod asm;
+pub mod batch;
+pub mod callstack;
+pub mod compile;
+pub mod coverage;
+pub mod dap;
+pub mod decompile;
+pub mod device;
+pub mod disassemble;
+pub mod extension;
+pub mod fuzz;
+pub mod gdb;
+pub mod history;
+pub mod image;
+pub mod json;
+pub mod library;
+pub mod link;
+pub mod lsp;
+pub mod minimise;
+pub mod network;
+pub mod program;
+pub mod protect;
+pub mod protocol;
+pub mod rng;
+pub mod scheduler;
+pub mod session;
+pub mod spec;
+pub mod toml;
+pub mod transpile;
+pub mod visualize;
+
+pub use program::{decode, Builder, Instruction, IntCode, OpCode, ParameterMode, Program, State};
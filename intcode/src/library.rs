@@ -0,0 +1,306 @@
+use crate::link::{Linker, Object};
+use crate::program::IntCode;
+
+// Routines follow the calling convention of `compile`: the caller points rb at a free
+// frame, stores the arguments from [rb+2] on and the return address in [rb+0], then jumps.
+// The result comes back in [rb+1], everything from [rb+2] on may be overwritten.
+
+/// `divmod(a, b)`: quotient in [rb+1], remainder in [rb+2], both truncated toward zero.
+/// Subtracts the largest doubling of `b` that fits until the rest is smaller than `b`.
+/// A negative `a` is divided as `|a| - 1`, so `i64::MIN` works too; only `i64::MIN / -1`
+/// overflows, as in Rust. Halts the program if `b` is zero.
+pub const DIVMOD: &str = "
+divmod:     JumpIfFalse [rb+3], _zero
+            Equals [rb+3], -9223372036854775808, [rb+1]
+            JumpIfTrue [rb+1], _smallest
+            Equals [rb+3], 1, [rb+6]
+            Equals [rb+3], -1, [rb+7]
+            Add [rb+6], [rb+7], [rb+6]
+            JumpIfTrue [rb+6], _unit
+            LessThan [rb+2], 0, [rb+4]
+            LessThan [rb+3], 0, [rb+5]
+            JumpIfFalse [rb+4], _a
+            Add [rb+2], 1, [rb+2]
+            Mul [rb+2], -1, [rb+2]          ; |a| - 1
+_a:         JumpIfFalse [rb+5], _b
+            Mul [rb+3], -1, [rb+3]
+_b:         Add 0, 0, [rb+1]
+_outer:     LessThan [rb+2], [rb+3], [rb+6]
+            JumpIfTrue [rb+6], _one
+            Add [rb+3], 0, [rb+7]           ; largest doubling of b so far
+            Add 1, 0, [rb+8]                ; and its multiple of b
+_inner:     Mul [rb+7], -1, [rb+9]
+            Add [rb+2], [rb+9], [rb+9]      ; what is left after subtracting it
+            LessThan [rb+9], [rb+7], [rb+6] ; compared instead of doubling, which may overflow
+            JumpIfTrue [rb+6], _subtract
+            Add [rb+7], [rb+7], [rb+7]
+            Add [rb+8], [rb+8], [rb+8]
+            JumpIfTrue 1, _inner
+_subtract:  Add [rb+9], 0, [rb+2]
+            Add [rb+1], [rb+8], [rb+1]
+            JumpIfTrue 1, _outer
+_one:       JumpIfFalse [rb+4], _signs      ; adds back the one taken off |a|
+            Add [rb+2], 1, [rb+2]
+            Equals [rb+2], [rb+3], [rb+6]
+            JumpIfFalse [rb+6], _signs
+            Add 0, 0, [rb+2]
+            Add [rb+1], 1, [rb+1]
+_signs:     Equals [rb+4], [rb+5], [rb+6]
+            JumpIfTrue [rb+6], _remainder
+            Mul [rb+1], -1, [rb+1]
+_remainder: JumpIfFalse [rb+4], _done
+            Mul [rb+2], -1, [rb+2]
+_done:      JumpIfTrue 1, [rb+0]
+_unit:      Mul [rb+2], [rb+3], [rb+1]
+            Add 0, 0, [rb+2]
+            JumpIfTrue 1, _done
+_smallest:  Equals [rb+2], -9223372036854775808, [rb+1]
+            JumpIfFalse [rb+1], _done       ; |a| < |b|, a is the remainder
+            Add 0, 0, [rb+2]
+            JumpIfTrue 1, _done
+_zero:      Halt
+";
+
+/// `div(a, b)` and `mod(a, b)`, the two halves of `divmod`.
+pub const DIV: &str = "
+div:        Add 1, 0, [rb+4]
+            JumpIfTrue 1, _call
+mod:        Add 2, 0, [rb+4]
+_call:      Add [rb+2], 0, [rb+12]
+            Add [rb+3], 0, [rb+13]
+            AdjustRelativeBase 10
+            Add _back, 0, [rb+0]
+            JumpIfTrue 1, divmod
+_back:      AdjustRelativeBase -10
+            Equals [rb+4], 1, [rb+5]
+            JumpIfFalse [rb+5], _remainder
+            Add [rb+11], 0, [rb+1]
+            JumpIfTrue 1, [rb+0]
+_remainder: Add [rb+12], 0, [rb+1]
+            JumpIfTrue 1, [rb+0]
+";
+
+/// `print_number(n)`: outputs `n` in decimal as ASCII, with a leading `-` if negative.
+/// A negative `n` is divided by -10 instead of being negated, `i64::MIN` has no positive
+/// counterpart.
+pub const PRINT_NUMBER: &str = "
+print_number:
+            Add 10, 0, [rb+13]
+            LessThan [rb+2], 0, [rb+3]
+            JumpIfFalse [rb+3], _positive
+            Out 45
+            Add -10, 0, [rb+13]
+_positive:  Add [rb+2], 0, [rb+12]
+            AdjustRelativeBase 10
+            Add _divided, 0, [rb+0]
+            JumpIfTrue 1, divmod
+_divided:   AdjustRelativeBase -10
+            JumpIfFalse [rb+3], _digit
+            Mul [rb+12], -1, [rb+12]        ; the remainder has the sign of n
+_digit:     Add [rb+12], 48, [rb+4]         ; the last digit
+            JumpIfFalse [rb+11], _last
+            Add [rb+11], 0, [rb+12]         ; the digits before it
+            AdjustRelativeBase 10
+            Add _printed, 0, [rb+0]
+            JumpIfTrue 1, print_number
+_printed:   AdjustRelativeBase -10
+_last:      Out [rb+4]
+            JumpIfTrue 1, [rb+0]
+";
+
+/// `print_string(address)`: outputs the words from `address` up to the first zero.
+/// Intcode only loads indirectly through the relative base, and a routine does not know
+/// the absolute value of its rb, so the routine patches the address into its own code. That
+/// is self-modifying code: under `protect::Policy::Trap` it stops with an error, run it
+/// with `Policy::Report` instead.
+pub const PRINT_STRING: &str = "
+print_string:
+_loop:      Add [rb+2], 0, [_address]
+            data 21001                      ; Add [address], 0, [rb+3]
+_address:   data 0, 0, 3
+            JumpIfFalse [rb+3], _end
+            Out [rb+3]
+            Add [rb+2], 1, [rb+2]
+            JumpIfTrue 1, _loop
+_end:       JumpIfTrue 1, [rb+0]
+";
+
+/// `memcpy(destination, source, count)`: copies `count` words front to back, so
+/// overlapping ranges only work if `destination` comes first. Patches both addresses into
+/// its own code like `print_string`, with the same restriction under `Policy::Trap`.
+pub const MEMCPY: &str = "
+memcpy:
+_loop:      JumpIfFalse [rb+4], _end
+            Add [rb+3], 0, [_from]
+            Add [rb+2], 0, [_to]
+            data 1001                       ; Add [from], 0, [to]
+_from:      data 0, 0
+_to:        data 0
+            Add [rb+2], 1, [rb+2]
+            Add [rb+3], 1, [rb+3]
+            Add [rb+4], -1, [rb+4]
+            JumpIfTrue 1, _loop
+_end:       JumpIfTrue 1, [rb+0]
+";
+
+/// `push(value)` and `pop()` keep a stack of values right below rb: `push` stores the value
+/// in [rb+0] of the caller and returns with rb one higher, `pop` returns with rb one lower
+/// and the value in [rb+1].
+pub const STACK: &str = "
+push:       Add [rb+0], 0, [rb+3]
+            Add [rb+2], 0, [rb+0]
+            AdjustRelativeBase 1
+            JumpIfTrue 1, [rb+2]
+pop:        Add [rb+0], 0, [rb+1]
+            Add [rb-1], 0, [rb+0]
+            AdjustRelativeBase -1
+            JumpIfTrue 1, [rb+2]
+";
+
+/// Every routine with the name of its object.
+pub const ROUTINES: [(&str, &str); 6] = [
+    ("divmod", DIVMOD),
+    ("div", DIV),
+    ("print_number", PRINT_NUMBER),
+    ("print_string", PRINT_STRING),
+    ("memcpy", MEMCPY),
+    ("stack", STACK)];
+
+/// A linker holding every routine of the library.
+pub fn linker() -> Linker {
+    let mut linker = Linker::new();
+    for (name, source) in ROUTINES.iter() {
+        linker.add(Object::assemble(name, source).unwrap());
+    }
+    linker
+}
+
+/// Assembles `source` and links it with the routines it uses.
+pub fn link(source: &str) -> Result<IntCode, String> {
+    linker().link(&Object::assemble("main", source)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::link;
+    use crate::program::{Program, State};
+
+    // Calls `routine` with `arguments` from a frame at the end of the image and outputs the
+    // result.
+    fn call(routine: &str, arguments: &[i64]) -> (Program, Vec<i64>) {
+        let mut source = String::from("        AdjustRelativeBase image_end\n");
+        for (i, argument) in arguments.iter().enumerate() {
+            source.push_str(&format!("        Add {}, 0, [rb+{}]\n", argument, i + 2));
+        }
+        source.push_str(&format!("        Add _back, 0, [rb+0]\n        JumpIfTrue 1, {}\n_back:  Out [rb+1]\n        Halt\n", routine));
+        let mut program = Program::new(link(&source).unwrap(), false);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        let mut output = Vec::new();
+        while let Some(value) = program.pop_output() {
+            output.push(value);
+        }
+        (program, output)
+    }
+
+    #[test]
+    fn test_divmod() {
+        let cases = [(17, 5), (-17, 5), (17, -5), (-17, -5), (-15, 5), (-15, -5), (0, 3), (4, 7), (1_000_000_007, 3),
+                     (i64::MAX / 2, 1), (-9, -1), (i64::MIN, 7), (i64::MIN, -7), (i64::MIN, 1), (i64::MIN, 2),
+                     (i64::MIN, i64::MIN), (5, i64::MIN), (-5, i64::MIN), (i64::MAX, i64::MIN)];
+        for (a, b) in &cases {
+            let (program, output) = call("divmod", &[*a, *b]);
+            let frame = program.relative_base();
+            assert_eq!(vec![a / b], output, "{} / {}", a, b);
+            assert_eq!(a % b, program.memory()[frame + 2], "{} % {}", a, b);
+            assert_eq!(vec![a / b], call("div", &[*a, *b]).1);
+            assert_eq!(vec![a % b], call("mod", &[*a, *b]).1);
+        }
+        let (program, output) = call("divmod", &[1, 0]);
+        assert!(output.is_empty());
+        assert_eq!(State::Halt, program.state);
+    }
+
+    #[test]
+    fn test_print_number() {
+        for n in &[0i64, 7, 10, 1234567890, -5, -10, -42, i64::MAX, i64::MIN] {
+            let (_, output) = call("print_number", &[*n]);
+            let text = output[..output.len() - 1].iter().map(|c| *c as u8 as char).collect::<String>();
+            assert_eq!(n.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_print_string() {
+        let source = "
+        AdjustRelativeBase image_end
+        Add _text, 0, [rb+2]
+        Add _back, 0, [rb+0]
+        JumpIfTrue 1, print_string
+_back:  Halt
+_text:  data 104, 105, 10, 0
+";
+        let mut program = Program::new(link(source).unwrap(), false);
+        program.process();
+        let output = program.output_queue().iter().map(|c| *c as u8 as char).collect::<String>();
+        assert_eq!("hi\n", output);
+    }
+
+    #[test]
+    fn test_memcpy() {
+        let source = "
+        AdjustRelativeBase image_end
+        Add _to, 0, [rb+2]
+        Add _from, 0, [rb+3]
+        Add 3, 0, [rb+4]
+        Add _back, 0, [rb+0]
+        JumpIfTrue 1, memcpy
+_back:  Out [_to]
+        Out [_last]
+        Halt
+_from:  data 7, 8, 9
+_to:    data 0, 0
+_last:  data 0
+";
+        let code = link(source).unwrap();
+        let mut program = Program::new(code, false);
+        program.process();
+        assert_eq!(vec![7, 9], program.output_queue().iter().copied().collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn test_stack() {
+        let source = "
+        AdjustRelativeBase image_end
+        Add 10, 0, [rb+2]
+        Add _pushed, 0, [rb+0]
+        JumpIfTrue 1, push
+_pushed:
+        Add 20, 0, [rb+2]
+        Add _again, 0, [rb+0]
+        JumpIfTrue 1, push
+_again: Add _popped, 0, [rb+0]
+        JumpIfTrue 1, pop
+_popped:
+        Out [rb+1]
+        Add _done, 0, [rb+0]
+        JumpIfTrue 1, pop
+_done:  Out [rb+1]
+        Halt
+";
+        let code = link(source).unwrap();
+        let end = code.len();
+        let mut program = Program::new(code, false);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(vec![20, 10], program.output_queue().iter().copied().collect::<Vec<i64>>());
+        assert_eq!(end, program.relative_base());
+    }
+
+    #[test]
+    fn test_unused_routines_are_left_out() {
+        let code = link("        Halt\n").unwrap();
+        assert_eq!(vec![99], code);
+        assert_eq!(Err(String::from("undefined symbol `sqrt`")), link("JumpIfTrue 1, sqrt\n"));
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use crate::asm::{assemble, Import};
+use crate::program::IntCode;
+
+/// Symbol the linker defines as the first address after the image, where a program can
+/// put its stack.
+pub const IMAGE_END: &str = "image_end";
+
+/// Assembled code that can be placed at any address. Labels starting with `_` are local,
+/// every other label is exported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub name: String,
+    pub code: IntCode,
+    pub symbols: HashMap<String, usize>,
+    pub relocations: Vec<usize>,
+    pub imports: Vec<Import>
+}
+
+impl Object {
+    /// Assembles `source`, labels it does not define are left for the linker.
+    pub fn assemble(name: &str, source: &str) -> Result<Object, String> {
+        let assembly = assemble(source);
+        let errors = assembly.diagnostics.iter()
+                                         .filter(|d| !assembly.imports.iter().any(|i| i.span == d.span))
+                                         .map(|d| format!("{}:{}", name, d))
+                                         .collect::<Vec<String>>();
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        let symbols = assembly.labels.iter()
+                                     .filter(|(label, _)| !label.starts_with('_'))
+                                     .map(|(label, l)| (label.clone(), l.address))
+                                     .collect();
+        Ok(Object {
+            name: String::from(name),
+            code: assembly.code,
+            symbols,
+            relocations: assembly.relocations,
+            imports: assembly.imports
+        })
+    }
+}
+
+/// Places a main object at address 0 followed by the objects it needs, directly or
+/// through other objects, and resolves the labels between them.
+#[derive(Debug, Clone, Default)]
+pub struct Linker {
+    objects: Vec<Object>
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    /// Adds an object that is only linked in if one of its symbols is used.
+    pub fn add(&mut self, object: Object) -> &mut Linker {
+        self.objects.push(object);
+        self
+    }
+
+    pub fn link(&self, main: &Object) -> Result<IntCode, String> {
+        let mut included = vec![main];
+        let mut symbols: HashMap<&str, (usize, &str)> = main.symbols.keys().map(|s| (s.as_str(), (0, main.name.as_str()))).collect();
+        let mut next = 0;
+        while next < included.len() {
+            let imports = included[next].imports.iter().map(|i| i.label.as_str()).collect::<Vec<&str>>();
+            next += 1;
+            for label in imports {
+                if symbols.contains_key(label) || label == IMAGE_END {
+                    continue;
+                }
+                let object = self.objects.iter()
+                                         .find(|o| o.symbols.contains_key(label))
+                                         .ok_or_else(|| format!("undefined symbol `{}`", label))?;
+                for symbol in object.symbols.keys() {
+                    if let Some((_, other)) = symbols.insert(symbol, (included.len(), object.name.as_str())) {
+                        return Err(format!("symbol `{}` is defined in {} and {}", symbol, other, object.name));
+                    }
+                }
+                included.push(object);
+            }
+        }
+        let mut bases = Vec::new();
+        let mut image = IntCode::new();
+        for object in &included {
+            bases.push(image.len());
+            image.extend(&object.code);
+        }
+        for (object, base) in included.iter().zip(&bases) {
+            for address in &object.relocations {
+                image[base + address] += *base as i64;
+            }
+            for import in &object.imports {
+                image[base + import.address] = match symbols.get(import.label.as_str()) {
+                    Some((n, _)) => (bases[*n] + included[*n].symbols[&import.label]) as i64,
+                    None => image.len() as i64
+                };
+            }
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Linker, Object};
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_link() {
+        let main = Object::assemble("main", "        In [value]\n        JumpIfTrue 1, twice\nback:   Halt\n").unwrap();
+        let twice = Object::assemble("twice", "twice:  Mul [value], 2, [value]\n        JumpIfTrue 1, _out\n_out:   Out [value]\n        JumpIfTrue 1, back\n").unwrap();
+        let unused = Object::assemble("unused", "unused: Halt\n").unwrap();
+        let value = Object::assemble("value", "value: data 0\n").unwrap();
+        assert_eq!(vec!["value", "value", "value", "back"], twice.imports.iter().map(|i| i.label.as_str()).collect::<Vec<&str>>());
+        let mut linker = Linker::new();
+        linker.add(unused).add(twice).add(value);
+        let code = linker.link(&main).unwrap();
+        assert_eq!(vec![3, 6, 1105, 1, 7, 99, 0, 1002, 6, 2, 6, 1105, 1, 14, 4, 6, 1105, 1, 5], code);
+        let mut program = Program::new(code, false);
+        program.push_input(21);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(Some(42), program.pop_output());
+    }
+
+    #[test]
+    fn test_errors() {
+        let main = Object::assemble("main", "JumpIfTrue 1, missing\n").unwrap();
+        assert_eq!(Err(String::from("undefined symbol `missing`")), Linker::new().link(&main));
+        let main = Object::assemble("main", "JumpIfTrue 1, a\nJumpIfTrue 1, c\n").unwrap();
+        let mut linker = Linker::new();
+        linker.add(Object::assemble("one", "a: Halt\nb: Halt\n").unwrap())
+              .add(Object::assemble("two", "b: Halt\nc: Halt\n").unwrap());
+        assert_eq!(Err(String::from("symbol `b` is defined in one and two")), linker.link(&main));
+        assert_eq!(Err(String::from("main:1:1: unknown mnemonic `Jump`")), Object::assemble("main", "Jump 1\n"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+
+use crate::asm::{assemble, mnemonic, Assembly, Span};
+use crate::json::Json;
+use crate::program::{OpCode, ParameterMode};
+
+// LSP error codes for messages that do not parse and requests the server does not know.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+// CompletionItemKind values.
+const FUNCTION: i64 = 3;
+const KEYWORD: i64 = 14;
+const REFERENCE: i64 = 18;
+
+fn range(span: &Span) -> Json {
+    let position = |character: usize| Json::object(vec![("line", Json::from(span.line)), ("character", Json::from(character))]);
+    Json::object(vec![("start", position(span.start)), ("end", position(span.end))])
+}
+
+fn operand_syntax(op_code: &OpCode) -> String {
+    let names = ["a", "b", "c"];
+    (0..op_code.parameter_count()).map(|n| match op_code.written_parameter() {
+        Some(w) if w == n => format!("[{}]", names[n]),
+        _ => String::from(names[n])
+    }).collect::<Vec<String>>().join(", ")
+}
+
+/// Language server for the assembly dialect of `asm`. Documents are assembled again on
+/// every change.
+pub struct Server {
+    documents: HashMap<String, Assembly>,
+    shutdown: bool,
+    pub finished: bool
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server { documents: HashMap::new(), shutdown: false, finished: false }
+    }
+
+    fn notification(&self, method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(method)),
+            ("params", params)])
+    }
+
+    fn response(&self, request: &Json, result: Result<Json, (i64, String)>) -> Json {
+        let mut members = vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", request.get("id").cloned().unwrap_or(Json::Null))];
+        match result {
+            Ok(value) => members.push(("result", value)),
+            Err((code, message)) => members.push(("error", Json::object(vec![
+                ("code", Json::from(code)),
+                ("message", Json::from(message))])))
+        }
+        Json::object(members)
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Json {
+        let assembly = assemble(text);
+        let diagnostics = assembly.diagnostics.iter().map(|d| Json::object(vec![
+            ("range", range(&d.span)),
+            ("severity", Json::from(1i64)),
+            ("source", Json::from("intcode")),
+            ("message", Json::from(d.message.clone()))])).collect::<Vec<Json>>();
+        self.documents.insert(String::from(uri), assembly);
+        self.notification("textDocument/publishDiagnostics", Json::object(vec![
+            ("uri", Json::from(uri)),
+            ("diagnostics", Json::from(diagnostics))]))
+    }
+
+    // The document and zero based line and character of a position request.
+    fn position<'a>(&'a self, params: Option<&Json>) -> Option<(&'a str, &'a Assembly, usize, usize)> {
+        let params = params?;
+        let uri = params.at(&["textDocument", "uri"]).and_then(|u| u.as_str())?;
+        let line = params.at(&["position", "line"]).and_then(|l| l.as_i64())?;
+        let character = params.at(&["position", "character"]).and_then(|c| c.as_i64())?;
+        let (uri, assembly) = self.documents.get_key_value(uri)?;
+        Some((uri, assembly, line as usize, character as usize))
+    }
+
+    fn hover(&self, params: Option<&Json>) -> Json {
+        let (_, assembly, line, character) = match self.position(params) {
+            Some(p) => p,
+            None => return Json::Null
+        };
+        if let Some(label) = assembly.label_at(line, character).and_then(|name| assembly.labels.get(name).map(|l| (name, l))) {
+            return Json::object(vec![("contents", Json::from(format!("`{}` = {}", label.0, label.1.address)))]);
+        }
+        let statement = match assembly.statement(line) {
+            Some(s) => s,
+            None => return Json::Null
+        };
+        let words = assembly.code.get(statement.address..statement.address + statement.len).unwrap_or(&[]);
+        let words = words.iter().map(|w| w.to_string()).collect::<Vec<String>>().join(",");
+        let mut text = format!("`{}` at {}", words, statement.address);
+        if let Some(op_code) = &statement.op_code {
+            let modes = statement.operands.iter().map(|o| match o.mode {
+                ParameterMode::Position     => "position",
+                ParameterMode::Immediate    => "immediate",
+                ParameterMode::Relative     => "relative"
+            }).collect::<Vec<&str>>();
+            text.push_str(&format!("\n\n{} {}", mnemonic(op_code), operand_syntax(op_code)));
+            if !modes.is_empty() {
+                text.push_str(&format!(" ({})", modes.join(", ")));
+            }
+        }
+        Json::object(vec![("contents", Json::from(text)), ("range", range(&statement.span))])
+    }
+
+    fn definition(&self, params: Option<&Json>) -> Json {
+        let found = self.position(params).and_then(|(uri, assembly, line, character)| {
+            assembly.label_at(line, character).and_then(|name| assembly.labels.get(name)).map(|l| (uri, l))
+        });
+        match found {
+            Some((uri, label)) => Json::object(vec![("uri", Json::from(uri)), ("range", range(&label.span))]),
+            None => Json::Null
+        }
+    }
+
+    fn completion(&self, params: Option<&Json>) -> Json {
+        let mut items = OpCode::all().iter().map(|op| Json::object(vec![
+            ("label", Json::from(mnemonic(op))),
+            ("kind", Json::from(FUNCTION)),
+            ("detail", Json::from(format!("{} {}", mnemonic(op), operand_syntax(op))))])).collect::<Vec<Json>>();
+        items.push(Json::object(vec![
+            ("label", Json::from("data")),
+            ("kind", Json::from(KEYWORD)),
+            ("detail", Json::from("data n, ..."))]));
+        if let Some((_, assembly, _, _)) = self.position(params) {
+            let mut labels = assembly.labels.iter().collect::<Vec<_>>();
+            labels.sort_by_key(|(_, l)| l.address);
+            for (name, label) in labels {
+                items.push(Json::object(vec![
+                    ("label", Json::from(name.as_str())),
+                    ("kind", Json::from(REFERENCE)),
+                    ("detail", Json::from(label.address))]));
+            }
+        }
+        Json::from(items)
+    }
+
+    /// Handles one request or notification and returns the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("").to_string();
+        let params = message.get("params");
+        let result = match method.as_str() {
+            "initialize" => Ok(Json::object(vec![
+                ("capabilities", Json::object(vec![
+                    ("textDocumentSync", Json::from(1i64)),
+                    ("hoverProvider", Json::from(true)),
+                    ("definitionProvider", Json::from(true)),
+                    ("completionProvider", Json::object(vec![]))])),
+                ("serverInfo", Json::object(vec![("name", Json::from("intcode-lsp"))]))])),
+            "textDocument/didOpen" => {
+                let document = params.and_then(|p| p.get("textDocument"));
+                let uri = document.and_then(|d| d.get("uri")).and_then(|u| u.as_str()).unwrap_or("");
+                let text = document.and_then(|d| d.get("text")).and_then(|t| t.as_str()).unwrap_or("");
+                return vec![self.open(uri, text)];
+            },
+            "textDocument/didChange" => {
+                let uri = params.and_then(|p| p.at(&["textDocument", "uri"])).and_then(|u| u.as_str()).unwrap_or("");
+                // Full sync, so the last change holds the whole text.
+                let changes = params.and_then(|p| p.get("contentChanges")).and_then(|c| c.as_array());
+                return match changes.and_then(|c| c.last()).and_then(|c| c.get("text")).and_then(|t| t.as_str()) {
+                    Some(text) => vec![self.open(uri, text)],
+                    None => Vec::new()
+                };
+            },
+            "textDocument/didClose" => {
+                let uri = params.and_then(|p| p.at(&["textDocument", "uri"])).and_then(|u| u.as_str()).unwrap_or("");
+                self.documents.remove(uri);
+                return Vec::new();
+            },
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            },
+            "exit" => {
+                self.finished = true;
+                return Vec::new();
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method)))
+        };
+        // Notifications have no id and get no response.
+        if message.get("id").is_none() {
+            return Vec::new();
+        }
+        vec![self.response(message, result)]
+    }
+
+    /// Response to a message that could not be read, it has no id to answer.
+    pub fn parse_error(&self, message: &str) -> Json {
+        self.response(&Json::Null, Err((PARSE_ERROR, String::from(message))))
+    }
+
+    /// Whether `shutdown` came before `exit`, the exit status the protocol asks for.
+    pub fn clean_exit(&self) -> bool {
+        self.shutdown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Server;
+    use crate::json::Json;
+
+    const URI: &str = "file:///doubler.asm";
+
+    fn request(id: i64, method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", params)])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("method", Json::from(method)),
+            ("params", params)])
+    }
+
+    fn position(line: i64, character: i64) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(URI))])),
+            ("position", Json::object(vec![("line", Json::from(line)), ("character", Json::from(character))]))])
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = Server::new();
+        let initialize = server.handle(&request(1, "initialize", Json::object(vec![])));
+        assert_eq!(Some(true), initialize[0].at(&["result", "capabilities", "hoverProvider"]).and_then(|h| h.as_bool()));
+
+        let text = "start:  In [x]\n        Add [x], 1, 5\n        Jmp start\n        JumpIfTrue 1, start\nx:      data 0\n";
+        let open = server.handle(&notification("textDocument/didOpen", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(URI)), ("text", Json::from(text))]))])));
+        assert_eq!(Some("textDocument/publishDiagnostics"), open[0].get("method").and_then(|m| m.as_str()));
+        let diagnostics = open[0].at(&["params", "diagnostics"]).and_then(|d| d.as_array()).unwrap();
+        let messages = diagnostics.iter().map(|d| d.get("message").and_then(|m| m.as_str()).unwrap()).collect::<Vec<&str>>();
+        assert_eq!(vec!["Add writes its 3rd operand, it can not be immediate", "unknown mnemonic `Jmp`"], messages);
+        assert_eq!(Some(1), diagnostics[0].at(&["range", "start", "line"]).and_then(|l| l.as_i64()));
+        assert_eq!(Some(20), diagnostics[0].at(&["range", "start", "character"]).and_then(|l| l.as_i64()));
+
+        let fixed = text.replace("1, 5", "1, [x]").replace("        Jmp start\n", "");
+        let change = server.handle(&notification("textDocument/didChange", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(URI)), ("version", Json::from(2i64))])),
+            ("contentChanges", Json::from(vec![Json::object(vec![("text", Json::from(fixed))])]))])));
+        assert_eq!(Some(0), change[0].at(&["params", "diagnostics"]).and_then(|d| d.as_array()).map(|d| d.len()));
+
+        let hover = server.handle(&request(2, "textDocument/hover", position(1, 9)));
+        let contents = hover[0].at(&["result", "contents"]).and_then(|c| c.as_str()).unwrap();
+        assert!(contents.starts_with("`1001,9,1,9` at 2"), "{}", contents);
+        assert!(contents.contains("(position, immediate, position)"), "{}", contents);
+
+        let definition = server.handle(&request(3, "textDocument/definition", position(2, 23)));
+        assert_eq!(Some(0), definition[0].at(&["result", "range", "start", "line"]).and_then(|l| l.as_i64()));
+        let definition = server.handle(&request(4, "textDocument/definition", position(0, 12)));
+        assert_eq!(Some(3), definition[0].at(&["result", "range", "start", "line"]).and_then(|l| l.as_i64()));
+
+        let completion = server.handle(&request(5, "textDocument/completion", position(2, 0)));
+        let labels = completion[0].get("result").and_then(|r| r.as_array()).unwrap()
+                                  .iter()
+                                  .map(|i| i.get("label").and_then(|l| l.as_str()).unwrap())
+                                  .collect::<Vec<&str>>();
+        assert_eq!(vec!["Add", "Mul", "In", "Out", "JumpIfTrue", "JumpIfFalse", "LessThan", "Equals",
+                        "AdjustRelativeBase", "Halt", "data", "start", "x"], labels);
+
+        let unknown = server.handle(&request(6, "workspace/symbol", Json::Null));
+        assert_eq!(Some(-32601), unknown[0].at(&["error", "code"]).and_then(|c| c.as_i64()));
+        let invalid = server.parse_error("expected value at 1");
+        assert_eq!(Some(-32700), invalid.at(&["error", "code"]).and_then(|c| c.as_i64()));
+        assert_eq!(Some(&Json::Null), invalid.get("id"));
+        server.handle(&request(7, "shutdown", Json::Null));
+        assert!(server.handle(&notification("exit", Json::Null)).is_empty());
+        assert!(server.finished && server.clean_exit());
+    }
+}
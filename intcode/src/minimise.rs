@@ -0,0 +1,238 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::fuzz::{panic_message, Outcome, Status};
+use crate::program::{decode, IntCode, ParameterMode, Program, State};
+
+/// Steps a candidate may run before it counts as hanging, unless a limit is given.
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+/// Removing words shifts data into operands, so candidates easily address huge amounts of
+/// memory. Touching an address from here on stops the run instead of allocating.
+pub const MEMORY_LIMIT: i64 = 1 << 24;
+
+// Whether the next instruction addresses memory beyond `MEMORY_LIMIT`. Missing operands and
+// negative addresses are left to the interpreter.
+fn beyond_limit(program: &Program) -> bool {
+    let memory = program.memory();
+    let ip = program.index();
+    let instruction = match memory.get(ip) {
+        Some(word) => decode(*word),
+        None => return false
+    };
+    let (pm1, pm2, pm3) = instruction.parameter_modes;
+    let modes = [pm1, pm2, pm3];
+    (0..instruction.op_code.parameter_count()).any(|i| {
+        let raw = memory.get(ip + i + 1).copied().unwrap_or(0);
+        let address = match modes[i] {
+            ParameterMode::Position     => raw,
+            ParameterMode::Immediate    => 0,
+            ParameterMode::Relative     => (program.relative_base() as i64).saturating_add(raw)
+        };
+        address >= MEMORY_LIMIT
+    })
+}
+
+/// Runs `code` on `Program` with `input`, turning panics into `Status::Panic` and stopping
+/// with an error after `max_steps` steps or at the memory limit.
+pub fn execute(code: &[i64], input: &[i64], max_steps: usize) -> Outcome {
+    let run = || {
+        let mut program = Program::new(code.to_vec(), false);
+        for value in input {
+            program.push_input(*value);
+        }
+        let mut steps = 0;
+        let mut status = None;
+        while program.state == State::Idle {
+            if steps == max_steps {
+                status = Some(Status::Error(String::from("step limit")));
+                break;
+            }
+            if beyond_limit(&program) {
+                status = Some(Status::Error(String::from("memory limit")));
+                break;
+            }
+            program.step();
+            steps += 1;
+        }
+        let status = status.unwrap_or_else(|| match &program.state {
+            State::Halt         => Status::Halt,
+            State::WaitForInput => Status::WaitForInput,
+            State::Error(v)     => Status::Error(v.clone()),
+            State::Idle         => unreachable!()
+        });
+        let output = program.output_queue().iter().copied().collect();
+        Outcome { status, output, memory: program.memory().clone() }
+    };
+    match panic::catch_unwind(AssertUnwindSafe(run)) {
+        Ok(outcome) => outcome,
+        Err(e) => Outcome { status: Status::Panic(panic_message(e)), output: Vec::new(), memory: Vec::new() }
+    }
+}
+
+/// A predicate for failures like `original`: a panic matches any panic, since its message
+/// mentions addresses that change while shrinking, and an error matches the same error.
+pub fn same_failure(original: &Outcome) -> impl Fn(&Outcome) -> bool {
+    let status = original.status.clone();
+    move |outcome| match (&status, &outcome.status) {
+        (Status::Panic(_), Status::Panic(_))    => true,
+        (Status::Error(e), Status::Error(a))    => e == a,
+        _                                       => false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reproducer {
+    pub code: IntCode,
+    pub input: Vec<i64>,
+    // How many candidates were tried.
+    pub tests: usize
+}
+
+// Offers `edit` the chunks of `len` words, halves first, then quarters and so on down to
+// single words. `edit` returns the new length if it changed the chunk; a removed chunk is
+// replaced by the words after it, so the same start is offered again.
+fn chunks<F: FnMut(usize, usize) -> Option<usize>>(mut len: usize, mut edit: F) -> bool {
+    let mut size = len.div_ceil(2).max(1);
+    let mut progress = false;
+    loop {
+        let mut start = 0;
+        while start < len {
+            let end = (start + size).min(len);
+            match edit(start, end) {
+                Some(new) if new < len => len = new,
+                Some(_) => start = end,
+                None => {
+                    start = end;
+                    continue;
+                }
+            }
+            progress = true;
+        }
+        if size == 1 {
+            return progress;
+        }
+        size = size.div_ceil(2);
+    }
+}
+
+/// Delta debugging: shrinks `code` and `input` while `test` keeps returning true for them,
+/// by truncating the input, keeping only a range of code, removing ranges of code and
+/// zeroing ranges of code until none of these changes keeps the failure. `test` has to hold
+/// for the original program.
+pub fn reduce<F: FnMut(&[i64], &[i64]) -> bool>(code: &[i64], input: &[i64], mut test: F) -> Reproducer {
+    let mut current = Reproducer { code: code.to_vec(), input: input.to_vec(), tests: 0 };
+    loop {
+        let mut progress = false;
+        let mut size = current.input.len().div_ceil(2);
+        while size > 0 {
+            let len = current.input.len();
+            current.tests += 1;
+            if size <= len && test(&current.code, &current.input[..len - size]) {
+                current.input.truncate(len - size);
+                progress = true;
+            } else {
+                size /= 2;
+            }
+        }
+        let mut size = current.code.len().div_ceil(2);
+        while size > 0 {
+            let kept = (0..current.code.len()).step_by(size).find(|start| {
+                current.tests += 1;
+                let end = (start + size).min(current.code.len());
+                end - start < current.code.len() && test(&current.code[*start..end], &current.input)
+            });
+            match kept {
+                Some(start) => {
+                    let end = (start + size).min(current.code.len());
+                    current.code = current.code[start..end].to_vec();
+                    size = current.code.len().div_ceil(2);
+                    progress = true;
+                },
+                None => size /= 2
+            }
+        }
+        progress |= chunks(current.code.len(), |start, end| {
+            let mut candidate = current.code.clone();
+            candidate.drain(start..end);
+            current.tests += 1;
+            if candidate.is_empty() || !test(&candidate, &current.input) {
+                return None;
+            }
+            current.code = candidate;
+            Some(current.code.len())
+        });
+        progress |= chunks(current.code.len(), |start, end| {
+            if current.code[start..end].iter().all(|w| *w == 0) {
+                return None;
+            }
+            let mut candidate = current.code.clone();
+            candidate[start..end].iter_mut().for_each(|w| *w = 0);
+            current.tests += 1;
+            if !test(&candidate, &current.input) {
+                return None;
+            }
+            current.code = candidate;
+            Some(current.code.len())
+        });
+        if !progress {
+            return current;
+        }
+    }
+}
+
+/// Shrinks a program that fails according to `failing` into the smallest reproducer found.
+pub fn minimise<F: Fn(&Outcome) -> bool>(code: &[i64], input: &[i64], max_steps: usize, failing: F) -> Result<Reproducer, String> {
+    if !failing(&execute(code, input, max_steps)) {
+        return Err(String::from("the program does not fail"));
+    }
+    Ok(reduce(code, input, |code, input| failing(&execute(code, input, max_steps))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{execute, minimise, reduce, same_failure};
+    use crate::fuzz::Status;
+
+    #[test]
+    fn test_execute() {
+        assert_eq!(Status::Error(String::from("42")), execute(&[42], &[], 1000).status);
+        assert!(matches!(execute(&[1], &[], 1000).status, Status::Panic(_)));
+        assert_eq!(Status::Error(String::from("step limit")), execute(&[1105, 1, 0], &[], 1000).status);
+        assert_eq!(Status::Error(String::from("memory limit")), execute(&[1101, 1, 1, 1 << 40, 99], &[], 1000).status);
+        let outcome = execute(&[3, 5, 4, 5, 99, 0], &[7], 1000);
+        assert_eq!((Status::Halt, vec![7]), (outcome.status, outcome.output));
+    }
+
+    #[test]
+    fn test_minimise_error() {
+        // Outputs the sum of two inputs, then jumps to an invalid opcode.
+        let code = vec![104,1,104,2,3,100,3,101,1,100,101,102,4,102,1105,1,18,99,42,99];
+        let original = execute(&code, &[3, 4, 5], 1000);
+        assert_eq!(Status::Error(String::from("42")), original.status);
+        let reproducer = minimise(&code, &[3, 4, 5], 1000, same_failure(&original)).unwrap();
+        assert_eq!(vec![42], reproducer.code);
+        assert!(reproducer.input.is_empty());
+        assert_eq!(Err(String::from("the program does not fail")), minimise(&[99], &[], 1000, same_failure(&original)));
+    }
+
+    #[test]
+    fn test_minimise_panic() {
+        // Reads a count and outputs that many words, then runs into an Add missing its operands.
+        let code = vec![3,14,1001,14,-1,14,104,5,1005,14,2,1105,1,15,99,1];
+        let original = execute(&code, &[2], 1000);
+        assert!(matches!(original.status, Status::Panic(_)));
+        let reproducer = minimise(&code, &[2, 8, 9], 1000, same_failure(&original)).unwrap();
+        assert_eq!(1, reproducer.code.len());
+        assert!(matches!(execute(&reproducer.code, &[], 1000).status, Status::Panic(_)));
+        assert!(reproducer.input.is_empty());
+    }
+
+    #[test]
+    fn test_reduce_input() {
+        // Echoes its input; the failure is outputting a 7.
+        let code = vec![3,9,4,9,1105,1,0,99,99,0];
+        let reproducer = reduce(&code, &[1, 2, 7, 4, 5], |code, input| execute(code, input, 1000).output.contains(&7));
+        assert_eq!(vec![1, 2, 7], reproducer.input);
+        assert!(reproducer.code.len() < code.len());
+        assert!(execute(&reproducer.code, &reproducer.input, 1000).output.contains(&7));
+    }
+}
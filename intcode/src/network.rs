@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+
+use crate::program::{IntCode, Program, State};
+use crate::protocol::Decoder;
+
+pub type Address = i64;
+
+pub type Observer = Box<dyn FnMut(&Packet)>;
+
+// Number of consecutive rounds without any traffic before the network counts as idle.
+const IDLE_ROUNDS: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Packet {
+    pub source: Address,
+    pub destination: Address,
+    pub x: i64,
+    pub y: i64
+}
+
+pub struct Network {
+    machines: Vec<Program>,
+    queues: Vec<VecDeque<(i64, i64)>>,
+    decoders: Vec<Decoder<(Address, i64, i64)>>,
+    monitor_address: Option<Address>,
+    monitor: Option<Packet>,
+    observers: Vec<Observer>,
+    quiet_rounds: usize,
+    pub rounds: usize,
+    pub dropped: usize
+}
+
+impl Network {
+    /// Boots `size` machines running `code`, each one receiving its address as first input.
+    pub fn new(code: &IntCode, size: usize) -> Network {
+        let mut machines = Vec::with_capacity(size);
+        for address in 0..size {
+            let mut program = Program::new(code.clone(), false);
+            program.push_input(address as i64);
+            machines.push(program);
+        }
+        Network {
+            machines,
+            queues: vec![VecDeque::new(); size],
+            decoders: (0..size).map(|_| Decoder::new(3, |t| Ok((t[0], t[1], t[2])))).collect(),
+            monitor_address: None,
+            monitor: None,
+            observers: Vec::new(),
+            quiet_rounds: 0,
+            rounds: 0,
+            dropped: 0
+        }
+    }
+
+    /// Packets sent to `address` are kept as the monitor (NAT) packet instead of being dropped.
+    pub fn set_monitor(&mut self, address: Address) {
+        self.monitor_address = Some(address);
+    }
+
+    /// Last packet received on the monitor address.
+    pub fn monitor(&self) -> Option<Packet> {
+        self.monitor
+    }
+
+    pub fn add_observer<F: FnMut(&Packet) + 'static>(&mut self, observer: F) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn size(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn state(&self, address: Address) -> Option<&State> {
+        self.machines.get(address as usize).map(|m| &m.state)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.quiet_rounds >= IDLE_ROUNDS && self.queues.iter().all(|q| q.is_empty())
+    }
+
+    /// Delivers `packet` as if it had been sent by `packet.source`.
+    pub fn send(&mut self, packet: Packet) {
+        for observer in &mut self.observers {
+            observer(&packet);
+        }
+        if Some(packet.destination) == self.monitor_address {
+            self.monitor = Some(packet);
+        } else if packet.destination >= 0 && (packet.destination as usize) < self.queues.len() {
+            self.queues[packet.destination as usize].push_back((packet.x, packet.y));
+            self.quiet_rounds = 0;
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Runs every machine until it blocks on input, then routes the packets it produced.
+    /// Returns the number of packets sent during the round.
+    pub fn step(&mut self) -> usize {
+        let mut sent = Vec::new();
+        let mut received = false;
+        for address in 0..self.machines.len() {
+            let machine = &mut self.machines[address];
+            if machine.state == State::Halt {
+                continue;
+            }
+            if let State::Error(_) = machine.state {
+                continue;
+            }
+            match self.queues[address].pop_front() {
+                Some((x, y)) => {
+                    machine.push_input(x);
+                    machine.push_input(y);
+                    received = true;
+                },
+                None => {
+                    if machine.state == State::WaitForInput {
+                        machine.push_input(-1);
+                    }
+                }
+            }
+            machine.process();
+            // A machine halting halfway through a packet only loses that packet, counted as dropped.
+            let (packets, error) = self.decoders[address].drain(machine);
+            if error.is_some() {
+                self.dropped += 1;
+            }
+            for (destination, x, y) in packets {
+                sent.push(Packet { source: address as i64, destination, x, y });
+            }
+        }
+        let count = sent.len();
+        for packet in sent {
+            self.send(packet);
+        }
+        if count == 0 && !received && self.decoders.iter().all(|d| d.pending().is_empty()) {
+            self.quiet_rounds += 1;
+        } else {
+            self.quiet_rounds = 0;
+        }
+        self.rounds += 1;
+        count
+    }
+
+    /// Steps the network until it is idle, giving up after `max_rounds`.
+    pub fn run_until_idle(&mut self, max_rounds: usize) -> bool {
+        for _ in 0..max_rounds {
+            self.step();
+            if self.is_idle() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Network, Packet};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Reads its address, reports (address, 10 * address) to 255 and then keeps polling input.
+    fn announce() -> Vec<i64> {
+        vec![3,100,104,255,4,100,1002,100,10,101,4,101,3,102,1105,1,12]
+    }
+
+    // Reads its address, then forwards every received packet to 255.
+    fn relay() -> Vec<i64> {
+        vec![3,100,3,101,1008,101,-1,103,1005,103,2,3,102,104,255,4,101,4,102,1105,1,2]
+    }
+
+    #[test]
+    fn test_boot_and_monitor() {
+        let mut network = Network::new(&announce(), 4);
+        network.set_monitor(255);
+        let traffic = Rc::new(RefCell::new(Vec::new()));
+        let log = traffic.clone();
+        network.add_observer(move |p| log.borrow_mut().push(*p));
+        assert!(network.run_until_idle(10));
+        assert_eq!(4, traffic.borrow().len());
+        assert_eq!(Some(Packet { source: 3, destination: 255, x: 3, y: 30 }), network.monitor());
+        assert_eq!(0, network.dropped);
+    }
+
+    #[test]
+    fn test_routing() {
+        let mut network = Network::new(&relay(), 3);
+        network.set_monitor(255);
+        assert!(network.run_until_idle(10));
+        assert_eq!(None, network.monitor());
+        network.send(Packet { source: 255, destination: 1, x: 7, y: 8 });
+        assert!(!network.is_idle());
+        assert_eq!(1, network.step());
+        assert_eq!(Some(Packet { source: 1, destination: 255, x: 7, y: 8 }), network.monitor());
+        network.send(Packet { source: 0, destination: 42, x: 0, y: 0 });
+        assert_eq!(1, network.dropped);
+        assert!(network.run_until_idle(10));
+    }
+
+    #[test]
+    fn test_halt_mid_packet() {
+        // Sends (255, 1, 2), then halts after the destination of a second packet.
+        let mut network = Network::new(&vec![3,100,104,255,104,1,104,2,104,255,99], 2);
+        network.set_monitor(255);
+        assert_eq!(2, network.step());
+        assert_eq!(Some(Packet { source: 1, destination: 255, x: 1, y: 2 }), network.monitor());
+        assert_eq!(2, network.dropped);
+        assert!(network.run_until_idle(10));
+    }
+}
@@ -0,0 +1,753 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::callstack::CallStack;
+use crate::coverage::Coverage;
+use crate::device::{self, Device, Mapping};
+use crate::extension::{self, Context, Effect, Extension, Role};
+use crate::history::Step;
+use crate::image;
+use crate::protect::{Policy, WriteProtection};
+
+pub type IntCode = Vec<i64>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpCode{
+    Add,
+    Mul,
+    In,
+    Out,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Halt,
+    Err(String)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParameterMode {
+    Position,
+    Immediate,
+    Relative
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub op_code: OpCode,
+    pub parameter_modes: (ParameterMode, ParameterMode, ParameterMode)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Idle,
+    WaitForInput,
+    Halt,
+    Error(String)
+}
+
+#[derive(Clone)]
+pub struct Program {
+    // Shared between clones until the first write.
+    code: Arc<IntCode>,
+    index: usize,
+    relative_base: usize,
+    pub state: State,
+    debug_mode: bool,
+    pub(crate) output: VecDeque<i64>,
+    pub(crate) input: VecDeque<i64>,
+    extensions: Vec<Extension>,
+    devices: Vec<Mapping>,
+    history: Option<Vec<Step>>,
+    coverage: Option<Coverage>,
+    calls: Option<CallStack>,
+    protection: Option<WriteProtection>,
+    instruction_count: usize
+}
+
+pub struct Builder {
+    code: IntCode,
+    debug_mode: bool,
+    extensions: Vec<Extension>,
+    devices: Vec<Mapping>
+}
+
+
+fn get_opcode(op_code_str: &str) -> OpCode {
+    match op_code_str {
+        "01"    => OpCode::Add,
+        "02"    => OpCode::Mul,
+        "03"    => OpCode::In,
+        "04"    => OpCode::Out,
+        "05"    => OpCode::JumpIfTrue,
+        "06"    => OpCode::JumpIfFalse,
+        "07"    => OpCode::LessThan,
+        "08"    => OpCode::Equals,
+        "09"    => OpCode::AdjustRelativeBase,
+        "99"    => OpCode::Halt,
+        v       => OpCode::Err(String::from(v))
+    }
+}
+
+fn get_parameter_mode(parameter_mode: char) -> ParameterMode {
+    match parameter_mode {
+        '0' => ParameterMode::Position,
+        '1' => ParameterMode::Immediate,
+        _   => ParameterMode::Relative
+    }
+}
+
+/// Decodes an instruction word the same way the interpreter does.
+pub fn decode(word: i64) -> Instruction {
+    let s = word.to_string();
+    if word < 0 || s.len() > 5 {
+        return Instruction {
+            op_code: OpCode::Err(s),
+            parameter_modes: (ParameterMode::Position, ParameterMode::Position, ParameterMode::Position)
+        };
+    }
+    let mut raw_instruction = ['0'; 5];
+    let start_index = 5 - s.len();
+    for (i, c) in s.chars().enumerate() {
+        raw_instruction[start_index + i] = c;
+    }
+    let op_code_str = raw_instruction[3..].to_vec().iter().collect::<String>();
+    Instruction {
+        op_code: get_opcode(&op_code_str),
+        parameter_modes: (
+            get_parameter_mode(raw_instruction[2]),
+            get_parameter_mode(raw_instruction[1]),
+            get_parameter_mode(raw_instruction[0]))}
+}
+
+impl OpCode {
+    pub fn parameter_count(&self) -> usize {
+        match self {
+            OpCode::Add | OpCode::Mul | OpCode::LessThan | OpCode::Equals   => 3,
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse                        => 2,
+            OpCode::In | OpCode::Out | OpCode::AdjustRelativeBase           => 1,
+            OpCode::Halt | OpCode::Err(_)                                   => 0
+        }
+    }
+
+    /// Every valid instruction, in opcode order.
+    pub fn all() -> Vec<OpCode> {
+        vec![OpCode::Add, OpCode::Mul, OpCode::In, OpCode::Out, OpCode::JumpIfTrue, OpCode::JumpIfFalse,
+             OpCode::LessThan, OpCode::Equals, OpCode::AdjustRelativeBase, OpCode::Halt]
+    }
+
+    pub fn number(&self) -> Option<i64> {
+        match self {
+            OpCode::Add                 => Some(1),
+            OpCode::Mul                 => Some(2),
+            OpCode::In                  => Some(3),
+            OpCode::Out                 => Some(4),
+            OpCode::JumpIfTrue          => Some(5),
+            OpCode::JumpIfFalse         => Some(6),
+            OpCode::LessThan            => Some(7),
+            OpCode::Equals              => Some(8),
+            OpCode::AdjustRelativeBase  => Some(9),
+            OpCode::Halt                => Some(99),
+            OpCode::Err(_)              => None
+        }
+    }
+
+    /// The parameter the instruction writes to, which must not be immediate.
+    pub fn written_parameter(&self) -> Option<usize> {
+        match self {
+            OpCode::Add | OpCode::Mul | OpCode::LessThan | OpCode::Equals   => Some(2),
+            OpCode::In                                                      => Some(0),
+            _                                                               => None
+        }
+    }
+}
+
+impl Instruction {
+    /// The instruction word, the inverse of `decode`.
+    pub fn encode(&self) -> Option<i64> {
+        let mode = |m: ParameterMode| match m {
+            ParameterMode::Position     => 0,
+            ParameterMode::Immediate    => 1,
+            ParameterMode::Relative     => 2
+        };
+        let (pm1, pm2, pm3) = self.parameter_modes;
+        self.op_code.number().map(|n| n + 100 * mode(pm1) + 1000 * mode(pm2) + 10000 * mode(pm3))
+    }
+
+    pub fn parameter_mode(&self, parameter: usize) -> ParameterMode {
+        let (pm1, pm2, pm3) = self.parameter_modes;
+        [pm1, pm2, pm3][parameter]
+    }
+}
+
+impl Program {
+    pub fn new(code: IntCode, debug_mode: bool) -> Program {
+        Program::shared(Arc::new(code), debug_mode)
+    }
+
+    /// Runs on `code` without copying it; the image is only copied once the program writes.
+    pub fn shared(code: Arc<IntCode>, debug_mode: bool) -> Program {
+        Program{
+            code,
+            index: 0,
+            relative_base: 0,
+            state: State::Idle,
+            debug_mode,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            extensions: Vec::new(),
+            devices: Vec::new(),
+            history: None,
+            coverage: None,
+            calls: None,
+            protection: None,
+            instruction_count: 0
+        }
+    }
+
+    /// Parses the comma separated text form.
+    pub fn from_text(text: &str, debug_mode: bool) -> Result<Program, String> {
+        image::parse(text).map(|code| Program::new(code, debug_mode))
+    }
+
+    /// Reads a binary image, see `image::encode`.
+    pub fn from_binary(bytes: &[u8], debug_mode: bool) -> Result<Program, String> {
+        image::decode(bytes).map(|code| Program::new(code, debug_mode))
+    }
+
+    /// Accepts either the text form or a binary image.
+    pub fn load(bytes: &[u8], debug_mode: bool) -> Result<Program, String> {
+        image::load(bytes).map(|code| Program::new(code, debug_mode))
+    }
+
+    pub fn builder(code: IntCode) -> Builder {
+        Builder {
+            code,
+            debug_mode: false,
+            extensions: Vec::new(),
+            devices: Vec::new()
+        }
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(value);
+        if self.state == State::WaitForInput {
+            self.state = State::Idle;
+        }
+    }
+
+    pub fn pop_output(&mut self) -> Option<i64> {
+        self.output.pop_front()
+    }
+
+    /// Number of inputs pushed but not yet consumed.
+    pub fn pending_input(&self) -> usize {
+        self.input.len()
+    }
+
+    pub fn input_queue(&self) -> &VecDeque<i64> {
+        &self.input
+    }
+
+    /// Outputs not popped yet.
+    pub fn output_queue(&self) -> &VecDeque<i64> {
+        &self.output
+    }
+
+    pub fn memory(&self) -> &IntCode {
+        &self.code
+    }
+
+    pub(crate) fn memory_mut(&mut self) -> &mut IntCode {
+        Arc::make_mut(&mut self.code)
+    }
+
+    /// Overwrites `address`, growing memory if needed. Not recorded in the history.
+    pub fn set_memory(&mut self, address: usize, value: i64) {
+        let code = Arc::make_mut(&mut self.code);
+        if address >= code.len() {
+            code.resize(address + 1, 0);
+        }
+        code[address] = value;
+    }
+
+    /// The device mapped at `start`, if it is of type `T`.
+    pub fn device<T: Device>(&self, start: usize) -> Option<&T> {
+        self.devices.iter()
+                    .find(|m| m.start == start)
+                    .and_then(|m| device::downcast_ref::<T>(m.device.as_ref()))
+    }
+
+    pub(crate) fn read(&mut self, address: usize) -> i64 {
+        match self.devices.iter_mut().find(|m| m.contains(address)) {
+            Some(m) => m.device.read(address - m.start),
+            None => self.code[address]
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: usize, value: i64) {
+        match self.devices.iter_mut().find(|m| m.contains(address)) {
+            Some(m) => m.device.write(address - m.start, value),
+            None => {
+                let old = self.code[address];
+                if let Some(protection) = &mut self.protection {
+                    if let Err(e) = protection.check(address, old, value, self.index, self.instruction_count) {
+                        self.state = State::Error(e);
+                        return;
+                    }
+                }
+                self.record(|step| step.writes.push((address, old)));
+                Arc::make_mut(&mut self.code)[address] = value;
+            }
+        }
+    }
+
+    fn record_branch(&mut self, taken: bool) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.branch(self.index, taken);
+        }
+    }
+
+    /// Starts watching for writes to executed addresses, and with `protect_image` to any
+    /// address of the current memory image.
+    pub fn enable_write_protection(&mut self, policy: Policy, protect_image: bool) {
+        if self.protection.is_none() {
+            let image = if protect_image { self.code.len() } else { 0 };
+            self.protection = Some(WriteProtection::new(policy, image));
+        }
+    }
+
+    pub fn write_protection(&self) -> Option<&WriteProtection> {
+        self.protection.as_ref()
+    }
+
+    pub fn write_protection_mut(&mut self) -> Option<&mut WriteProtection> {
+        self.protection.as_mut()
+    }
+
+    /// Starts counting executed addresses and conditional jump outcomes.
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new());
+        }
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts reconstructing calls and returns from stack usage. Stepping back does not
+    /// undo the call stack.
+    pub fn enable_call_stack(&mut self) {
+        if self.calls.is_none() {
+            self.calls = Some(CallStack::new());
+        }
+    }
+
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.calls.as_ref()
+    }
+
+    pub fn call_stack_mut(&mut self) -> Option<&mut CallStack> {
+        self.calls.as_mut()
+    }
+
+    /// Symbolic backtrace of the current position, if the call stack is enabled.
+    pub fn backtrace(&self) -> Option<Vec<String>> {
+        self.calls.as_ref().map(|c| c.backtrace(self.index, self.relative_base))
+    }
+
+    pub(crate) fn record<F: FnOnce(&mut Step)>(&mut self, f: F) {
+        if let Some(step) = self.history.as_mut().and_then(|h| h.last_mut()) {
+            f(step);
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn relative_base(&self) -> usize {
+        self.relative_base
+    }
+
+    /// Moves the instruction pointer and relative base, as a debugger would.
+    pub fn set_registers(&mut self, index: usize, relative_base: usize) {
+        self.index = index;
+        self.relative_base = relative_base;
+        if let State::Error(_) = self.state {
+            self.state = State::Idle;
+        }
+    }
+
+    /// Number of instructions executed so far.
+    pub fn instruction_count(&self) -> usize {
+        self.instruction_count
+    }
+
+    /// Starts recording an undo log so execution can be stepped backwards.
+    /// Device accesses and outputs already popped by the caller can not be undone.
+    pub fn enable_history(&mut self) {
+        if self.history.is_none() {
+            self.history = Some(Vec::new());
+        }
+    }
+
+    /// Undoes the last executed instruction. Returns false when there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let step = match self.history.as_mut().and_then(|h| h.pop()) {
+            Some(s) => s,
+            None => return false
+        };
+        for (address, old) in step.writes.into_iter().rev() {
+            Arc::make_mut(&mut self.code)[address] = old;
+        }
+        if self.code.len() > step.memory_len {
+            Arc::make_mut(&mut self.code).truncate(step.memory_len);
+        }
+        for value in step.inputs.into_iter().rev() {
+            self.input.push_front(value);
+        }
+        for _ in 0..step.outputs {
+            self.output.pop_back();
+        }
+        self.index = step.index;
+        self.relative_base = step.relative_base;
+        self.state = State::Idle;
+        self.instruction_count -= 1;
+        true
+    }
+
+    /// Steps back until just before the last instruction that wrote `address`.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        loop {
+            let wrote = match self.history.as_ref().and_then(|h| h.last()) {
+                Some(step) => step.writes.iter().any(|(a, _)| *a == address),
+                None => return false
+            };
+            self.step_back();
+            if wrote {
+                return true;
+            }
+        }
+    }
+
+    /// Steps back until exactly `count` instructions have been executed.
+    pub fn rewind_to(&mut self, count: usize) -> bool {
+        while self.instruction_count > count {
+            if !self.step_back() {
+                return false;
+            }
+        }
+        self.instruction_count == count
+    }
+
+    fn get_parameter_indices(&mut self, instruction: &Instruction, parameter_count: usize) -> (usize, usize, usize) {
+        let (pm1, pm2, pm3) = instruction.parameter_modes;
+        let mut ix = 0;
+        if parameter_count > 0 {
+            ix = match pm1 {
+                ParameterMode::Position     => self.code[self.index + 1] as usize,
+                ParameterMode::Immediate    => self.index + 1,
+                ParameterMode::Relative     => (self.relative_base as i64 + self.code[self.index + 1]) as usize
+            };
+        }
+        let mut iy = 0;
+        if parameter_count > 1 {
+            iy = match pm2 {
+                ParameterMode::Position     => self.code[self.index + 2] as usize,
+                ParameterMode::Immediate    => self.index + 2,
+                ParameterMode::Relative     => (self.relative_base as i64 + self.code[self.index + 2]) as usize 
+            };
+        }
+        let mut iz = 0;
+        if parameter_count > 2 {
+            iz = match pm3 {
+                ParameterMode::Position     => self.code[self.index + 3] as usize,
+                ParameterMode::Immediate    => self.index + 3,
+                ParameterMode::Relative     => (self.relative_base as i64 + self.code[self.index + 3]) as usize 
+            };
+        }
+        let max_index = std::cmp::max(ix, std::cmp::max(iy, iz));
+        if max_index >= self.code.len() {
+            if self.debug_mode {
+                println!("Memory allocation:");
+                println!("\tOld memort size:\t{:?}", self.code.len());
+            }
+            Arc::make_mut(&mut self.code).extend(vec![0; 2 * max_index]);
+            if self.debug_mode {
+                println!("\tNew memory size:\t{:?}", self.code.len());
+            }
+        }
+        (ix, iy, iz)
+    }
+
+    fn get_next_instruction(&self) -> Instruction {
+        if self.debug_mode {
+            if let Some(calls) = &self.calls {
+                print!("{}\t", calls.symbol(self.index));
+            }
+            print!("{}\t->\t", self.code[self.index]);
+        }
+        decode(self.code[self.index])
+    }
+
+    pub fn process(&mut self) {
+        while self.state == State::Idle {
+            self.execute();
+        }
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) {
+        if self.state == State::Idle {
+            self.execute();
+        }
+    }
+
+    fn execute(&mut self) {
+        let start = self.index;
+        let relative_base = self.relative_base;
+        if let Some(history) = &mut self.history {
+            history.push(Step::new(self.index, self.relative_base, self.code.len()));
+        }
+        let instruction = self.get_next_instruction();
+        let destination = match &self.calls {
+            Some(_) => CallStack::destination(&self.code, start, &instruction, relative_base),
+            None => None
+        };
+        if self.protection.is_some() {
+            // Marked up front, so an instruction overwriting itself counts as well.
+            let len = self.instruction_len(&instruction);
+            if let Some(protection) = &mut self.protection {
+                protection.executed(start, len);
+            }
+        }
+        match instruction.op_code {
+            OpCode::Add => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::Add);
+                }
+                let (ix, iy, iz) = self.get_parameter_indices(&instruction, 3);
+                let value = self.read(ix) + self.read(iy);
+                self.write(iz, value);
+                self.index += 4;
+            },
+            OpCode::Mul => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::Mul);
+                }
+                let (ix, iy, iz) =  self.get_parameter_indices(&instruction, 3);
+                let value = self.read(ix) * self.read(iy);
+                self.write(iz, value);
+                self.index += 4;
+            },
+            OpCode::In => { 
+                if self.debug_mode {
+                    println!("{:?}", OpCode::In);
+                }
+                let (ix, _, _) =  self.get_parameter_indices(&instruction, 1);
+                match self.input.front().copied() {
+                    Some(v) => {
+                        self.write(ix, v);
+                        // A trapped write leaves the input for when the instruction runs again.
+                        if !matches!(self.state, State::Error(_)) {
+                            self.input.pop_front();
+                            self.record(|step| step.inputs.push(v));
+                        }
+                        self.index += 2;
+                    },
+                    None => self.state = State::WaitForInput
+                }
+            },
+            OpCode::Out => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::Out);
+                }
+                let (ix, _, _) =  self.get_parameter_indices(&instruction, 1);
+                let value = self.read(ix);
+                self.output.push_back(value);
+                self.record(|step| step.outputs += 1);
+                self.index += 2;
+            },
+            OpCode::JumpIfTrue => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::JumpIfTrue);
+                }
+                let (ix, iy, _) =  self.get_parameter_indices(&instruction, 2);
+                if self.read(ix) != 0 {
+                    self.record_branch(true);
+                    self.index = self.read(iy) as usize;
+                } else {
+                    self.record_branch(false);
+                    self.index += 3;
+                }
+            },
+            OpCode::JumpIfFalse => {
+                let (ix, iy, _) =  self.get_parameter_indices(&instruction, 2);
+                if self.debug_mode {
+                    println!("{:?}", OpCode::JumpIfFalse);
+                }
+                if self.read(ix) != 0 {
+                    self.record_branch(false);
+                    self.index += 3;
+                } else {
+                    self.record_branch(true);
+                    self.index = self.read(iy) as usize;
+                }
+            },
+            OpCode::LessThan => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::LessThan);
+                }
+                let (ix, iy, iz) =  self.get_parameter_indices(&instruction, 3);
+                if self.read(ix) < self.read(iy) {
+                    self.write(iz, 1)
+                } else {
+                    self.write(iz, 0)
+                }
+                self.index += 4;
+            },
+            OpCode::Equals => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::Equals);
+                }
+                let (ix, iy, iz) =  self.get_parameter_indices(&instruction, 3);
+                if self.read(ix) == self.read(iy) {
+                    self.write(iz, 1)
+                } else {
+                    self.write(iz, 0)
+                }
+                self.index += 4;
+            },
+            OpCode::AdjustRelativeBase => {
+                if self.debug_mode {
+                    println!("{:?}", OpCode::AdjustRelativeBase);
+                }
+                let (ix, _, _) =  self.get_parameter_indices(&instruction, 1);
+                self.relative_base = (self.relative_base as i64 + self.read(ix)) as usize;
+                self.index += 2;
+            },
+            OpCode::Halt => self.state = State::Halt,
+            OpCode::Err(ref v) => {
+                match self.extensions.iter().position(|e| v.parse::<i64>() == Ok(e.op_code)) {
+                    Some(i) => self.run_extension(&instruction, i),
+                    None => self.state = State::Error(v.clone())
+                }
+            }
+        };
+        if self.protection.as_mut().map(|p| p.take_trap()).unwrap_or(false) {
+            // The write did not happen, the instruction runs again once the error is cleared.
+            self.index = start;
+            if let Some(history) = &mut self.history {
+                history.pop();
+            }
+            return;
+        }
+        if self.state == State::WaitForInput {
+            // Nothing was executed, the instruction runs again once input arrives.
+            if let Some(history) = &mut self.history {
+                history.pop();
+            }
+        } else {
+            self.instruction_count += 1;
+            if self.coverage.is_some() {
+                let len = self.instruction_len(&instruction);
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.hit(start, len);
+                }
+            }
+            let code = &self.code;
+            if let Some(calls) = &mut self.calls {
+                let written = destination.and_then(|d| code.get(d)).copied();
+                calls.observe(start, &instruction, written, relative_base, self.index, self.relative_base);
+            }
+            for mapping in &mut self.devices {
+                mapping.device.tick();
+            }
+        }
+    }
+
+    fn instruction_len(&self, instruction: &Instruction) -> usize {
+        match &instruction.op_code {
+            OpCode::Err(v) => self.extensions.iter()
+                                             .find(|e| v.parse::<i64>() == Ok(e.op_code))
+                                             .map(|e| e.roles.len() + 1)
+                                             .unwrap_or(1),
+            op => op.parameter_count() + 1
+        }
+    }
+
+    fn run_extension(&mut self, instruction: &Instruction, i: usize) {
+        let extension = self.extensions[i].clone();
+        if self.debug_mode {
+            println!("{}", extension.name);
+        }
+        let (pm1, pm2, pm3) = instruction.parameter_modes;
+        let modes = [pm1, pm2, pm3];
+        for (role, mode) in extension.roles.iter().zip(modes.iter()) {
+            if let (Role::Write, ParameterMode::Immediate) = (role, mode) {
+                self.state = State::Error(format!("{} writes to an immediate parameter", extension.name));
+                return;
+            }
+        }
+        let (ix, iy, iz) = self.get_parameter_indices(instruction, extension.roles.len());
+        let index = self.index;
+        let relative_base = self.relative_base;
+        let mut context = Context {
+            program: self,
+            parameters: [ix, iy, iz],
+            roles: &extension.roles,
+            index,
+            relative_base
+        };
+        let effect = (extension.handler)(&mut context);
+        if let State::Error(_) = self.state {
+            // A trapped write, the instruction runs again once the error is cleared.
+            return;
+        }
+        match effect {
+            Effect::Next => self.index += extension.roles.len() + 1,
+            Effect::Jump(target) => self.index = target,
+            Effect::WaitForInput => self.state = State::WaitForInput,
+            Effect::Halt => self.state = State::Halt,
+            Effect::Error(e) => self.state = State::Error(e)
+        }
+    }
+}
+
+
+impl Builder {
+    pub fn debug_mode(mut self, debug_mode: bool) -> Builder {
+        self.debug_mode = debug_mode;
+        self
+    }
+
+    /// Registers a custom instruction under `op_code`, taking one parameter per role.
+    pub fn opcode<F>(mut self, op_code: i64, name: &str, roles: &[Role], handler: F) -> Builder
+    where F: Fn(&mut Context) -> Effect + Send + Sync + 'static {
+        self.extensions.push(Extension {
+            op_code,
+            name: String::from(name),
+            roles: roles.to_vec(),
+            handler: Arc::new(handler)
+        });
+        self
+    }
+
+    /// Maps the addresses `start..start + len` to `device`.
+    pub fn device<D: Device>(mut self, start: usize, len: usize, device: D) -> Builder {
+        self.devices.push(Mapping { start, len, device: Box::new(device) });
+        self
+    }
+
+    pub fn build(self) -> Result<Program, String> {
+        extension::validate(&self.extensions)?;
+        device::validate(&self.devices)?;
+        let mut program = Program::new(self.code, self.debug_mode);
+        program.extensions = self.extensions;
+        program.devices = self.devices;
+        Ok(program)
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+/// What happens when a program writes to its own code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Policy {
+    // Record the write and carry on.
+    Report,
+    // Stop with an error before the write happens.
+    Trap
+}
+
+/// A write into code: `address` held `old` and `writer` stored `new` there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modification {
+    pub address: usize,
+    pub writer: usize,
+    pub old: i64,
+    pub new: i64,
+    pub instruction_count: usize,
+    // Whether the address had been executed, otherwise it only belongs to the image.
+    pub executed: bool
+}
+
+/// Tracks which addresses were executed, as opcode or parameter, and the writes to them.
+/// With `protect_image` every address of the image as it was when protection started
+/// counts as code, executed or not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteProtection {
+    pub policy: Policy,
+    pub image: usize,
+    pub modifications: Vec<Modification>,
+    executed: Vec<bool>,
+    trapped: bool
+}
+
+impl WriteProtection {
+    pub fn new(policy: Policy, image: usize) -> WriteProtection {
+        WriteProtection { policy, image, modifications: Vec::new(), executed: Vec::new(), trapped: false }
+    }
+
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.executed.get(address).copied().unwrap_or(false)
+    }
+
+    pub(crate) fn executed(&mut self, start: usize, len: usize) {
+        if self.executed.len() < start + len {
+            self.executed.resize(start + len, false);
+        }
+        for flag in &mut self.executed[start..start + len] {
+            *flag = true;
+        }
+    }
+
+    // Records a write to code. Returns the error to stop with if the write is trapped.
+    pub(crate) fn check(&mut self, address: usize, old: i64, new: i64, writer: usize, instruction_count: usize) -> Result<(), String> {
+        let executed = self.is_executed(address);
+        if !executed && address >= self.image {
+            return Ok(());
+        }
+        self.modifications.push(Modification { address, writer, old, new, instruction_count, executed });
+        match self.policy {
+            Policy::Report => Ok(()),
+            Policy::Trap => {
+                self.trapped = true;
+                let target = if executed { "executed code" } else { "the program image" };
+                Err(format!("write to {} at {} by the instruction at {}", target, address, writer))
+            }
+        }
+    }
+
+    // Whether the last write was trapped, clearing the flag.
+    pub(crate) fn take_trap(&mut self) -> bool {
+        std::mem::replace(&mut self.trapped, false)
+    }
+
+    /// One line per written address and writer, in the order they first happened.
+    pub fn summary(&self) -> String {
+        let mut groups: Vec<(usize, usize, usize, bool)> = Vec::new();
+        for m in &self.modifications {
+            match groups.iter_mut().find(|g| g.0 == m.address && g.1 == m.writer) {
+                Some(group) => {
+                    group.2 += 1;
+                    group.3 |= m.executed;
+                },
+                None => groups.push((m.address, m.writer, 1, m.executed))
+            }
+        }
+        let mut text = format!("{} write{} into code\n", self.modifications.len(), if self.modifications.len() == 1 { "" } else { "s" });
+        for (address, writer, count, executed) in groups {
+            writeln!(text, "  [{}] written {} time{} by the instruction at {}, {}", address, count, if count == 1 { "" } else { "s" }, writer,
+                     if executed { "executed" } else { "image only" }).unwrap();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Modification, Policy};
+    use crate::library::link;
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_report() {
+        // Overwrites its own opcode, then writes to data after the halt.
+        let mut program = Program::new(vec![1101,1,1,0,1101,7,0,9,99,0], false);
+        program.enable_write_protection(Policy::Report, false);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        let protection = program.write_protection().unwrap();
+        assert_eq!(vec![Modification { address: 0, writer: 0, old: 1101, new: 2, instruction_count: 0, executed: true }], protection.modifications);
+        assert_eq!("1 write into code\n  [0] written 1 time by the instruction at 0, executed\n", protection.summary());
+
+        let mut program = Program::new(vec![1101,1,1,0,1101,7,0,9,99,0], false);
+        program.enable_write_protection(Policy::Report, true);
+        program.process();
+        let addresses = program.write_protection().unwrap().modifications.iter().map(|m| (m.address, m.executed)).collect::<Vec<_>>();
+        assert_eq!(vec![(0, true), (9, false)], addresses);
+    }
+
+    #[test]
+    fn test_trap() {
+        let mut program = Program::new(vec![1101,7,0,9,1101,1,1,0,99,0], false);
+        program.enable_write_protection(Policy::Trap, false);
+        program.process();
+        assert_eq!(State::Error(String::from("write to executed code at 0 by the instruction at 4")), program.state);
+        assert_eq!(4, program.index());
+        assert_eq!(1101, program.memory()[0]);
+        assert_eq!(7, program.memory()[9]);
+
+        program.write_protection_mut().unwrap().policy = Policy::Report;
+        program.set_registers(4, 0);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(2, program.write_protection().unwrap().modifications.len());
+
+        // A trapped input keeps its value for when the instruction runs again.
+        let mut program = Program::new(vec![3,0,99], false);
+        program.enable_write_protection(Policy::Trap, false);
+        program.push_input(5);
+        program.process();
+        assert_eq!(State::Error(String::from("write to executed code at 0 by the instruction at 0")), program.state);
+        assert_eq!(vec![5], program.input_queue().iter().copied().collect::<Vec<i64>>());
+        program.write_protection_mut().unwrap().policy = Policy::Report;
+        program.set_registers(0, 0);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        assert_eq!(5, program.memory()[0]);
+        assert!(program.input_queue().is_empty());
+    }
+
+    #[test]
+    fn test_patched_routine() {
+        // print_string patches the address it loads from into its own code for every word.
+        let code = link("
+        AdjustRelativeBase image_end
+        Add _text, 0, [rb+2]
+        Add _back, 0, [rb+0]
+        JumpIfTrue 1, print_string
+_back:  Halt
+_text:  data 104, 105, 10, 0
+").unwrap();
+        let mut program = Program::new(code.clone(), false);
+        program.enable_write_protection(Policy::Report, false);
+        program.process();
+        assert_eq!(State::Halt, program.state);
+        let protection = program.write_protection().unwrap();
+        assert_eq!(3, protection.modifications.len());
+        assert_eq!(1, protection.summary().lines().skip(1).count());
+
+        // Which is why it can not run under `Policy::Trap`.
+        let mut program = Program::new(code, false);
+        program.enable_write_protection(Policy::Trap, false);
+        program.process();
+        assert!(matches!(program.state, State::Error(_)));
+        assert_eq!(1, program.output_queue().len());
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::program::{Program, State};
+
+pub type DecodeFn<E> = Arc<dyn Fn(&[i64]) -> Result<E, String> + Send + Sync>;
+
+/// Groups program outputs into tuples of `size` values and turns every complete tuple into
+/// an event of type `E`.
+pub struct Decoder<E> {
+    size: usize,
+    decode: DecodeFn<E>,
+    buffer: Vec<i64>
+}
+
+impl<E> Decoder<E> {
+    pub fn new<F>(size: usize, decode: F) -> Decoder<E>
+        where F: Fn(&[i64]) -> Result<E, String> + Send + Sync + 'static {
+        assert!(size > 0, "tuple size must be positive");
+        Decoder { size, decode: Arc::new(decode), buffer: Vec::with_capacity(size) }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Values of the tuple that is not complete yet.
+    pub fn pending(&self) -> &[i64] {
+        &self.buffer
+    }
+
+    /// Adds one output value, decoding the tuple once it is complete.
+    pub fn push(&mut self, value: i64) -> Result<Option<E>, String> {
+        self.buffer.push(value);
+        if self.buffer.len() < self.size {
+            return Ok(None);
+        }
+        let result = (self.decode)(&self.buffer).map_err(|e| format!("can not decode {:?}: {}", self.buffer, e));
+        self.buffer.clear();
+        result.map(Some)
+    }
+
+    /// Errors if a tuple was started but never completed, discarding its values.
+    pub fn finish(&mut self) -> Result<(), String> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let error = format!("incomplete tuple {:?}, expected {} values", self.buffer, self.size);
+        self.buffer.clear();
+        Err(error)
+    }
+
+    /// Pops every output of `program` and decodes it. A partial tuple is kept for the next
+    /// call unless the program has halted, which makes it an error. Tuples that fail to
+    /// decode are skipped, the events are returned together with the first error.
+    pub fn drain(&mut self, program: &mut Program) -> (Vec<E>, Option<String>) {
+        let mut events = Vec::new();
+        let mut error = None;
+        while let Some(value) = program.pop_output() {
+            match self.push(value) {
+                Ok(Some(event)) => events.push(event),
+                Ok(None)        => {},
+                Err(e)          => { error.get_or_insert(e); }
+            }
+        }
+        if program.state == State::Halt {
+            if let Err(e) = self.finish() {
+                error.get_or_insert(e);
+            }
+        }
+        (events, error)
+    }
+}
+
+impl<E> Clone for Decoder<E> {
+    fn clone(&self) -> Decoder<E> {
+        Decoder { size: self.size, decode: self.decode.clone(), buffer: self.buffer.clone() }
+    }
+}
+
+impl<E> fmt::Debug for Decoder<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoder").field("size", &self.size).field("buffer", &self.buffer).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decoder;
+    use crate::program::Program;
+
+    #[derive(Debug, PartialEq)]
+    struct Tile {
+        x: i64,
+        y: i64,
+        id: i64
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Turn {
+        Left,
+        Right
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Paint {
+        color: i64,
+        turn: Turn
+    }
+
+    fn tiles() -> Decoder<Tile> {
+        Decoder::new(3, |t| Ok(Tile { x: t[0], y: t[1], id: t[2] }))
+    }
+
+    fn paint() -> Decoder<Paint> {
+        Decoder::new(2, |t| match t[1] {
+            0 => Ok(Paint { color: t[0], turn: Turn::Left }),
+            1 => Ok(Paint { color: t[0], turn: Turn::Right }),
+            v => Err(format!("unknown turn {}", v))
+        })
+    }
+
+    #[test]
+    fn test_tiles() {
+        // Outputs two tiles, the second one split around an input.
+        let mut program = Program::new(vec![104,1,104,2,104,3,104,4,3,20,104,5,104,6,99], false);
+        let mut decoder = tiles();
+        program.process();
+        assert_eq!((vec![Tile { x: 1, y: 2, id: 3 }], None), decoder.drain(&mut program));
+        assert_eq!(&[4], decoder.pending());
+        program.push_input(0);
+        program.process();
+        assert_eq!((vec![Tile { x: 4, y: 5, id: 6 }], None), decoder.drain(&mut program));
+        assert!(decoder.finish().is_ok());
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut program = Program::new(vec![104,1,104,0,104,0,104,1,99], false);
+        program.process();
+        let (events, error) = paint().drain(&mut program);
+        assert_eq!(vec![Paint { color: 1, turn: Turn::Left }, Paint { color: 0, turn: Turn::Right }], events);
+        assert_eq!(None, error);
+        // A tuple that does not decode does not take the others with it.
+        let mut program = Program::new(vec![104,1,104,7,104,0,104,1,99], false);
+        program.process();
+        let (events, error) = paint().drain(&mut program);
+        assert_eq!(vec![Paint { color: 0, turn: Turn::Right }], events);
+        assert!(error.unwrap().contains("unknown turn 7"));
+        let mut decoder = paint();
+        assert_eq!(Ok(None), decoder.push(1));
+        assert!(decoder.push(7).unwrap_err().contains("unknown turn 7"));
+    }
+
+    #[test]
+    fn test_incomplete_at_halt() {
+        let mut program = Program::new(vec![104,1,104,2,104,3,104,4,99], false);
+        program.process();
+        let mut decoder = tiles();
+        let (events, error) = decoder.drain(&mut program);
+        assert_eq!(vec![Tile { x: 1, y: 2, id: 3 }], events);
+        assert!(error.unwrap().contains("incomplete tuple [4]"));
+        assert!(decoder.pending().is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::fuzz::panic_message;
+use crate::program::{Program, State};
+use crate::protocol::Decoder;
+
+pub type MachineId = usize;
+
+/// Where the outputs of a machine go.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Route {
+    // Outputs stay in the output queue of the machine.
+    Keep,
+    // Every output becomes an input of the machine.
+    Pipe(MachineId),
+    // Every output becomes an input of each of the machines.
+    Broadcast(Vec<MachineId>),
+    // Outputs are grouped into packets of this many words: a destination, then the words
+    // delivered to it. The size has to be positive.
+    Packet(usize)
+}
+
+/// How a run of the scheduler ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    // Every machine halted or stopped with an error.
+    Finished,
+    // These machines wait for input and no machine is left that could send them any.
+    Deadlock(Vec<MachineId>),
+    // The slices ran out while machines were still ready to run.
+    Running
+}
+
+struct Machine {
+    program: Program,
+    route: Route,
+    // Set for `Route::Packet`.
+    packets: Option<Decoder<Vec<i64>>>,
+    queued: bool
+}
+
+fn packets(route: &Route) -> Option<Decoder<Vec<i64>>> {
+    match route {
+        Route::Packet(len) => {
+            assert!(*len > 0, "packet size must be positive");
+            Some(Decoder::new(*len, |t| Ok(t.to_vec())))
+        },
+        _ => None
+    }
+}
+
+/// Runs many programs on a single thread. Machines take turns in slices of at most
+/// `time_slice` instructions, a machine waiting for input only runs again once something
+/// is delivered to it.
+pub struct Scheduler {
+    machines: Vec<Machine>,
+    ready: VecDeque<MachineId>,
+    pub time_slice: usize,
+    pub slices: usize,
+    // Packets sent to machines that do not exist, with their sender.
+    pub undelivered: Vec<(MachineId, Vec<i64>)>
+}
+
+impl Scheduler {
+    pub fn new(time_slice: usize) -> Scheduler {
+        assert!(time_slice > 0, "time slice must be positive");
+        Scheduler { machines: Vec::new(), ready: VecDeque::new(), time_slice, slices: 0, undelivered: Vec::new() }
+    }
+
+    pub fn spawn(&mut self, program: Program, route: Route) -> MachineId {
+        let id = self.machines.len();
+        let packets = packets(&route);
+        self.machines.push(Machine { program, route, packets, queued: false });
+        self.wake(id);
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn program(&self, id: MachineId) -> &Program {
+        &self.machines[id].program
+    }
+
+    /// Changing the state of a machine directly is noticed the next time something is
+    /// delivered to it.
+    pub fn program_mut(&mut self, id: MachineId) -> &mut Program {
+        &mut self.machines[id].program
+    }
+
+    /// A packet started under the old route is dropped.
+    pub fn set_route(&mut self, id: MachineId, route: Route) {
+        let machine = &mut self.machines[id];
+        machine.packets = packets(&route);
+        machine.route = route;
+    }
+
+    pub fn push_input(&mut self, id: MachineId, value: i64) {
+        self.deliver(MachineId::MAX, id, &[value]);
+    }
+
+    fn wake(&mut self, id: MachineId) {
+        let machine = &mut self.machines[id];
+        if !machine.queued && machine.program.state == State::Idle {
+            machine.queued = true;
+            self.ready.push_back(id);
+        }
+    }
+
+    fn deliver(&mut self, source: MachineId, destination: MachineId, values: &[i64]) {
+        match self.machines.get_mut(destination) {
+            Some(machine) => {
+                for value in values {
+                    machine.program.push_input(*value);
+                }
+                self.wake(destination);
+            },
+            None => self.undelivered.push((source, values.to_vec()))
+        }
+    }
+
+    fn route(&mut self, id: MachineId) {
+        let mut outputs = Vec::new();
+        let machine = &mut self.machines[id];
+        if machine.route == Route::Keep {
+            return;
+        }
+        while let Some(value) = machine.program.pop_output() {
+            outputs.push(value);
+        }
+        match machine.route.clone() {
+            Route::Keep => {},
+            Route::Pipe(destination) => self.deliver(id, destination, &outputs),
+            Route::Broadcast(destinations) => {
+                for destination in destinations {
+                    self.deliver(id, destination, &outputs);
+                }
+            },
+            Route::Packet(_) => {
+                let machine = &mut self.machines[id];
+                let decoder = machine.packets.as_mut().unwrap();
+                let packets = outputs.into_iter().filter_map(|v| decoder.push(v).unwrap_or(None)).collect::<Vec<Vec<i64>>>();
+                // A machine that stops halfway through a packet never completes it.
+                if matches!(machine.program.state, State::Halt | State::Error(_)) && !decoder.pending().is_empty() {
+                    self.undelivered.push((id, decoder.pending().to_vec()));
+                    decoder.finish().ok();
+                }
+                for packet in packets {
+                    let destination = usize::try_from(packet[0]).unwrap_or(MachineId::MAX);
+                    self.deliver(id, destination, &packet[1..]);
+                }
+            }
+        }
+    }
+
+    /// Runs the next ready machine for one slice and routes its outputs. Returns false if no
+    /// machine is ready.
+    pub fn step(&mut self) -> bool {
+        let id = match self.ready.pop_front() {
+            Some(id) => id,
+            None => return false
+        };
+        let machine = &mut self.machines[id];
+        machine.queued = false;
+        let time_slice = self.time_slice;
+        let slice = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..time_slice {
+                if machine.program.state != State::Idle {
+                    break;
+                }
+                machine.program.step();
+            }
+        }));
+        // A panicking interpreter only stops its own machine.
+        if let Err(e) = slice {
+            machine.program.state = State::Error(format!("panic: {}", panic_message(e)));
+        }
+        self.slices += 1;
+        self.route(id);
+        // Still running when the slice ended, back to the end of the queue.
+        self.wake(id);
+        true
+    }
+
+    /// Where the machines are right now, see `Status`.
+    pub fn status(&self) -> Status {
+        if !self.ready.is_empty() {
+            return Status::Running;
+        }
+        let waiting = (0..self.machines.len()).filter(|id| self.machines[*id].program.state == State::WaitForInput).collect::<Vec<MachineId>>();
+        if waiting.is_empty() {
+            Status::Finished
+        } else {
+            Status::Deadlock(waiting)
+        }
+    }
+
+    /// Runs at most `max_slices` slices, stopping early once no machine is ready.
+    pub fn run(&mut self, max_slices: usize) -> Status {
+        for _ in 0..max_slices {
+            if !self.step() {
+                break;
+            }
+        }
+        self.status()
+    }
+
+    /// Machines that stopped with an error, with the error.
+    pub fn errors(&self) -> Vec<(MachineId, String)> {
+        self.machines.iter().enumerate().filter_map(|(id, m)| match &m.program.state {
+            State::Error(e) => Some((id, e.clone())),
+            _               => None
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{Route, Scheduler, Status};
+    use crate::program::{Program, State};
+
+    #[test]
+    fn test_feedback_loop() {
+        // The amplifiers of day 7, part 2: five machines in a ring.
+        let code = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
+        let mut scheduler = Scheduler::new(3);
+        for (i, phase) in [9, 8, 7, 6, 5].iter().enumerate() {
+            let mut program = Program::new(code.clone(), false);
+            program.push_input(*phase);
+            scheduler.spawn(program, Route::Pipe((i + 1) % 5));
+        }
+        scheduler.push_input(0, 0);
+        assert_eq!(Status::Finished, scheduler.run(10_000));
+        assert_eq!(vec![139629729], scheduler.program(0).input_queue().iter().copied().collect::<Vec<i64>>());
+        assert!(scheduler.slices > 5);
+    }
+
+    #[test]
+    fn test_many_machines() {
+        // Each machine adds one to what it reads and passes it on.
+        let code = Arc::new(vec![3,9,1001,9,1,9,4,9,99,0]);
+        let mut scheduler = Scheduler::new(100);
+        for id in 0..5000 {
+            let route = if id == 4999 { Route::Keep } else { Route::Pipe(id + 1) };
+            scheduler.spawn(Program::shared(code.clone(), false), route);
+        }
+        assert_eq!(Status::Deadlock((0..5000).collect()), scheduler.run(100_000));
+        scheduler.push_input(0, 0);
+        assert_eq!(Status::Finished, scheduler.run(100_000));
+        assert_eq!(Some(5000), scheduler.program_mut(4999).pop_output());
+    }
+
+    #[test]
+    fn test_deadlock() {
+        // Two machines that both wait for the other before sending anything.
+        let code = vec![3,7,4,7,1105,1,0,0];
+        let mut scheduler = Scheduler::new(10);
+        scheduler.spawn(Program::new(code.clone(), false), Route::Pipe(1));
+        scheduler.spawn(Program::new(code, false), Route::Pipe(0));
+        assert_eq!(Status::Deadlock(vec![0, 1]), scheduler.run(100));
+        // Once a value is in the ring they pass it back and forth forever.
+        scheduler.push_input(0, 7);
+        assert_eq!(Status::Running, scheduler.run(100));
+        assert_eq!(100, scheduler.slices - 2);
+    }
+
+    #[test]
+    fn test_time_slices() {
+        // A machine spinning forever does not keep the others from running.
+        let mut scheduler = Scheduler::new(5);
+        let spinner = scheduler.spawn(Program::new(vec![1105,1,0], false), Route::Keep);
+        let counter = scheduler.spawn(Program::new(vec![104,1,104,2,104,3,99], false), Route::Broadcast(vec![2, 3]));
+        let sink = scheduler.spawn(Program::new(vec![3,5,1105,1,0,0], false), Route::Keep);
+        assert_eq!(Status::Running, scheduler.run(10));
+        assert_eq!(State::Idle, scheduler.program(spinner).state);
+        assert_eq!(State::Halt, scheduler.program(counter).state);
+        assert_eq!(State::WaitForInput, scheduler.program(sink).state);
+        assert_eq!(vec![(counter, vec![1, 2, 3])], scheduler.undelivered);
+        assert!(scheduler.errors().is_empty());
+    }
+
+    #[test]
+    fn test_packets() {
+        // Machine 0 sends (1, 10, 11) and (9, 0, 0); machine 1 adds the pair and halts.
+        let mut scheduler = Scheduler::new(2);
+        scheduler.spawn(Program::new(vec![104,1,104,10,104,11,104,9,104,0,104,0,99], false), Route::Packet(3));
+        scheduler.spawn(Program::new(vec![3,9,3,10,1,9,10,11,42,0,0,0], false), Route::Keep);
+        assert_eq!(Status::Finished, scheduler.run(100));
+        assert_eq!(21, scheduler.program(1).memory()[11]);
+        assert_eq!(vec![(1, String::from("42"))], scheduler.errors());
+        assert_eq!(vec![(0, vec![0, 0])], scheduler.undelivered);
+
+        // Halting halfway through a packet leaves the partial packet undelivered.
+        let mut scheduler = Scheduler::new(2);
+        scheduler.spawn(Program::new(vec![104,1,104,5,104,1,99], false), Route::Packet(2));
+        scheduler.spawn(Program::new(vec![3,0,3,0,99], false), Route::Keep);
+        assert_eq!(Status::Deadlock(vec![1]), scheduler.run(100));
+        assert_eq!(vec![(0, vec![1])], scheduler.undelivered);
+    }
+
+    #[test]
+    #[should_panic(expected = "packet size must be positive")]
+    fn test_empty_packets() {
+        Scheduler::new(1).spawn(Program::new(vec![99], false), Route::Packet(0));
+    }
+
+    #[test]
+    fn test_panic() {
+        // An Add missing its operands panics the interpreter, the other machine still runs.
+        let mut scheduler = Scheduler::new(10);
+        scheduler.spawn(Program::new(vec![1101,1], false), Route::Keep);
+        scheduler.spawn(Program::new(vec![104,7,99], false), Route::Keep);
+        assert_eq!(Status::Finished, scheduler.run(100));
+        let errors = scheduler.errors();
+        assert_eq!(1, errors.len());
+        assert!(errors[0].1.starts_with("panic: "), "{:?}", errors);
+        assert_eq!(Some(7), scheduler.program_mut(1).pop_output());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::program::{IntCode, Program, State};
+
+/// Input consumed or output produced, with the instruction count at which it happened.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    Input(usize, i64),
+    Output(usize, i64)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    pub events: Vec<Event>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Event>,
+    pub actual: Option<Event>
+}
+
+/// Drives a program one instruction at a time so every input and output is recorded
+/// with its exact instruction count.
+pub struct Recorder {
+    pub program: Program,
+    pending: VecDeque<i64>,
+    session: Session
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    pub fn inputs(&self) -> Vec<i64> {
+        self.events.iter().filter_map(|e| match e {
+            Event::Input(_, v)  => Some(*v),
+            _                   => None
+        }).collect()
+    }
+
+    pub fn parse(content: &str) -> Result<Session, String> {
+        let mut session = Session::new();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            let parsed = match fields.as_slice() {
+                [kind, at, value] => at.parse::<usize>().ok().zip(value.parse::<i64>().ok()).map(|(a, v)| (*kind, a, v)),
+                _ => None
+            };
+            let event = match parsed {
+                Some(("in", at, value))     => Event::Input(at, value),
+                Some(("out", at, value))    => Event::Output(at, value),
+                _ => return Err(format!("line {}: can not parse {:?}", n + 1, line))
+            };
+            session.events.push(event);
+        }
+        Ok(session)
+    }
+
+    /// First event where `actual` differs from this recording.
+    pub fn compare(&self, actual: &Session) -> Option<Divergence> {
+        let len = self.events.len().max(actual.events.len());
+        (0..len).find(|i| self.events.get(*i) != actual.events.get(*i)).map(|index| Divergence {
+            index,
+            expected: self.events.get(index).copied(),
+            actual: actual.events.get(index).copied()
+        })
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# intcode session")?;
+        for event in &self.events {
+            match event {
+                Event::Input(at, value)     => writeln!(f, "in {} {}", at, value)?,
+                Event::Output(at, value)    => writeln!(f, "out {} {}", at, value)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Recorder {
+    pub fn new(program: Program) -> Recorder {
+        Recorder { program, pending: VecDeque::new(), session: Session::new() }
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.pending.push_back(value);
+        self.program.push_input(value);
+    }
+
+    /// Runs until the program stops and returns the outputs it produced.
+    pub fn run(&mut self) -> Vec<i64> {
+        let mut outputs = Vec::new();
+        while self.program.state == State::Idle {
+            let at = self.program.instruction_count();
+            let waiting = self.program.pending_input();
+            self.program.step();
+            for _ in self.program.pending_input()..waiting {
+                if let Some(value) = self.pending.pop_front() {
+                    self.session.events.push(Event::Input(at, value));
+                }
+            }
+            while let Some(value) = self.program.pop_output() {
+                self.session.events.push(Event::Output(at, value));
+                outputs.push(value);
+            }
+        }
+        outputs
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+/// Reruns `code` feeding the recorded inputs whenever it waits and reports the first event
+/// that differs from `session`.
+pub fn replay(code: IntCode, session: &Session) -> Result<Session, Divergence> {
+    let mut recorder = Recorder::new(Program::new(code, false));
+    let mut inputs = session.inputs().into_iter();
+    loop {
+        recorder.run();
+        if recorder.program.state != State::WaitForInput {
+            break;
+        }
+        match inputs.next() {
+            Some(value) => recorder.push_input(value),
+            None        => break
+        }
+    }
+    match session.compare(&recorder.session) {
+        Some(divergence)    => Err(divergence),
+        None                => Ok(recorder.session)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replay, Event, Recorder, Session};
+    use crate::program::{Program, State};
+
+    // Outputs every non-zero input doubled, forever.
+    fn doubler() -> Vec<i64> {
+        vec![3,20,1006,20,11,1002,20,2,21,4,21,1105,1,0,99]
+    }
+
+    fn record(inputs: &[i64]) -> Session {
+        let mut recorder = Recorder::new(Program::new(doubler(), false));
+        for value in inputs {
+            recorder.run();
+            assert_eq!(State::WaitForInput, recorder.program.state);
+            recorder.push_input(*value);
+        }
+        recorder.run();
+        recorder.session().clone()
+    }
+
+    #[test]
+    fn test_record() {
+        let session = record(&[3, 5, 0]);
+        assert_eq!(vec![
+            Event::Input(0, 3), Event::Output(3, 6),
+            Event::Input(5, 5), Event::Output(8, 10),
+            Event::Input(10, 0)], session.events);
+        assert_eq!(Ok(session.clone()), Session::parse(&session.to_string()));
+    }
+
+    #[test]
+    fn test_replay() {
+        let session = record(&[3, 5, 0]);
+        assert!(replay(doubler(), &session).is_ok());
+        let mut changed = doubler();
+        changed[7] = 3;
+        let divergence = replay(changed, &session).unwrap_err();
+        assert_eq!(1, divergence.index);
+        assert_eq!(Some(Event::Output(3, 6)), divergence.expected);
+        assert_eq!(Some(Event::Output(3, 9)), divergence.actual);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Session::parse("# intcode session\nin 1\n").is_err());
+        assert!(Session::parse("jump 1 2\n").is_err());
+    }
+}
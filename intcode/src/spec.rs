@@ -0,0 +1,300 @@
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+use crate::fuzz::panic_message;
+use crate::image;
+use crate::program::{IntCode, Program, State};
+use crate::toml::Toml;
+
+// A spec file is TOML with one `[[test]]` table per case:
+//
+//     [[test]]
+//     name = "day 2"
+//     program = "day2.txt"            # relative to the spec, text or binary image
+//     # code = "1,9,10,3,2,3,11,0,99,30,40,50" instead of a file
+//     patches = { 1 = 12, 2 = 2 }     # written before running
+//     input = [1, 2]
+//     output = [3500]                 # left out to not check the output
+//     state = "Halt"                  # the default, or "WaitForInput" or "Error"
+//     error = "42"                    # part of the error message, implies "Error"
+//     panic = "out of bounds"         # part of the message the interpreter panics with
+//     max_steps = 1000000
+//
+//     [test.memory]                   # words expected after the run
+//     0 = 3500
+
+/// Steps a case may run if it does not set `max_steps`.
+pub const DEFAULT_MAX_STEPS: usize = 10_000_000;
+
+/// One case of a spec file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spec {
+    pub name: String,
+    pub code: IntCode,
+    pub patches: Vec<(usize, i64)>,
+    pub input: Vec<i64>,
+    pub output: Option<Vec<i64>>,
+    // An expected error message only has to be part of the actual one.
+    pub state: State,
+    // Set if the interpreter is expected to panic, with part of the message.
+    pub panic: Option<String>,
+    pub memory: Vec<(usize, i64)>,
+    pub max_steps: usize
+}
+
+/// The result of running a spec, passed if there are no failures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    pub failures: Vec<String>
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+fn integers(value: &Toml, key: &str) -> Result<Vec<i64>, String> {
+    value.as_array()
+         .ok_or_else(|| format!("`{}` is not an array", key))?
+         .iter()
+         .map(|v| v.as_i64().ok_or_else(|| format!("`{}` holds a value that is not an integer", key)))
+         .collect()
+}
+
+fn addresses(value: &Toml, key: &str) -> Result<Vec<(usize, i64)>, String> {
+    value.as_table()
+         .ok_or_else(|| format!("`{}` is not a table", key))?
+         .iter()
+         .map(|(address, v)| {
+             let address = address.parse::<usize>().map_err(|_| format!("`{}` has the invalid address {}", key, address))?;
+             let value = v.as_i64().ok_or_else(|| format!("`{}` holds a value that is not an integer", key))?;
+             Ok((address, value))
+         })
+         .collect()
+}
+
+fn case(test: &Toml, index: usize, dir: &Path) -> Result<Spec, String> {
+    let mut name = format!("test {}", index + 1);
+    let mut code = None;
+    let mut spec_state = None;
+    let mut error = None;
+    let mut spec = Spec {
+        name: String::new(),
+        code: IntCode::new(),
+        patches: Vec::new(),
+        input: Vec::new(),
+        output: None,
+        state: State::Halt,
+        panic: None,
+        memory: Vec::new(),
+        max_steps: DEFAULT_MAX_STEPS
+    };
+    let members = test.as_table().ok_or("`test` has to be an array of tables")?;
+    for (key, value) in members {
+        let text = || value.as_str().ok_or_else(|| format!("`{}` is not a string", key));
+        match key.as_str() {
+            "name"      => name = String::from(text()?),
+            "code"      => code = Some(image::parse(text()?)?),
+            "program"   => {
+                let path: PathBuf = dir.join(text()?);
+                let bytes = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                code = Some(image::load(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?);
+            },
+            "patches"   => spec.patches = addresses(value, key)?,
+            "input"     => spec.input = integers(value, key)?,
+            "output"    => spec.output = Some(integers(value, key)?),
+            "state"     => spec_state = Some(String::from(text()?)),
+            "error"     => error = Some(String::from(text()?)),
+            "panic"     => spec.panic = Some(String::from(text()?)),
+            "memory"    => spec.memory = addresses(value, key)?,
+            "max_steps" => spec.max_steps = value.as_i64().filter(|n| *n > 0).ok_or("`max_steps` is not a positive integer")? as usize,
+            _           => return Err(format!("unknown key `{}`", key))
+        }
+    }
+    spec.name = name;
+    spec.code = code.ok_or_else(|| format!("{}: needs `program` or `code`", spec.name))?;
+    spec.state = match (spec_state.as_deref(), error) {
+        (None, None) | (Some("Halt"), None)            => State::Halt,
+        (Some("WaitForInput"), None)                   => State::WaitForInput,
+        (None, Some(message)) | (Some("Error"), Some(message)) => State::Error(message),
+        (Some("Error"), None)                          => State::Error(String::new()),
+        (Some(state), _)                               => return Err(format!("{}: invalid state `{}`", spec.name, state))
+    };
+    Ok(spec)
+}
+
+/// Parses a spec file, `dir` is where `program` paths are looked up.
+pub fn parse(text: &str, dir: &Path) -> Result<Vec<Spec>, String> {
+    let document = Toml::parse(text)?;
+    if let Some((key, _)) = document.as_table().unwrap().iter().find(|(k, _)| k != "test") {
+        return Err(format!("unknown key `{}`", key));
+    }
+    let tests = match document.get("test") {
+        Some(Toml::Array(tests)) => tests,
+        _ => return Err(String::from("no `[[test]]` tables"))
+    };
+    tests.iter().enumerate().map(|(i, test)| case(test, i, dir)).collect()
+}
+
+/// Reads and parses the spec file at `path`.
+pub fn load(path: &Path) -> Result<Vec<Spec>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    parse(&text, dir).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Describes where `actual` differs from `expected`, one line per differing index, at most
+/// `MAX_DIFF_LINES` of them.
+pub fn diff(expected: &[i64], actual: &[i64]) -> Option<String> {
+    const MAX_DIFF_LINES: usize = 10;
+    let first = (0..expected.len().max(actual.len())).find(|i| expected.get(*i) != actual.get(*i))?;
+    let mut text = format!("output differs from index {}, {} values expected, {} produced", first, expected.len(), actual.len());
+    let differing = (first..expected.len().max(actual.len())).filter(|i| expected.get(*i) != actual.get(*i)).collect::<Vec<usize>>();
+    for i in differing.iter().take(MAX_DIFF_LINES) {
+        let show = |v: Option<&i64>| v.map(|v| v.to_string()).unwrap_or_else(|| String::from("nothing"));
+        write!(text, "\n  [{}] expected {}, got {}", i, show(expected.get(*i)), show(actual.get(*i))).unwrap();
+    }
+    if differing.len() > MAX_DIFF_LINES {
+        write!(text, "\n  ... {} more", differing.len() - MAX_DIFF_LINES).unwrap();
+    }
+    Some(text)
+}
+
+impl Spec {
+    pub fn run(&self) -> Outcome {
+        let mut program = Program::new(self.code.clone(), false);
+        for (address, value) in &self.patches {
+            program.set_memory(*address, *value);
+        }
+        for value in &self.input {
+            program.push_input(*value);
+        }
+        let mut steps = 0;
+        let run = panic::catch_unwind(AssertUnwindSafe(|| {
+            while program.state == State::Idle && steps < self.max_steps {
+                program.step();
+                steps += 1;
+            }
+        }));
+        let mut failures = Vec::new();
+        let state_matches = match (&self.state, &program.state) {
+            (State::Error(expected), State::Error(actual)) => actual.contains(expected.as_str()),
+            (expected, actual) => expected == actual
+        };
+        match (run.map_err(panic_message), &self.panic) {
+            (Err(message), None) => failures.push(format!("panicked: {}", message)),
+            (Err(message), Some(expected)) if !message.contains(expected.as_str()) => {
+                failures.push(format!("expected a panic with {:?}, panicked: {}", expected, message));
+            },
+            (Err(_), Some(_)) => {},
+            (Ok(()), _) if program.state == State::Idle => failures.push(format!("did not stop within {} steps", self.max_steps)),
+            (Ok(()), Some(_)) => failures.push(format!("expected a panic, stopped in {:?}", program.state)),
+            (Ok(()), None) if !state_matches => failures.push(format!("expected state {:?}, stopped in {:?}", self.state, program.state)),
+            (Ok(()), None) => {}
+        }
+        let output = program.output_queue().iter().copied().collect::<Vec<i64>>();
+        if let Some(difference) = self.output.as_ref().and_then(|expected| diff(expected, &output)) {
+            failures.push(difference);
+        }
+        for (address, expected) in &self.memory {
+            let actual = program.memory().get(*address).copied().unwrap_or(0);
+            if actual != *expected {
+                failures.push(format!("memory [{}] expected {}, got {}", address, expected, actual));
+            }
+        }
+        Outcome { name: self.name.clone(), failures }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{diff, parse};
+    use crate::program::State;
+
+    #[test]
+    fn test_parse() {
+        let specs = parse(r#"
+[[test]]
+name = "day 2"
+code = "1,9,10,3,2,3,11,0,99,30,40,50"
+output = []
+
+[test.memory]
+0 = 3500
+
+[[test]]
+code = "3,0,99"
+state = "WaitForInput"
+
+[[test]]
+code = "42"
+error = "42"
+max_steps = 5
+"#, Path::new(".")).unwrap();
+        assert_eq!(3, specs.len());
+        assert_eq!("day 2", specs[0].name);
+        assert_eq!(vec![(0, 3500)], specs[0].memory);
+        assert_eq!(Some(vec![]), specs[0].output);
+        assert_eq!(State::Halt, specs[0].state);
+        assert_eq!("test 2", specs[1].name);
+        assert_eq!(None, specs[1].output);
+        assert_eq!(State::WaitForInput, specs[1].state);
+        assert_eq!(State::Error(String::from("42")), specs[2].state);
+        assert_eq!(5, specs[2].max_steps);
+        assert!(specs.iter().all(|s| s.run().passed()), "{:?}", specs.iter().map(|s| s.run()).collect::<Vec<_>>());
+
+        assert_eq!(Err(String::from("unknown key `outptu`")), parse("[[test]]\ncode = \"99\"\noutptu = [1]", Path::new(".")));
+        assert_eq!(Err(String::from("test 1: needs `program` or `code`")), parse("[[test]]\ninput = [1]", Path::new(".")));
+        assert_eq!(Err(String::from("`max_steps` is not a positive integer")), parse("[[test]]\ncode = \"99\"\nmax_steps = 0", Path::new(".")));
+        assert_eq!(Err(String::from("test 1: invalid state `Done`")), parse("[[test]]\ncode = \"99\"\nstate = \"Done\"", Path::new(".")));
+    }
+
+    #[test]
+    fn test_failures() {
+        let specs = parse(r#"
+[[test]]
+name = "echo"
+code = "3,7,4,7,1105,1,0,0"
+input = [1, 2]
+output = [1, 3, 4]
+patches = { 100 = 7 }
+memory = { 7 = 2, 100 = 8, 200 = 0 }
+
+[[test]]
+code = "1105,1,0"
+max_steps = 100
+
+[[test]]
+name = "add without operands"
+code = "1"
+panic = "out of bounds"
+
+[[test]]
+code = "1"
+"#, Path::new(".")).unwrap();
+        let outcome = specs[0].run();
+        assert_eq!("echo", outcome.name);
+        assert_eq!(vec![
+            String::from("expected state Halt, stopped in WaitForInput"),
+            String::from("output differs from index 1, 3 values expected, 2 produced\n  [1] expected 3, got 2\n  [2] expected 4, got nothing"),
+            String::from("memory [100] expected 8, got 7")], outcome.failures);
+        assert_eq!(vec![String::from("did not stop within 100 steps")], specs[1].run().failures);
+        assert!(specs[2].run().passed(), "{:?}", specs[2].run());
+        let failures = specs[3].run().failures;
+        assert!(failures.len() == 1 && failures[0].starts_with("panicked: "), "{:?}", failures);
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(None, diff(&[1, 2], &[1, 2]));
+        let expected = (0..20).collect::<Vec<i64>>();
+        let text = diff(&expected, &[]).unwrap();
+        assert_eq!(12, text.lines().count());
+        assert!(text.ends_with("... 10 more"));
+    }
+}
@@ -0,0 +1,386 @@
+/// The part of TOML test specs use: integers, booleans, strings, arrays, tables, inline
+/// tables and arrays of tables. Floats and dates are rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Toml {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Toml>),
+    // Keeps the key order, lookups are linear.
+    Table(Vec<(String, Toml)>)
+}
+
+type Table = Vec<(String, Toml)>;
+
+// The table `path` leads to, creating missing tables. Arrays of tables lead to their last one.
+fn table_at<'a>(table: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let (first, rest) = match path.split_first() {
+        Some(p) => p,
+        None => return Ok(table)
+    };
+    let position = match table.iter().position(|(k, _)| k == first) {
+        Some(p) => p,
+        None => {
+            table.push((first.clone(), Toml::Table(Vec::new())));
+            table.len() - 1
+        }
+    };
+    match &mut table[position].1 {
+        Toml::Table(inner) => table_at(inner, rest),
+        Toml::Array(items) => match items.last_mut() {
+            Some(Toml::Table(inner)) => table_at(inner, rest),
+            _ => Err(format!("`{}` is not an array of tables", first))
+        },
+        _ => Err(format!("`{}` is not a table", first))
+    }
+}
+
+fn insert(table: &mut Table, key: String, value: Toml) -> Result<(), String> {
+    if table.iter().any(|(k, _)| *k == key) {
+        return Err(format!("duplicate key `{}`", key));
+    }
+    table.push((key, value));
+    Ok(())
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    at: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let line = self.text[..self.at.min(self.text.len())].iter().filter(|b| **b == b'\n').count() + 1;
+        Err(format!("line {}: {}", line, message))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.at).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(b' ') || self.peek() == Some(b'\t') {
+            self.at += 1;
+        }
+    }
+
+    // Spaces, newlines and comments, as allowed between array items.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') => self.at += 1,
+                Some(b'#') => {
+                    while self.peek().map(|b| b != b'\n').unwrap_or(false) {
+                        self.at += 1;
+                    }
+                },
+                _ => return
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            None | Some(b'\n') | Some(b'\r') | Some(b'#') => Ok(()),
+            _ => self.error("expected the end of the line")
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(b'"') | Some(b'\'') => self.string(),
+            _ => {
+                let start = self.at;
+                while self.peek().map(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-').unwrap_or(false) {
+                    self.at += 1;
+                }
+                if start == self.at {
+                    return self.error("expected a key");
+                }
+                Ok(String::from_utf8_lossy(&self.text[start..self.at]).into_owned())
+            }
+        }
+    }
+
+    fn path(&mut self) -> Result<Vec<String>, String> {
+        let mut path = vec![self.key()?];
+        self.skip_spaces();
+        while self.peek() == Some(b'.') {
+            self.at += 1;
+            path.push(self.key()?);
+            self.skip_spaces();
+        }
+        Ok(path)
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() == Some(byte) {
+            self.at += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", byte as char))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap();
+        self.at += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = match self.peek() {
+                Some(b'\n') | None => return self.error("unterminated string"),
+                Some(b) => b
+            };
+            self.at += 1;
+            if byte == quote {
+                break;
+            }
+            if byte != b'\\' || quote == b'\'' {
+                bytes.push(byte);
+                continue;
+            }
+            let escaped = self.peek();
+            self.at += 1;
+            let c = match escaped {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    let digits = self.text.get(self.at..self.at + 4).and_then(|d| std::str::from_utf8(d).ok());
+                    match digits.and_then(|d| u32::from_str_radix(d, 16).ok()).and_then(std::char::from_u32) {
+                        Some(c) => {
+                            self.at += 4;
+                            c
+                        },
+                        None => return self.error("invalid unicode escape")
+                    }
+                },
+                _ => return self.error("invalid escape")
+            };
+            let mut buffer = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+        }
+        String::from_utf8(bytes).or_else(|_| self.error("invalid utf-8"))
+    }
+
+    fn value(&mut self) -> Result<Toml, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(b'"') | Some(b'\'') => self.string().map(Toml::String),
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(b']') {
+                        self.at += 1;
+                        return Ok(Toml::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank();
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        Some(b']') => {},
+                        _ => return self.error("expected `,` or `]`")
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.at += 1;
+                let mut table = Vec::new();
+                self.skip_spaces();
+                if self.peek() == Some(b'}') {
+                    self.at += 1;
+                    return Ok(Toml::Table(table));
+                }
+                loop {
+                    let path = self.path()?;
+                    self.expect(b'=')?;
+                    let value = self.value()?;
+                    let (key, parents) = path.split_last().unwrap();
+                    let target = table_at(&mut table, parents).or_else(|e| self.error(&e))?;
+                    insert(target, key.clone(), value).or_else(|e| self.error(&e))?;
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(b',') => self.at += 1,
+                        Some(b'}') => {
+                            self.at += 1;
+                            return Ok(Toml::Table(table));
+                        },
+                        _ => return self.error("expected `,` or `}`")
+                    }
+                }
+            },
+            Some(b't') | Some(b'f') => {
+                for (word, value) in &[("true", true), ("false", false)] {
+                    if self.text[self.at..].starts_with(word.as_bytes()) {
+                        self.at += word.len();
+                        return Ok(Toml::Boolean(*value));
+                    }
+                }
+                self.error("expected a value")
+            },
+            Some(b'+') | Some(b'-') | Some(b'0'..=b'9') => {
+                let start = self.at;
+                while self.peek().map(|b| b.is_ascii_alphanumeric() || b"+-_.".contains(&b)).unwrap_or(false) {
+                    self.at += 1;
+                }
+                let text = String::from_utf8_lossy(&self.text[start..self.at]).replace('_', "");
+                let (negative, digits) = match text.strip_prefix('-') {
+                    Some(d) => (true, d),
+                    None => (false, text.trim_start_matches('+'))
+                };
+                let parsed = match digits.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => digits.parse::<i64>()
+                };
+                match parsed {
+                    Ok(n) => Ok(Toml::Integer(if negative { -n } else { n })),
+                    Err(_) if text.contains('.') || text.contains('e') => self.error("floats are not supported"),
+                    Err(_) => self.error(&format!("invalid integer {}", text))
+                }
+            },
+            _ => self.error("expected a value")
+        }
+    }
+
+    fn document(&mut self) -> Result<Toml, String> {
+        let mut root = Vec::new();
+        let mut current = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(Toml::Table(root)),
+                Some(b'[') => {
+                    self.at += 1;
+                    let array = self.peek() == Some(b'[');
+                    if array {
+                        self.at += 1;
+                    }
+                    let path = self.path()?;
+                    self.expect(b']')?;
+                    if array {
+                        self.expect(b']')?;
+                        let (key, parents) = path.split_last().unwrap();
+                        let parent = table_at(&mut root, parents).or_else(|e| self.error(&e))?;
+                        match parent.iter_mut().find(|(k, _)| k == key) {
+                            Some((_, Toml::Array(items))) => items.push(Toml::Table(Vec::new())),
+                            Some(_) => return self.error(&format!("`{}` is not an array of tables", key)),
+                            None => parent.push((key.clone(), Toml::Array(vec![Toml::Table(Vec::new())])))
+                        }
+                    } else {
+                        table_at(&mut root, &path).or_else(|e| self.error(&e))?;
+                    }
+                    current = path;
+                },
+                Some(_) => {
+                    let path = self.path()?;
+                    self.expect(b'=')?;
+                    let value = self.value()?;
+                    let (key, parents) = path.split_last().unwrap();
+                    let table = table_at(&mut root, &current).or_else(|e| self.error(&e))?;
+                    let target = table_at(table, parents).or_else(|e| self.error(&e))?;
+                    insert(target, key.clone(), value).or_else(|e| self.error(&e))?;
+                }
+            }
+            self.end_of_line()?;
+        }
+    }
+}
+
+impl Toml {
+    pub fn parse(text: &str) -> Result<Toml, String> {
+        Parser { text: text.as_bytes(), at: 0 }.document()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Toml> {
+        self.as_table().and_then(|t| t.iter().find(|(k, _)| k == key).map(|(_, v)| v))
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Toml::Integer(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Toml::Boolean(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Toml::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Toml>> {
+        match self {
+            Toml::Array(items) => Some(items),
+            _ => None
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Vec<(String, Toml)>> {
+        match self {
+            Toml::Table(members) => Some(members),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Toml;
+
+    #[test]
+    fn test_parse() {
+        let document = Toml::parse(r#"
+# comment
+title = "specs" # trailing comment
+limits.steps = 1_000
+
+[[test]]
+name = 'raw \n'
+input = [1, -2,
+         0x10, # comment inside an array
+        ]
+patches = { 1 = 12, "2" = +2 }
+
+[test.memory]
+0 = 3500
+
+[[test]]
+name = "esc\"aped\n"
+halts = true
+"#).unwrap();
+        assert_eq!(Some("specs"), document.get("title").and_then(|t| t.as_str()));
+        assert_eq!(Some(1000), document.get("limits").and_then(|l| l.get("steps")).and_then(|s| s.as_i64()));
+        let tests = document.get("test").and_then(|t| t.as_array()).unwrap();
+        assert_eq!(2, tests.len());
+        assert_eq!(Some("raw \\n"), tests[0].get("name").and_then(|n| n.as_str()));
+        assert_eq!(Some(&vec![Toml::Integer(1), Toml::Integer(-2), Toml::Integer(16)]), tests[0].get("input").and_then(|i| i.as_array()));
+        assert_eq!(Some(2), tests[0].get("patches").and_then(|p| p.get("2")).and_then(|v| v.as_i64()));
+        assert_eq!(Some(3500), tests[0].get("memory").and_then(|m| m.get("0")).and_then(|v| v.as_i64()));
+        assert_eq!(None, tests[1].get("memory"));
+        assert_eq!(Some("esc\"aped\n"), tests[1].get("name").and_then(|n| n.as_str()));
+        assert_eq!(Some(true), tests[1].get("halts").and_then(|h| h.as_bool()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Err(String::from("line 2: duplicate key `a`")), Toml::parse("a = 1\na = 2"));
+        assert_eq!(Err(String::from("line 1: floats are not supported")), Toml::parse("a = 1.5"));
+        assert_eq!(Err(String::from("line 1: expected the end of the line")), Toml::parse("a = 1 b = 2"));
+        assert_eq!(Err(String::from("line 1: unterminated string")), Toml::parse("a = \"open\nb = 1"));
+        assert_eq!(Err(String::from("line 2: `a` is not a table")), Toml::parse("a = 1\n[a]"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+use crate::program::{decode, IntCode, OpCode, ParameterMode};
+
+// Runtime shared by every generated program: the machine state, memory helpers that
+// remember which words were overwritten and a plain interpreter used for those words.
+const RUNTIME: &str = r#"#![allow(dead_code, unused_mut, unused_parens, unreachable_patterns, clippy::all)]
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    Idle,
+    WaitForInput,
+    Halt,
+    Error(String)
+}
+
+pub struct Program {
+    code: Vec<i64>,
+    dirty: Vec<bool>,
+    index: usize,
+    relative_base: i64,
+    pub state: State,
+    output: VecDeque<i64>,
+    input: VecDeque<i64>
+}
+
+impl Program {
+    pub fn new() -> Program {
+        Program {
+            code: CODE.to_vec(),
+            dirty: vec![false; CODE.len()],
+            index: 0,
+            relative_base: 0,
+            state: State::Idle,
+            output: VecDeque::new(),
+            input: VecDeque::new()
+        }
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(value);
+        if self.state == State::WaitForInput {
+            self.state = State::Idle;
+        }
+    }
+
+    pub fn pop_output(&mut self) -> Option<i64> {
+        self.output.pop_front()
+    }
+
+    pub fn memory(&self) -> &Vec<i64> {
+        &self.code
+    }
+
+    fn read(&self, address: i64) -> i64 {
+        if address < 0 {
+            return 0;
+        }
+        self.code.get(address as usize).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, address: i64, value: i64) {
+        if address < 0 {
+            self.state = State::Error(String::from("negative address"));
+            return;
+        }
+        let address = address as usize;
+        if address >= self.code.len() {
+            self.code.resize(address + 1, 0);
+        }
+        if address < self.dirty.len() && self.code[address] != value {
+            self.dirty[address] = true;
+        }
+        self.code[address] = value;
+    }
+
+    fn clean(&self, start: usize, len: usize) -> bool {
+        !self.dirty[start..start + len].iter().any(|d| *d)
+    }
+
+    fn interpret(&mut self) {
+        let ip = self.index as i64;
+        let word = self.read(ip);
+        let mut addresses = [0i64; 3];
+        for n in 0..3 {
+            let raw = self.read(ip + n as i64 + 1);
+            addresses[n] = match word / [100, 1000, 10000][n] % 10 {
+                0 => raw,
+                1 => ip + n as i64 + 1,
+                _ => self.relative_base + raw
+            };
+        }
+        let [a, b, c] = addresses;
+        match word % 100 {
+            1 => {
+                let value = self.read(a) + self.read(b);
+                self.write(c, value);
+                self.index += 4;
+            },
+            2 => {
+                let value = self.read(a) * self.read(b);
+                self.write(c, value);
+                self.index += 4;
+            },
+            3 => match self.input.pop_front() {
+                Some(value) => {
+                    self.write(a, value);
+                    self.index += 2;
+                },
+                None => self.state = State::WaitForInput
+            },
+            4 => {
+                let value = self.read(a);
+                self.output.push_back(value);
+                self.index += 2;
+            },
+            5 => self.index = if self.read(a) != 0 { self.read(b) as usize } else { self.index + 3 },
+            6 => self.index = if self.read(a) == 0 { self.read(b) as usize } else { self.index + 3 },
+            7 => {
+                let value = (self.read(a) < self.read(b)) as i64;
+                self.write(c, value);
+                self.index += 4;
+            },
+            8 => {
+                let value = (self.read(a) == self.read(b)) as i64;
+                self.write(c, value);
+                self.index += 4;
+            },
+            9 => {
+                self.relative_base += self.read(a);
+                self.index += 2;
+            },
+            99 => self.state = State::Halt,
+            op => self.state = State::Error(format!("{:02}", op))
+        }
+    }
+"#;
+
+const MAIN: &str = r#"
+fn main() {
+    let mut program = Program::new();
+    loop {
+        while let Some(value) = program.pop_output() {
+            println!("{}", value);
+        }
+        match program.state {
+            State::Idle => program.process(),
+            State::WaitForInput => {
+                let mut line = String::new();
+                if std::io::stdin().read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                match line.trim().parse::<i64>() {
+                    Ok(v) => program.push_input(v),
+                    Err(..) => eprintln!("this was not an integer: {}", line.trim())
+                }
+            },
+            State::Halt => break,
+            State::Error(ref e) => {
+                eprintln!("Error: {:?} !", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+"#;
+
+fn address(mode: ParameterMode, at: usize, raw: i64) -> String {
+    match mode {
+        ParameterMode::Position     => raw.to_string(),
+        ParameterMode::Immediate    => at.to_string(),
+        ParameterMode::Relative     => format!("self.relative_base + {}", raw)
+    }
+}
+
+fn value(mode: ParameterMode, at: usize, raw: i64) -> String {
+    match mode {
+        ParameterMode::Immediate    => format!("({})", raw),
+        _                           => format!("self.read({})", address(mode, at, raw))
+    }
+}
+
+// Body of the match arm for the instruction at `ip`, or None if it can not be lifted.
+fn lift(code: &IntCode, ip: usize) -> Option<String> {
+    let instruction = decode(code[ip]);
+    let count = instruction.op_code.parameter_count();
+    if let OpCode::Err(_) = instruction.op_code {
+        return None;
+    }
+    if ip + count >= code.len() {
+        return None;
+    }
+    let next = ip + count + 1;
+    let a = |n: usize| address(instruction.parameter_mode(n), ip + n + 1, code[ip + n + 1]);
+    let v = |n: usize| value(instruction.parameter_mode(n), ip + n + 1, code[ip + n + 1]);
+    let body = match instruction.op_code {
+        OpCode::Add => format!("let value = {} + {}; self.write({}, value); self.index = {};", v(0), v(1), a(2), next),
+        OpCode::Mul => format!("let value = {} * {}; self.write({}, value); self.index = {};", v(0), v(1), a(2), next),
+        OpCode::LessThan => format!("let value = ({} < {}) as i64; self.write({}, value); self.index = {};", v(0), v(1), a(2), next),
+        OpCode::Equals => format!("let value = ({} == {}) as i64; self.write({}, value); self.index = {};", v(0), v(1), a(2), next),
+        OpCode::In => format!("match self.input.pop_front() {{ Some(value) => {{ self.write({}, value); self.index = {}; }}, None => self.state = State::WaitForInput }}", a(0), next),
+        OpCode::Out => format!("let value = {}; self.output.push_back(value); self.index = {};", v(0), next),
+        OpCode::JumpIfTrue => format!("self.index = if {} != 0 {{ {} as usize }} else {{ {} }};", v(0), v(1), next),
+        OpCode::JumpIfFalse => format!("self.index = if {} == 0 {{ {} as usize }} else {{ {} }};", v(0), v(1), next),
+        OpCode::AdjustRelativeBase => format!("self.relative_base += {}; self.index = {};", v(0), next),
+        OpCode::Halt => String::from("self.state = State::Halt;"),
+        OpCode::Err(_) => return None
+    };
+    Some(body)
+}
+
+/// Translates `code` into a Rust module exposing a `Program` with `new`, `push_input`,
+/// `pop_output`, `process` and `state`. Every address holding a valid instruction gets its own
+/// compiled match arm, words overwritten at runtime are executed by the embedded interpreter.
+pub fn transpile(code: &IntCode) -> String {
+    let mut source = String::from(RUNTIME);
+    source.push_str("\n    pub fn process(&mut self) {\n");
+    source.push_str("        while self.state == State::Idle {\n");
+    source.push_str("            match self.index {\n");
+    for ip in 0..code.len() {
+        if let Some(body) = lift(code, ip) {
+            let len = decode(code[ip]).op_code.parameter_count() + 1;
+            writeln!(source, "                {} if self.clean({}, {}) => {{ {} }},", ip, ip, len, body).unwrap();
+        }
+    }
+    source.push_str("                _ => self.interpret()\n");
+    source.push_str("            }\n        }\n    }\n}\n\n");
+    let words = code.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",");
+    writeln!(source, "const CODE: [i64; {}] = [{}];", code.len(), words).unwrap();
+    source
+}
+
+/// Like `transpile`, with a `main` reading inputs from stdin and printing outputs.
+pub fn transpile_binary(code: &IntCode) -> String {
+    let mut source = transpile(code);
+    source.push_str(MAIN);
+    source
+}
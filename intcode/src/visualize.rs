@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::disassemble::disassemble;
+use crate::program::{Program, State};
+use crate::protocol::Decoder;
+
+const WORDS_PER_ROW: usize = 8;
+const OUTPUT_LOG: usize = 16;
+
+const CLEAR: &str = "\x1b[H\x1b[2J";
+const REVERSE: &str = "\x1b[7m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// How outputs are drawn below the machine state.
+pub enum Grid {
+    Off,
+    // (x, y, id) triples, `id` indexing into the palette.
+    Tiles(String),
+    Ascii
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Radix {
+    Decimal,
+    Hex
+}
+
+/// Full screen view of a program: status line, decoded instruction, memory around the
+/// instruction pointer, I/O queues and the output grid.
+pub struct Screen {
+    pub grid: Grid,
+    pub radix: Radix,
+    pub rows: usize,
+    outputs: Vec<i64>,
+    tiles: HashMap<(i64, i64), i64>,
+    decoder: Decoder<(i64, i64, i64)>,
+    text: String
+}
+
+fn word(value: i64, radix: Radix) -> String {
+    match radix {
+        Radix::Decimal              => value.to_string(),
+        Radix::Hex if value < 0     => format!("-{:x}", -(value as i128)),
+        Radix::Hex                  => format!("{:x}", value)
+    }
+}
+
+impl Screen {
+    pub fn new(grid: Grid) -> Screen {
+        Screen {
+            grid,
+            radix: Radix::Decimal,
+            rows: 8,
+            outputs: Vec::new(),
+            tiles: HashMap::new(),
+            decoder: Decoder::new(3, |t| Ok((t[0], t[1], t[2]))),
+            text: String::new()
+        }
+    }
+
+    pub fn toggle_radix(&mut self) {
+        self.radix = match self.radix {
+            Radix::Decimal  => Radix::Hex,
+            Radix::Hex      => Radix::Decimal
+        };
+    }
+
+    /// Pops the outputs of `program` into the output log and the grid.
+    pub fn collect(&mut self, program: &mut Program) {
+        while let Some(value) = program.pop_output() {
+            self.outputs.push(value);
+            if let Some((x, y, id)) = self.decoder.push(value).unwrap_or(None) {
+                self.tiles.insert((x, y), id);
+            }
+            if (0..128).contains(&value) {
+                self.text.push(value as u8 as char);
+            }
+        }
+        let excess = self.outputs.len().saturating_sub(OUTPUT_LOG);
+        self.outputs.drain(..excess);
+    }
+
+    fn memory(&self, program: &Program) -> Vec<String> {
+        let memory = program.memory();
+        let ip = program.index();
+        let len = disassemble(memory, ip).map(|(_, len)| len).unwrap_or(1);
+        let first = (ip / WORDS_PER_ROW).saturating_sub(self.rows / 2) * WORDS_PER_ROW;
+        let mut lines = Vec::new();
+        for row in (first..memory.len()).step_by(WORDS_PER_ROW).take(self.rows) {
+            let mut line = format!("{:>6}:", row);
+            let end = (row + WORDS_PER_ROW).min(memory.len());
+            for (address, value) in memory[row..end].iter().enumerate().map(|(n, v)| (row + n, *v)) {
+                let cell = format!("{:>9}", word(value, self.radix));
+                if address >= ip && address < ip + len {
+                    write!(line, "{}{}{}", REVERSE, cell, RESET).unwrap();
+                } else {
+                    line.push_str(&cell);
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn grid(&self) -> Vec<String> {
+        match self.grid {
+            Grid::Off => Vec::new(),
+            Grid::Ascii => {
+                let lines = self.text.lines().collect::<Vec<&str>>();
+                let skip = lines.len().saturating_sub(self.rows * 3);
+                lines[skip..].iter().map(|l| l.to_string()).collect()
+            },
+            Grid::Tiles(ref palette) => {
+                if self.tiles.is_empty() {
+                    return Vec::new();
+                }
+                let palette = palette.chars().collect::<Vec<char>>();
+                let min_x = self.tiles.keys().map(|p| p.0).min().unwrap();
+                let max_x = self.tiles.keys().map(|p| p.0).max().unwrap();
+                let min_y = self.tiles.keys().map(|p| p.1).min().unwrap();
+                let max_y = self.tiles.keys().map(|p| p.1).max().unwrap();
+                (min_y..=max_y).map(|y| (min_x..=max_x).map(|x| match self.tiles.get(&(x, y)) {
+                    Some(id) => *palette.get(*id as usize).unwrap_or(&'?'),
+                    None     => ' '
+                }).collect()).collect()
+            }
+        }
+    }
+
+    /// One frame, starting with the escape sequence clearing the terminal.
+    pub fn render(&self, program: &Program, status: &str) -> String {
+        let mut frame = String::from(CLEAR);
+        let state = match program.state {
+            State::Idle             => String::from("running"),
+            State::WaitForInput     => String::from("waiting for input"),
+            State::Halt             => String::from("halted"),
+            State::Error(ref e)     => format!("error: {}", e)
+        };
+        writeln!(frame, "{}Intcode{}  {}  [{}]", BOLD, RESET, status, state).unwrap();
+        writeln!(frame, "ip {}  rb {}  instructions {}", program.index(), program.relative_base(), program.instruction_count()).unwrap();
+        let next = disassemble(program.memory(), program.index()).map(|(t, _)| t).unwrap_or_else(|| String::from("??"));
+        writeln!(frame, "next: {}{}{}", BOLD, next, RESET).unwrap();
+        for line in program.backtrace().unwrap_or_default() {
+            writeln!(frame, "  {}", line).unwrap();
+        }
+        writeln!(frame).unwrap();
+        let radix = match self.radix {
+            Radix::Decimal  => "dec",
+            Radix::Hex      => "hex"
+        };
+        writeln!(frame, "memory ({})", radix).unwrap();
+        for line in self.memory(program) {
+            writeln!(frame, "{}", line).unwrap();
+        }
+        let input = program.input_queue().iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ");
+        let output = self.outputs.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(", ");
+        writeln!(frame, "\ninput:  [{}]", input).unwrap();
+        writeln!(frame, "output: [{}]\n", output).unwrap();
+        for line in self.grid() {
+            writeln!(frame, "{}", line).unwrap();
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Grid, Screen};
+    use crate::program::Program;
+
+    #[test]
+    fn test_render() {
+        // Draws a wall, a block and a ball, then waits for input.
+        let mut program = Program::new(vec![104,0,104,0,104,1,104,1,104,0,104,2,104,2,104,1,104,4,3,40,99], false);
+        let mut screen = Screen::new(Grid::Tiles(String::from(" #x-o")));
+        program.process();
+        screen.collect(&mut program);
+        program.push_input(-5);
+        let frame = screen.render(&program, "paused");
+        assert!(frame.contains("[running]"));
+        assert!(frame.contains("ip 18  rb 0  instructions 9"));
+        assert!(frame.contains("next: \x1b[1mIn [40]"));
+        assert!(frame.contains("input:  [-5]"));
+        assert!(frame.contains("output: [0, 0, 1, 1, 0, 2, 2, 1, 4]"));
+        assert!(frame.contains("\x1b[7m        3\x1b[0m\x1b[7m       40\x1b[0m"));
+        assert!(frame.ends_with("#x \n  o\n"));
+        screen.toggle_radix();
+        assert!(screen.render(&program, "paused").contains("       28"));
+    }
+}
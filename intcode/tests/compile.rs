@@ -0,0 +1,134 @@
+// Compiles small programs and checks what they output when run on `Program`.
+use intcode::compile::compile;
+use intcode::{Program, State};
+
+fn run(source: &str, input: &[i64]) -> Vec<i64> {
+    let code = compile(source).unwrap_or_else(|e| panic!("{}", e));
+    let mut program = Program::new(code, false);
+    for value in input {
+        program.push_input(*value);
+    }
+    program.process();
+    assert_eq!(State::Halt, program.state);
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(value);
+    }
+    output
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(vec![14, -4, 20, -7, 7], run("
+        let a = 2 + 3 * 4;
+        output(a);
+        output(1 - 5);
+        output((2 + 3) * 4);
+        output(-7);
+        output(--7);
+    ", &[]));
+}
+
+#[test]
+fn comparisons() {
+    let source = "
+        let a = input();
+        let b = input();
+        output(a < b); output(a <= b); output(a > b); output(a >= b); output(a == b); output(a != b);
+        output(!a); output(a && b); output(a || b);
+    ";
+    assert_eq!(vec![1, 1, 0, 0, 0, 1, 0, 1, 1], run(source, &[2, 5]));
+    assert_eq!(vec![0, 1, 0, 1, 1, 0, 0, 1, 1], run(source, &[5, 5]));
+    assert_eq!(vec![0, 0, 1, 1, 0, 1, 1, 0, 1], run(source, &[0, -3]));
+    assert_eq!(vec![0, 1, 0, 1, 1, 0, 1, 0, 0], run(source, &[0, 0]));
+}
+
+#[test]
+fn short_circuit() {
+    // The right hand side would read an input that is never given.
+    assert_eq!(vec![0, 1], run("output(0 && input()); output(1 || input());", &[]));
+}
+
+#[test]
+fn control_flow() {
+    let source = "
+        // Sums the inputs up to the first zero, skipping negative ones.
+        let sum = 0;
+        while 1 {
+            let n = input();
+            if n == 0 {
+                break;
+            } else if n < 0 {
+                continue;
+            }
+            sum = sum + n;
+        }
+        output(sum);
+    ";
+    assert_eq!(vec![10], run(source, &[1, -5, 2, 3, -1, 4, 0]));
+}
+
+#[test]
+fn functions() {
+    let source = "
+        fn fib(n) {
+            if n < 2 { return n; }
+            return fib(n - 1) + fib(n - 2);
+        }
+        fn max(a, b) {
+            if a > b { return a; }
+            return b;
+        }
+        fn nothing() {}
+        let i = 0;
+        while i < 10 {
+            output(fib(i));
+            i = i + 1;
+        }
+        output(max(fib(7), max(3, 20)));
+        output(nothing());
+    ";
+    assert_eq!(vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 20, 0], run(source, &[]));
+}
+
+#[test]
+fn echo_until_zero() {
+    let source = "
+        fn twice(x) { return x + x; }
+        let x = input();
+        while x != 0 {
+            output(twice(x));
+            x = input();
+        }
+    ";
+    let code = compile(source).unwrap();
+    let mut program = Program::new(code, false);
+    program.process();
+    assert_eq!(State::WaitForInput, program.state);
+    program.push_input(21);
+    program.process();
+    assert_eq!(Some(42), program.pop_output());
+    program.push_input(0);
+    program.process();
+    assert_eq!(State::Halt, program.state);
+}
+
+#[test]
+fn call_stack() {
+    let source = "
+        fn down(n) {
+            if n == 0 { return input(); }
+            return down(n - 1);
+        }
+        output(down(3));
+    ";
+    let mut program = Program::new(compile(source).unwrap(), false);
+    program.enable_call_stack();
+    program.process();
+    assert_eq!(State::WaitForInput, program.state);
+    assert_eq!(4, program.call_stack().unwrap().frames.len());
+    program.push_input(5);
+    program.process();
+    assert_eq!(Some(5), program.pop_output());
+    assert_eq!(0, program.call_stack().unwrap().frames.len());
+}
@@ -0,0 +1,61 @@
+// Every directory below tests/conformance is one case: `program.txt` holds the intcode,
+// the optional `input.txt` the values pushed before running, and the optional `output.txt`
+// and `memory.txt` what the program has to produce. Memory is compared on the listed
+// prefix, anything the program allocated beyond it has to stay zero.
+use std::fs;
+use std::path::Path;
+
+use intcode::{IntCode, Program, State};
+
+fn read_values(path: &Path) -> Option<IntCode> {
+    let content = fs::read_to_string(path).ok()?;
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return Some(Vec::new());
+    }
+    Some(trimmed.split(',').map(|s| s.trim().parse::<i64>().unwrap()).collect())
+}
+
+fn run_case(dir: &Path) -> Result<(), String> {
+    let code = read_values(&dir.join("program.txt")).ok_or("missing program.txt")?;
+    let mut program = Program::new(code, false);
+    for value in read_values(&dir.join("input.txt")).unwrap_or_default() {
+        program.push_input(value);
+    }
+    program.process();
+    if program.state != State::Halt {
+        return Err(format!("stopped in state {:?}", program.state));
+    }
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(value);
+    }
+    if let Some(expected) = read_values(&dir.join("output.txt")) {
+        if expected != output {
+            return Err(format!("output {:?}, expected {:?}", output, expected));
+        }
+    }
+    if let Some(expected) = read_values(&dir.join("memory.txt")) {
+        let memory = program.memory();
+        let prefix = &memory[..expected.len().min(memory.len())];
+        if prefix != &expected[..] || memory[prefix.len()..].iter().any(|v| *v != 0) {
+            return Err(format!("memory {:?}, expected {:?}", memory, expected));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn conformance() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+    let mut dirs = fs::read_dir(&root).unwrap()
+                      .map(|e| e.unwrap().path())
+                      .filter(|p| p.is_dir())
+                      .collect::<Vec<_>>();
+    dirs.sort();
+    assert!(!dirs.is_empty());
+    let failures = dirs.iter()
+                       .filter_map(|d| run_case(d).err().map(|e| format!("{}: {}", d.file_name().unwrap().to_string_lossy(), e)))
+                       .collect::<Vec<String>>();
+    assert!(failures.is_empty(), "{} of {} cases failed:\n{}", failures.len(), dirs.len(), failures.join("\n"));
+}
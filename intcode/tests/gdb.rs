@@ -0,0 +1,106 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use intcode::gdb::{checksum, frame, hex, unhex, Stub};
+use intcode::Program;
+
+// Scripted debugger side of the remote protocol.
+struct Client {
+    stream: TcpStream
+}
+
+impl Client {
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn receive(&mut self) -> String {
+        while self.byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b => data.push(b)
+            }
+        }
+        let sum = [self.byte(), self.byte()];
+        let data = String::from_utf8(data).unwrap();
+        assert_eq!(format!("{:02x}", checksum(&data)), String::from_utf8(sum.to_vec()).unwrap());
+        self.stream.write_all(b"+").unwrap();
+        data
+    }
+
+    fn send(&mut self, packet: &str) -> String {
+        self.stream.write_all(frame(packet).as_bytes()).unwrap();
+        assert_eq!(b'+', self.byte());
+        self.receive()
+    }
+}
+
+// [20] = 5, reads [21], outputs [20] + [21] and halts.
+fn sum() -> Vec<i64> {
+    vec![1101,2,3,20,3,21,1,20,21,22,4,22,99]
+}
+
+#[test]
+fn test_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Stub::new(Program::new(sum(), false)).serve(stream.try_clone().unwrap(), stream).unwrap();
+    });
+    let mut client = Client { stream: TcpStream::connect(address).unwrap() };
+
+    assert!(client.send("qSupported:multiprocess+;swbreak+").contains("qXfer:features:read+"));
+    let xml = client.send("qXfer:features:read:target.xml:0,fff");
+    assert!(xml.starts_with('l') && xml.contains("name=\"ip\"") && xml.contains("name=\"rb\""));
+    assert_eq!("S05", client.send("?"));
+    assert_eq!("0".repeat(32), client.send("g"));
+
+    // Break on the input instruction at word 4, i.e. byte 0x20.
+    assert_eq!("OK", client.send("Z0,20,1"));
+    assert_eq!("S05", client.send("c"));
+    assert_eq!("2000000000000000", client.send("p0"));
+    assert_eq!("0500000000000000", client.send("ma0,8"));
+    assert_eq!("OK", client.send("z0,20,1"));
+
+    // Stepping without input keeps the program where it is.
+    let waiting = client.send("s");
+    assert!(String::from_utf8(unhex(&waiting[1..]).unwrap()).unwrap().contains("waiting for input"));
+    assert_eq!("S05", client.receive());
+    assert_eq!("OK", client.send(&format!("qRcmd,{}", hex(b"input 7"))));
+    assert_eq!("S05", client.send("s"));
+    assert_eq!("3000000000000000", client.send("p0"));
+
+    let output = client.send("c");
+    assert_eq!("Out: 12\n", String::from_utf8(unhex(&output[1..]).unwrap()).unwrap());
+    assert_eq!("W00", client.receive());
+    assert_eq!("OK", client.send("D"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_packet_while_running() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        Stub::new(Program::new(vec![1105,1,0], false)).serve(stream.try_clone().unwrap(), stream).unwrap();
+    });
+    let mut client = Client { stream: TcpStream::connect(address).unwrap() };
+
+    // A packet sent while the program runs is answered once the interrupt stops it.
+    client.stream.write_all(frame("c").as_bytes()).unwrap();
+    assert_eq!(b'+', client.byte());
+    client.stream.write_all(frame("p0").as_bytes()).unwrap();
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!("S05", client.receive());
+    assert_eq!(b'+', client.byte());
+    assert_eq!("0000000000000000", client.receive());
+    assert_eq!("OK", client.send("D"));
+    server.join().unwrap();
+}
@@ -0,0 +1,22 @@
+// Runs the spec files in tests/specs, each of them has to pass.
+use std::path::Path;
+
+use intcode::spec;
+
+#[test]
+fn specs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("specs");
+    let mut files = std::fs::read_dir(&root).unwrap()
+                       .map(|e| e.unwrap().path())
+                       .filter(|p| p.extension().map(|e| e == "toml").unwrap_or(false))
+                       .collect::<Vec<_>>();
+    files.sort();
+    assert!(!files.is_empty());
+    let failures = files.iter()
+                        .flat_map(|f| spec::load(f).unwrap_or_else(|e| panic!("{}", e)))
+                        .map(|s| s.run())
+                        .filter(|o| !o.passed())
+                        .map(|o| format!("{}:\n{}", o.name, o.failures.join("\n")))
+                        .collect::<Vec<String>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
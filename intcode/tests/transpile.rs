@@ -0,0 +1,65 @@
+// Transpiles every program of the conformance corpus into one crate, compiles it with rustc
+// and checks the native programs produce the same outputs and final state as `Program`.
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use intcode::{transpile, IntCode, Program};
+
+fn read_values(path: &Path) -> IntCode {
+    match fs::read_to_string(path) {
+        Ok(c) if !c.trim().is_empty() => c.trim().split(',').map(|s| s.trim().parse::<i64>().unwrap()).collect(),
+        _ => Vec::new()
+    }
+}
+
+fn interpret(code: &IntCode, input: &IntCode) -> String {
+    let mut program = Program::new(code.clone(), false);
+    for value in input {
+        program.push_input(*value);
+    }
+    program.process();
+    let mut output = Vec::new();
+    while let Some(value) = program.pop_output() {
+        output.push(value.to_string());
+    }
+    format!("{:?} {}", program.state, output.join(","))
+}
+
+#[test]
+fn transpiled_conformance() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+    let mut dirs = fs::read_dir(&root).unwrap().map(|e| e.unwrap().path()).filter(|p| p.is_dir()).collect::<Vec<_>>();
+    dirs.sort();
+    let mut source = String::new();
+    let mut main = String::from("fn main() {\n");
+    let mut expected = String::new();
+    for (i, dir) in dirs.iter().enumerate() {
+        let code = read_values(&dir.join("program.txt"));
+        let input = read_values(&dir.join("input.txt"));
+        source.push_str(&format!("mod case{} {{\n{}}}\n", i, transpile::transpile(&code)));
+        main.push_str(&format!(
+            "    {{\n        let mut p = case{}::Program::new();\n        for v in &{:?} {{ p.push_input(*v); }}\n        p.process();\n        let mut out = Vec::new();\n        while let Some(v) = p.pop_output() {{ out.push(v.to_string()); }}\n        println!(\"{{:?}} {{}}\", p.state, out.join(\",\"));\n    }}\n",
+            i, input));
+        expected.push_str(&interpret(&code, &input));
+        expected.push('\n');
+    }
+    main.push_str("}\n");
+    source.push_str(&main);
+
+    let dir = std::env::temp_dir().join(format!("intcode-transpile-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("conformance.rs");
+    fs::write(&file, source).unwrap();
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let status = Command::new(rustc)
+        .arg("--edition=2018")
+        .arg("-o").arg(dir.join("conformance"))
+        .arg(&file)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = Command::new(dir.join("conformance")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(expected, String::from_utf8(output.stdout).unwrap());
+}